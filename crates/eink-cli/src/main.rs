@@ -13,7 +13,7 @@
 use std::ops::Sub;
 
 use anyhow::bail;
-use serde_json::json;
+use eink_service_proto::{keyboard, tcon, Empty, SetMipiModeParams};
 use structopt::StructOpt;
 use windows::{
     core::PCSTR,
@@ -47,6 +47,18 @@ enum Subcommand {
     DisableWinKey,
     #[structopt(about = "Enable alt-tab / win key")]
     EnableWinKey,
+    #[structopt(about = "Show service build info and methods")]
+    Discover {
+        /// Pipe name, e.g. \\.\pipe\lenovo\eink-service\tcon
+        #[structopt(long)]
+        pipe: String,
+    },
+    #[structopt(about = "Show per-method call counts and latency")]
+    Stats {
+        /// Pipe name, e.g. \\.\pipe\lenovo\eink-service\tcon
+        #[structopt(long)]
+        pipe: String,
+    },
     #[structopt(about = "Test")]
     Test,
 }
@@ -84,8 +96,8 @@ fn main() {
             println!("EinkSetMipiMode mode: {mode}");
             let mut client = eink_pipe_io::blocking::connect(TCON_PIPE_NAME)
                 .expect("Cannot connect to tcon service");
-            let reply = client
-                .call_with_params("set_mipi_mode", json!({ "mode": mode }))
+            let reply = tcon::BlockingClient::new(&mut client)
+                .set_mipi_mode(SetMipiModeParams { mode })
                 .expect("Cannot invoke remote method to tcon service");
             println!("reply: {reply:?}");
         }
@@ -93,8 +105,8 @@ fn main() {
             println!("EinkRefresh");
             let mut client = eink_pipe_io::blocking::connect(TCON_PIPE_NAME)
                 .expect("Cannot connect to tcon service");
-            let reply = client
-                .call_with_params("refresh", json!({}))
+            let reply = tcon::BlockingClient::new(&mut client)
+                .refresh(Empty {})
                 .expect("Cannot invoke remote method to tcon service");
             println!("reply: {reply:?}");
         }
@@ -103,8 +115,8 @@ fn main() {
             println!("DisableWinKey");
             let mut client = eink_pipe_io::blocking::connect(KEYBOARD_PIPE_NAME)
                 .expect("Cannot connect to keyboard service");
-            let reply = client
-                .call_with_params("disable_win_key", json!({}))
+            let reply = keyboard::BlockingClient::new(&mut client)
+                .disable_win_key(Empty {})
                 .expect("Cannot invoke remote method to tcon service");
             println!("reply: {reply:?}");
         }
@@ -113,12 +125,45 @@ fn main() {
             println!("EnableWinKey");
             let mut client = eink_pipe_io::blocking::connect(KEYBOARD_PIPE_NAME)
                 .expect("Cannot connect to keyboard service");
-            let reply = client
-                .call_with_params("enable_win_key", json!({}))
+            let reply = keyboard::BlockingClient::new(&mut client)
+                .enable_win_key(Empty {})
                 .expect("Cannot invoke remote method to tcon service");
             println!("reply: {reply:?}");
         }
 
+        Subcommand::Discover { pipe } => {
+            let mut client =
+                eink_pipe_io::blocking::connect(&pipe).expect("Cannot connect to service");
+            println!("protocol: {}", client.protocol_version());
+            let info = client
+                .discover()
+                .expect("Cannot invoke remote method to service");
+            println!("{info:#?}");
+        }
+
+        Subcommand::Stats { pipe } => {
+            let mut client =
+                eink_pipe_io::blocking::connect(&pipe).expect("Cannot connect to service");
+            let stats = client
+                .stats()
+                .expect("Cannot invoke remote method to service");
+            println!(
+                "{}: uptime {}s, connections {}/{}",
+                stats.endpoint, stats.uptime_secs, stats.connections, stats.total_connections
+            );
+            for (method, method_stats) in &stats.methods {
+                println!(
+                    "{method}: calls {}, errors {}, cancelled {}, mean {:.1}ms, max {:.1}ms, p99 {:?}ms",
+                    method_stats.calls,
+                    method_stats.errors,
+                    method_stats.cancelled,
+                    method_stats.mean_ms(),
+                    method_stats.max_ms,
+                    method_stats.percentile_ms(0.99),
+                );
+            }
+        }
+
         Subcommand::Test => unsafe {
             // #[windows_dll::dll(User32)]
             // extern "system" {
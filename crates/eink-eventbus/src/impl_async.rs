@@ -0,0 +1,354 @@
+//
+// Copyright (C) Lenovo ThinkBook Gen4 Project.
+//
+// This program is protected under international and China copyright laws as
+// an unpublished work. This program is confidential and proprietary to the
+// copyright owners. Reproduction or disclosure, in whole or in part, or the
+// production of derivative works therefrom without the express permission of
+// the copyright owners is prohibited.
+//
+// All rights reserved.
+//
+
+use std::collections::HashMap;
+use std::sync::{Arc, Weak};
+
+use async_trait::async_trait;
+use parking_lot::Mutex;
+use tokio::runtime::Handle;
+use tokio::sync::mpsc;
+
+use crate::topic_tree::TopicTree;
+use crate::{Event, EventListener, Eventbus, Topic, TopicHandlers, TopicKey};
+
+/// Asynchronous event listener
+///
+/// Async listeners only receive events posted with the async API (`post_async`),
+/// they run on the tokio runtime and never block the posting task. Events posted
+/// with `post` or `post_owned` are not delivered to them, post a message with both
+/// APIs if a topic has listeners of both kinds.
+#[async_trait]
+pub trait AsyncListener<T>: Send + Sync + 'static {
+    /// handler callback to process event
+    async fn handle(&self, event: &Event<T>);
+}
+
+/// short hand of async event listeners set
+pub type AsyncEventListeners<T> = Arc<Mutex<HashMap<u64, Arc<dyn AsyncListener<T>>>>>;
+
+/// enqueue an event to the dispatcher task, returns the event back if the task stopped
+type Dispatcher<T> = Box<dyn Fn(Arc<Event<T>>) -> Result<(), Arc<Event<T>>> + Send + Sync>;
+
+/// async listeners registered with topic patterns
+type AsyncWildcardListeners<T> = Arc<Mutex<TopicTree<Arc<dyn AsyncListener<T>>>>>;
+
+/// Async listeners and the optional dispatcher of a topic
+pub(crate) struct AsyncTopic<T> {
+    key: TopicKey,
+    listeners: AsyncEventListeners<T>,
+    /// shared by all topics with the same message type
+    wildcards: AsyncWildcardListeners<T>,
+    /// queue of the dedicated dispatcher task, events are dispatched in FIFO order
+    dispatcher: Mutex<Option<Dispatcher<T>>>,
+}
+
+/// short hand of topic to async topic map
+type AsyncTopicMap<T> = Arc<Mutex<HashMap<TopicKey, Arc<AsyncTopic<T>>>>>;
+
+impl Eventbus {
+    /// register an async listener to eventbus, the topic key can be a pattern
+    ///
+    /// Events retained by matching sticky topics are handled by a new task, they are
+    /// dropped with a warning outside a tokio runtime.
+    pub fn register_async<T, K, L>(&self, topic_key: K, listener: L) -> EventListener<T>
+    where
+        T: Send + Sync + 'static,
+        K: Into<TopicKey>,
+        L: AsyncListener<T>,
+    {
+        let topic_key = topic_key.into();
+        let event_listener = EventListener::<T>::new(topic_key.clone(), self.clone());
+        trace!("add async event_listener: {:?}", event_listener);
+        let listener: Arc<dyn AsyncListener<T>> = Arc::new(listener);
+        let handlers = &self.inner.topic_handlers;
+        if topic_key.is_pattern() {
+            handlers.get_async_wildcards::<T>().lock().insert(
+                &topic_key,
+                event_listener.rand_id,
+                listener.clone(),
+            );
+        } else {
+            handlers
+                .get_async_topic::<T>(topic_key.clone())
+                .listeners
+                .lock()
+                .insert(event_listener.rand_id, listener.clone());
+        }
+
+        let retained = handlers.retained::<T>(&topic_key);
+        if !retained.is_empty() {
+            match Handle::try_current() {
+                Ok(handle) => {
+                    handle.spawn(async move {
+                        for event in retained {
+                            listener.handle(&event).await;
+                        }
+                    });
+                }
+                Err(_) => warn!(
+                    "retained events of {:?} dropped, no tokio runtime",
+                    topic_key
+                ),
+            }
+        }
+        event_listener
+    }
+
+    /// post an event to async listeners, returns once the event is enqueued
+    ///
+    /// Synchronous listeners are not called, they only receive `post` and `post_owned`.
+    ///
+    /// Without a dispatcher each event is handled by a new task, listeners of the
+    /// same event are called one by one. Must be called within a tokio runtime.
+    pub async fn post_async<T: Send + Sync + 'static>(&self, event: Event<T>) {
+        let topic = self
+            .inner
+            .topic_handlers
+            .get_async_topic::<T>(event.topic.clone());
+        let event = Arc::new(event);
+        self.inner.topic_handlers.retain(&event);
+
+        let event = match &*topic.dispatcher.lock() {
+            Some(dispatcher) => match dispatcher(event) {
+                Ok(_) => return,
+                Err(event) => event,
+            },
+            None => event,
+        };
+        let listeners = topic.snapshot();
+        tokio::spawn(async move { dispatch(&listeners, &event).await });
+    }
+
+    /// spawn a dedicated dispatcher task for a topic
+    ///
+    /// Events posted to the topic are queued and dispatched in FIFO order, a slow
+    /// listener delays later events of the same topic but never the poster.
+    /// Must be called within a tokio runtime.
+    pub fn spawn_dispatcher<T: Send + Sync + 'static, K: Into<TopicKey>>(&self, topic_key: K) {
+        let topic_key = topic_key.into();
+        let topic = self
+            .inner
+            .topic_handlers
+            .get_async_topic::<T>(topic_key.clone());
+        let mut dispatcher = topic.dispatcher.lock();
+        if dispatcher.is_some() {
+            return;
+        }
+
+        let (tx, mut rx) = mpsc::unbounded_channel::<Arc<Event<T>>>();
+        *dispatcher = Some(Box::new(move |event| tx.send(event).map_err(|err| err.0)));
+
+        // the task holds a weak reference, it stops when the eventbus is dropped
+        let weak: Weak<AsyncTopic<T>> = Arc::downgrade(&topic);
+        tokio::spawn(async move {
+            while let Some(event) = rx.recv().await {
+                let listeners = match weak.upgrade() {
+                    Some(topic) => topic.snapshot(),
+                    None => break,
+                };
+                // a panicking listener must not stop the dispatcher
+                let task = tokio::spawn(async move { dispatch(&listeners, &event).await });
+                if let Err(err) = task.await {
+                    warn!("async listener of topic {:?} failed: {}", topic_key, err);
+                }
+            }
+            trace!("dispatcher of topic {:?} stopped", topic_key);
+        });
+    }
+
+    /// stop the dedicated dispatcher task of a topic, queued events are still dispatched
+    pub fn stop_dispatcher<T: Send + Sync + 'static, K: Into<TopicKey>>(&self, topic_key: K) {
+        let topic = self.inner.topic_handlers.get_async_topic::<T>(topic_key);
+        topic.dispatcher.lock().take();
+    }
+}
+
+impl TopicHandlers {
+    pub(crate) fn get_async_topic<T: 'static>(
+        &self,
+        topic_key: impl Into<TopicKey>,
+    ) -> Arc<AsyncTopic<T>> {
+        let wildcards = self.get_async_wildcards::<T>();
+        let mut guard = self.inner.lock();
+        if !guard.contains::<AsyncTopicMap<T>>() {
+            guard.insert::<AsyncTopicMap<T>>(Default::default());
+        }
+        let inner = guard.get::<AsyncTopicMap<T>>().unwrap();
+        let mut inner_guard = inner.lock();
+        let topic_key = topic_key.into();
+        inner_guard
+            .entry(topic_key.clone())
+            .or_insert_with(|| {
+                Arc::new(AsyncTopic {
+                    key: topic_key,
+                    listeners: Default::default(),
+                    wildcards,
+                    dispatcher: Mutex::new(None),
+                })
+            })
+            .clone()
+    }
+
+    fn get_async_wildcards<T: 'static>(&self) -> AsyncWildcardListeners<T> {
+        let mut guard = self.inner.lock();
+        if !guard.contains::<AsyncWildcardListeners<T>>() {
+            guard.insert::<AsyncWildcardListeners<T>>(Default::default());
+        }
+        guard.get::<AsyncWildcardListeners<T>>().unwrap().clone()
+    }
+
+    pub(crate) fn remove_async_listener<T: 'static>(&self, rand_id: u64, topic_key: &TopicKey) {
+        if topic_key.is_pattern() {
+            self.get_async_wildcards::<T>()
+                .lock()
+                .remove(topic_key, rand_id);
+            return;
+        }
+        let guard = self.inner.lock();
+        if let Some(inner) = guard.get::<AsyncTopicMap<T>>() {
+            if let Some(topic) = inner.lock().get(topic_key) {
+                topic.listeners.lock().remove(&rand_id);
+            }
+        }
+    }
+}
+
+impl<T> AsyncTopic<T> {
+    /// listeners at the time of dispatching, the lock is not held while handling
+    fn snapshot(&self) -> Vec<Arc<dyn AsyncListener<T>>> {
+        let mut snapshot: Vec<_> = self.listeners.lock().values().cloned().collect();
+        let wildcards = self.wildcards.lock();
+        if !wildcards.is_empty() {
+            snapshot.extend(wildcards.matches(&self.key));
+        }
+        snapshot
+    }
+}
+
+async fn dispatch<T: 'static>(listeners: &[Arc<dyn AsyncListener<T>>], event: &Event<T>) {
+    for listener in listeners {
+        trace!("notify async listener for event [{:?}]", event.topic);
+        listener.handle(event).await;
+    }
+}
+
+impl<T: Send + Sync + 'static> Topic<T> {
+    /// shorthand for post event to async listeners
+    pub async fn post_async(&self, event: Event<T>) {
+        self.bus.post_async(event).await;
+    }
+
+    /// shorthand for post message to async listeners
+    pub async fn post_message_async(&self, message: T) {
+        let event = self.create_event(message);
+        self.post_async(event).await;
+    }
+
+    /// shorthand for spawn a dedicated dispatcher task for this topic
+    pub fn spawn_dispatcher(&self) {
+        self.bus.spawn_dispatcher::<T, _>(self.key.clone());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+    use parking_lot::Mutex;
+    use tokio::sync::mpsc;
+
+    use crate::{AsyncListener, Event, Eventbus, Listener};
+
+    struct Slow {
+        delay: Duration,
+        tx: mpsc::UnboundedSender<u32>,
+    }
+
+    #[async_trait]
+    impl AsyncListener<u32> for Slow {
+        async fn handle(&self, event: &Event<u32>) {
+            tokio::time::sleep(self.delay).await;
+            let _ = self.tx.send(**event);
+        }
+    }
+
+    struct Record(Arc<Mutex<Vec<u32>>>);
+
+    #[async_trait]
+    impl AsyncListener<u32> for Record {
+        async fn handle(&self, event: &Event<u32>) {
+            self.0.lock().push(**event);
+        }
+    }
+
+    struct SyncRecord(Arc<Mutex<Vec<u32>>>);
+
+    impl Listener<u32> for SyncRecord {
+        fn handle(&self, event: &Event<u32>) {
+            self.0.lock().push(**event);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sync_and_async_dispatch_are_separate() {
+        let eventbus = Eventbus::new();
+        let topic = eventbus.create_topic::<u32, _>("lid");
+        let sync = Arc::new(Mutex::new(Vec::new()));
+        eventbus.register("lid", SyncRecord(sync.clone()));
+        let record = Arc::new(Mutex::new(Vec::new()));
+        eventbus.register_async("lid", Record(record.clone()));
+
+        topic.post_message(0);
+        topic.post(&Event::new("lid", 1));
+        topic.post_message_async(2).await;
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        // a sync post never reaches async listeners and vice versa
+        assert_eq!(*sync.lock(), vec![0, 1]);
+        assert_eq!(*record.lock(), vec![2]);
+    }
+
+    #[tokio::test]
+    async fn test_post_async_does_not_wait_listener() {
+        let eventbus = Eventbus::new();
+        let topic = eventbus.create_topic::<u32, _>("wmi");
+        topic.spawn_dispatcher();
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let delay = Duration::from_millis(50);
+        let listener = eventbus.register_async("wmi", Slow { delay, tx });
+        let record = Arc::new(Mutex::new(Vec::new()));
+        eventbus.register_async("wmi", Record(record.clone()));
+
+        let started_at = std::time::Instant::now();
+        for message in 0..3 {
+            topic.post_message_async(message).await;
+        }
+        assert!(started_at.elapsed() < delay);
+
+        // the dispatcher keeps FIFO order
+        for expected in 0..3 {
+            assert_eq!(rx.recv().await, Some(expected));
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(*record.lock(), vec![0, 1, 2]);
+
+        listener.unregister();
+        topic.post_message_async(3).await;
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(*record.lock(), vec![0, 1, 2, 3]);
+        assert!(rx.try_recv().is_err());
+    }
+}
@@ -10,11 +10,32 @@
 // All rights reserved.
 //
 
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::sync::Arc;
+
 use crate::{
     Event, EventListener, EventListeners, Eventbus, Topic, TopicHandlers, TopicHandlersMap,
-    TopicKey,
+    TopicKey, WildcardListeners,
 };
 
+/// delivery of a posted event to the listeners of its topic
+type Delivery = Box<dyn FnOnce()>;
+
+thread_local! {
+    /// events posted from inside a handler on this thread, `None` when no event is delivering
+    static PENDING: RefCell<Option<VecDeque<Delivery>>> = RefCell::new(None);
+}
+
+/// reset the pending queue when the outermost delivery finished or a listener panicked
+struct Draining;
+
+impl Drop for Draining {
+    fn drop(&mut self) {
+        PENDING.with(|pending| *pending.borrow_mut() = None);
+    }
+}
+
 /// Event listener
 ///
 /// Note: the struct which implements `Listener` need to be `Send` and `Sync`
@@ -38,33 +59,80 @@ impl Eventbus {
     }
 
     /// register a listener to eventbus
+    ///
+    /// The topic key can be a pattern such as `display/*` or `wmi/#`, the listener
+    /// then receives the events of all matching topics with the same message type.
+    /// Events retained by matching sticky topics are delivered to the new listener.
     #[cfg_attr(docsrs, doc(cfg(feature = "sync")))]
     pub fn register<T: 'static, K: Into<TopicKey>, L: Listener<T>>(
         &self,
         topic_key: K,
         listener: L,
     ) -> EventListener<T> {
-        let topic_key = topic_key.into();
-        let event_listener = EventListener::<T>::new(topic_key.clone(), self.clone());
-        trace!("add event_listener: {:?}", event_listener);
-        self.inner
-            .topic_handlers
-            .add_listener(event_listener.rand_id, topic_key, listener);
+        let event_listener = EventListener::<T>::new(topic_key, self.clone());
+        self.add_event_listener(&event_listener, Arc::new(listener));
         event_listener
     }
 
+    /// install a listener for an `EventListener` and replay the retained events
+    pub(crate) fn add_event_listener<T: 'static>(
+        &self,
+        event_listener: &EventListener<T>,
+        listener: Arc<dyn Listener<T>>,
+    ) {
+        trace!("add event_listener: {:?}", event_listener);
+        let topic_key = &event_listener.topic;
+        let handlers = &self.inner.topic_handlers;
+        handlers.add_listener(event_listener.rand_id, topic_key.clone(), listener.clone());
+
+        let retained = handlers.retained::<T>(topic_key);
+        if !retained.is_empty() {
+            deliver(Box::new(move || {
+                retained.iter().for_each(|event| listener.handle(event))
+            }));
+        }
+    }
+
     /// unregister an event listener
     #[cfg_attr(docsrs, doc(cfg(feature = "sync")))]
     pub fn unregister<T: 'static>(&self, event_listener: EventListener<T>) {
+        self.inner
+            .topic_handlers
+            .remove_async_listener::<T>(event_listener.rand_id, &event_listener.topic);
         self.inner
             .topic_handlers
             .remove_listener::<T, _>(event_listener.rand_id, event_listener.topic);
     }
 
     /// post an event to eventbus
+    ///
+    /// Listeners are called on the posting thread, async listeners only receive
+    /// `post_async`. The event is borrowed, so when posted from inside a handler it
+    /// is delivered before `post` returns, use `post_owned` to deliver it after the
+    /// current event instead.
     #[cfg_attr(docsrs, doc(cfg(feature = "sync")))]
     pub fn post<T: Sync + 'static>(&self, event: &Event<T>) {
-        self.inner.topic_handlers.notify(event);
+        let handlers = &self.inner.topic_handlers;
+        let listeners = handlers.get_listener::<T, _>(event.topic.clone());
+        let wildcards = handlers.get_wildcards::<T>();
+        handlers.retain_cloned(event);
+        deliver_now(|| TopicHandlers::notify(&listeners, &wildcards, event));
+    }
+
+    /// post an event to eventbus, taking its ownership
+    ///
+    /// Listeners are called on the posting thread. Events posted from inside a
+    /// handler are queued and delivered in FIFO order after the current event.
+    #[cfg_attr(docsrs, doc(cfg(feature = "sync")))]
+    pub fn post_owned<T: Sync + 'static>(&self, event: Event<T>) {
+        let handlers = &self.inner.topic_handlers;
+        let listeners = handlers.get_listener::<T, _>(event.topic.clone());
+        let wildcards = handlers.get_wildcards::<T>();
+        let event = Arc::new(event);
+        handlers.retain(&event);
+        deliver(Box::new(move || {
+            TopicHandlers::notify(&listeners, &wildcards, &event)
+        }));
     }
 }
 
@@ -77,22 +145,45 @@ impl<T: 'static> EventListener<T> {
 }
 
 impl TopicHandlers {
-    fn add_listener<T: 'static, K: Into<TopicKey>, L: Listener<T>>(
+    fn add_listener<T: 'static, K: Into<TopicKey>>(
         &self,
         rand_id: u64,
         topic_key: K,
-        listener: L,
+        listener: Arc<dyn Listener<T>>,
     ) {
         trace!("add listener: rand_id={}", rand_id);
-        let listeners = self.get_listener::<T, K>(topic_key);
-        listeners.lock().insert(rand_id, Box::new(listener));
+        let topic_key = topic_key.into();
+        if topic_key.is_pattern() {
+            let wildcards = self.get_wildcards::<T>();
+            wildcards.lock().insert(&topic_key, rand_id, listener);
+            return;
+        }
+        let listeners = self.get_listener::<T, _>(topic_key);
+        listeners.lock().insert(rand_id, listener);
     }
 
-    fn remove_listener<T: 'static, K: Into<TopicKey>>(&self, rand_id: u64, topic_key: K) {
-        let listeners = self.get_listener::<T, K>(topic_key);
+    pub(crate) fn remove_listener<T: 'static, K: Into<TopicKey>>(
+        &self,
+        rand_id: u64,
+        topic_key: K,
+    ) {
+        let topic_key = topic_key.into();
+        if topic_key.is_pattern() {
+            self.get_wildcards::<T>().lock().remove(&topic_key, rand_id);
+            return;
+        }
+        let listeners = self.get_listener::<T, _>(topic_key);
         listeners.lock().remove(&rand_id);
     }
 
+    fn get_wildcards<T: 'static>(&self) -> WildcardListeners<T> {
+        let mut guard = self.inner.lock();
+        if !guard.contains::<WildcardListeners<T>>() {
+            guard.insert::<WildcardListeners<T>>(Default::default());
+        }
+        guard.get::<WildcardListeners<T>>().unwrap().clone()
+    }
+
     fn get_listener<T: 'static, K: Into<TopicKey>>(&self, topic_key: K) -> EventListeners<T> {
         let mut guard = self.inner.lock();
         if !guard.contains::<TopicHandlersMap<T>>() {
@@ -102,24 +193,71 @@ impl TopicHandlers {
         let mut inner_guard = inner.lock();
 
         let topic_key = topic_key.into();
-        let listeners = inner_guard
-            .entry(topic_key)
-            .or_insert_with(Default::default);
+        let listeners = inner_guard.entry(topic_key).or_default();
         trace!("current listeners: {}", listeners.lock().len());
         listeners.clone()
     }
 
-    fn notify<T: Sync + 'static>(&self, event: &Event<T>) {
-        let listeners = self.get_listener::<T, _>(event.topic.clone());
-        let guard = listeners.lock();
+    /// notify a snapshot of the listeners, the lock is not held while handling,
+    /// so listeners can register, unregister and post from inside `handle`
+    fn notify<T: 'static>(
+        listeners: &EventListeners<T>,
+        wildcards: &WildcardListeners<T>,
+        event: &Event<T>,
+    ) {
+        let mut snapshot: Vec<_> = listeners.lock().values().cloned().collect();
+        let wildcards = wildcards.lock();
+        if !wildcards.is_empty() {
+            snapshot.extend(wildcards.matches(&event.topic));
+        }
+        drop(wildcards);
 
-        guard.iter().for_each(|(_, listener)| {
+        snapshot.iter().for_each(|listener| {
             trace!("notify listener for event [{:?}]", event.topic);
             listener.handle(event)
         });
     }
 }
 
+/// deliver an event now, or queue it if an event is delivering on this thread
+fn deliver(delivery: Delivery) {
+    let delivery = PENDING.with(|pending| match pending.borrow_mut().as_mut() {
+        Some(queue) => {
+            queue.push_back(delivery);
+            None
+        }
+        None => Some(delivery),
+    });
+    if let Some(delivery) = delivery {
+        deliver_now(delivery);
+    }
+}
+
+/// deliver an event now, then the events queued by its listeners
+///
+/// Inside a delivery on this thread the event is delivered inline, before the
+/// events already queued.
+fn deliver_now(delivery: impl FnOnce()) {
+    let outermost = PENDING.with(|pending| {
+        let mut pending = pending.borrow_mut();
+        if pending.is_some() {
+            return false;
+        }
+        *pending = Some(VecDeque::new());
+        true
+    });
+    if !outermost {
+        delivery();
+        return;
+    }
+
+    let _draining = Draining;
+    delivery();
+    while let Some(next) = PENDING.with(|pending| pending.borrow_mut().as_mut()?.pop_front()) {
+        next();
+    }
+}
+
 impl<T: Sync + 'static> Topic<T> {
     /// shorthand for post event to eventbus
     #[cfg_attr(docsrs, doc(cfg(feature = "sync")))]
@@ -127,10 +265,132 @@ impl<T: Sync + 'static> Topic<T> {
         self.bus.post(event);
     }
 
+    /// shorthand for post owned event to eventbus
+    #[cfg_attr(docsrs, doc(cfg(feature = "sync")))]
+    pub fn post_owned(&self, event: Event<T>) {
+        self.bus.post_owned(event);
+    }
+
     /// shorthand for post message to eventbus
     #[cfg_attr(docsrs, doc(cfg(feature = "sync")))]
     pub fn post_message(&self, message: T) {
         let event = self.create_event(message);
-        self.post(&event);
+        self.post_owned(event);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use parking_lot::Mutex;
+
+    use crate::{Event, EventListener, Eventbus, Listener};
+
+    struct Record(Arc<Mutex<Vec<u32>>>);
+
+    impl Listener<u32> for Record {
+        fn handle(&self, event: &Event<u32>) {
+            self.0.lock().push(**event);
+        }
+    }
+
+    /// posts follow-up events and registers a listener from inside `handle`
+    struct Chain {
+        bus: Eventbus,
+        received: Arc<Mutex<Vec<u32>>>,
+        late: Arc<Mutex<Vec<u32>>>,
+    }
+
+    impl Listener<u32> for Chain {
+        fn handle(&self, event: &Event<u32>) {
+            self.received.lock().push(**event);
+            match **event {
+                0 => {
+                    self.bus.register("chain", Record(self.late.clone()));
+                    self.bus.post_owned(Event::new("chain", 1u32));
+                    self.bus.post_owned(Event::new("chain", 2u32));
+                }
+                1 => self.bus.post_owned(Event::new("chain", 3u32)),
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn test_nested_post_is_fifo() {
+        let bus = Eventbus::new();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let late = Arc::new(Mutex::new(Vec::new()));
+        bus.register(
+            "chain",
+            Chain {
+                bus: bus.clone(),
+                received: received.clone(),
+                late: late.clone(),
+            },
+        );
+
+        bus.post(&Event::new("chain", 0u32));
+        assert_eq!(*received.lock(), vec![0, 1, 2, 3]);
+        // registered while delivering event 0, receives the follow-up events
+        assert_eq!(*late.lock(), vec![1, 2, 3]);
+    }
+
+    /// unregisters itself when the first event is received
+    struct Once {
+        this: Arc<Mutex<Option<EventListener<u32>>>>,
+        received: Arc<Mutex<Vec<u32>>>,
+    }
+
+    impl Listener<u32> for Once {
+        fn handle(&self, event: &Event<u32>) {
+            self.received.lock().push(**event);
+            if let Some(this) = self.this.lock().take() {
+                this.unregister();
+            }
+        }
+    }
+
+    #[test]
+    fn test_self_unregistration() {
+        let bus = Eventbus::new();
+        let topic = bus.create_topic::<u32, _>("once");
+        let this = Arc::new(Mutex::new(None));
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let listener = bus.register(
+            "once",
+            Once {
+                this: this.clone(),
+                received: received.clone(),
+            },
+        );
+        *this.lock() = Some(listener);
+
+        topic.post_message(1);
+        topic.post_message(2);
+        assert_eq!(*received.lock(), vec![1]);
+        assert!(topic.get_listeners().lock().is_empty());
+    }
+
+    #[test]
+    fn test_wildcard_listeners() {
+        let bus = Eventbus::new();
+        let single = Arc::new(Mutex::new(Vec::new()));
+        let multi = Arc::new(Mutex::new(Vec::new()));
+        bus.register("display/*", Record(single.clone()));
+        let listener = bus.register("display/#", Record(multi.clone()));
+
+        let mode = bus.create_topic::<u32, _>("display/mode");
+        mode.post_message(1);
+        bus.post(&Event::new("display/mode/eink", 2u32));
+        bus.post_owned(Event::new("wmi/lid/open", 3u32));
+        assert_eq!(*single.lock(), vec![1]);
+        assert_eq!(*multi.lock(), vec![1, 2]);
+
+        listener.unregister();
+        mode.post_message(4);
+        assert_eq!(*single.lock(), vec![1, 4]);
+        assert_eq!(*multi.lock(), vec![1, 2]);
     }
 }
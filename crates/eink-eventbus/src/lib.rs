@@ -1,36 +1,57 @@
 //! An universal eventbus for Rust!
 //!
-//! This crate provides a strong-typed asynchronous eventbus implementation.
+//! This crate provides a strong-typed eventbus implementation.
 //!
 //! # Get Started
 //!
-//! comet-eventbus is async-first-classed. We recommend you to use async API.
+//! Synchronous listeners (`Listener`) are called on the posting thread by `post`,
+//! `post_owned` takes the event so it can be queued when posted from a listener.
+//! Asynchronous listeners (`AsyncListener`) run on the tokio runtime, `post_async`
+//! returns once the event is enqueued, so a slow listener never stalls the poster.
+//! The two dispatch paths are separate: `post` only reaches `Listener`s and
+//! `post_async` only reaches `AsyncListener`s.
+//! A topic can also have a dedicated dispatcher task which handles its events in
+//! FIFO order.
 //!
-//! Add following code to your `Cargo.toml`:
-//! ```toml
-//! comet-eventbus = "0.1"
-//! ```
+//! A sticky topic retains its last events, listeners registered later receive
+//! them first and `current()` returns the last message, so late components can
+//! read state such as the laptop mode without waiting for the next change.
+//!
+//! `subscribe` returns a `Subscription` guard which unregisters the listener when
+//! dropped, `register_weak` and `register_once` install listeners which remove
+//! themselves when their owner is gone or after the first event.
 //!
 //! ## Example
 //!
 //! ```
-//! use comet_eventbus::{Event, Eventbus};
+//! use eink_eventbus::{AsyncListener, Event, Eventbus};
 //!
 //! // define your message struct
 //! struct Message {
 //!     content: u8,
 //! }
 //!
+//! struct Printer;
+//!
+//! #[async_trait::async_trait]
+//! impl AsyncListener<Message> for Printer {
+//!     async fn handle(&self, event: &Event<Message>) {
+//!         println!("content: {}", event.content);
+//!     }
+//! }
+//!
 //! #[tokio::main]
 //! async fn main() {
 //!     // creat a new eventbus
 //!     let eventbus = Eventbus::new();
 //!
-//!     // create topic
-//!     let topic = eventbus.create_topic("my awsome topic").await;
+//!     // create topic and its dispatcher task
+//!     let topic = eventbus.create_topic("my awsome topic");
+//!     topic.spawn_dispatcher();
+//!     eventbus.register_async("my awsome topic", Printer);
 //!
 //!     // post message to a topic
-//!     topic.post_message(Message { content: 0 }).await;
+//!     topic.post_message_async(Message { content: 0 }).await;
 //! }
 //! ```
 //!
@@ -53,16 +74,24 @@ use std::sync::Arc;
 
 mod event;
 mod event_listener;
+mod impl_async;
 mod impl_sync;
+mod service;
+mod sticky;
+mod subscription;
 mod topic;
 mod topic_key;
+mod topic_tree;
 
 pub use event::Event;
 pub use event_listener::EventListener;
 pub use topic::Topic;
 pub use topic_key::TopicKey;
 
+pub use impl_async::{AsyncEventListeners, AsyncListener};
 pub use impl_sync::Listener;
+pub use service::Request;
+pub use subscription::Subscription;
 
 use parking_lot::Mutex;
 
@@ -73,9 +102,11 @@ pub struct Eventbus {
 }
 
 /// short hand of event listeners set
-pub type EventListeners<T> = Arc<Mutex<HashMap<u64, Box<dyn Listener<T>>>>>;
+pub type EventListeners<T> = Arc<Mutex<HashMap<u64, Arc<dyn Listener<T>>>>>;
 /// short hand of topic to handlers map
 pub type TopicHandlersMap<T> = Arc<Mutex<HashMap<TopicKey, EventListeners<T>>>>;
+/// listeners registered with topic patterns
+type WildcardListeners<T> = Arc<Mutex<topic_tree::TopicTree<Arc<dyn Listener<T>>>>>;
 
 #[derive(Debug)]
 struct EventbusInner {
@@ -1,83 +1,128 @@
 //! Service wrapped of an eventbus.
 //!
 //! e.g. Create a Handler to accept request and post Response on the topic.
+//!
+//! ```
+//! use eink_eventbus::{Event, Eventbus, Listener, Request};
+//!
+//! struct MyServiceRequest {
+//!     arg0: u8,
+//!     arg1: String,
+//! }
+//!
+//! #[derive(Clone)]
+//! struct MyService {
+//!     eventbus: Eventbus,
+//! }
+//!
+//! impl Listener<Request<MyServiceRequest>> for MyService {
+//!     fn handle(&self, event: &Event<Request<MyServiceRequest>>) {
+//!         let response = format!("{}, {}", event.arg0, event.arg1);
+//!         event.reply(&self.eventbus, response);
+//!     }
+//! }
+//!
+//! let eventbus = Eventbus::new();
+//! let service = MyService { eventbus: eventbus.clone() };
+//! eventbus.register("my_service", service);
+//!
+//! let request = MyServiceRequest { arg0: 0, arg1: "hello".into() };
+//! let response: Option<String> = eventbus.request("my_service", request, None);
+//! assert_eq!(response.as_deref(), Some("0, hello"));
+//! ```
+
+use std::fmt::{Debug, Formatter};
+use std::ops::Deref;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+
+use crate::{Event, Eventbus, Listener, TopicKey};
+
+/// A request message carrying the topic its response is posted to
+pub struct Request<T> {
+    reply_topic: TopicKey,
+    message: T,
+}
+
+impl<T> Request<T> {
+    /// topic of the response
+    pub fn reply_topic(&self) -> &TopicKey {
+        &self.reply_topic
+    }
+
+    /// post the response of this request
+    pub fn reply<R: Sync + 'static>(&self, eventbus: &Eventbus, response: R) {
+        eventbus.post_owned(Event::new(self.reply_topic.clone(), response));
+    }
+
+    /// into inner message
+    pub fn into_inner(self) -> T {
+        self.message
+    }
+}
+
+impl<T> Deref for Request<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.message
+    }
+}
+
+impl<T> Debug for Request<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct(format!("Request<{}>", std::any::type_name::<T>()).as_str())
+            .field("reply_topic", &self.reply_topic)
+            .finish()
+    }
+}
+
+/// receive the response once, the sender is dropped after the first event
+struct Responder<R> {
+    tx: Mutex<Option<mpsc::Sender<R>>>,
+}
+
+impl<R: Clone + Send + 'static> Listener<R> for Responder<R> {
+    fn handle(&self, event: &Event<R>) {
+        if let Some(tx) = self.tx.lock().take() {
+            let _ = tx.send((**event).clone());
+        }
+    }
+}
 
+impl Eventbus {
+    /// post a request and wait for its response, `None` on timeout
+    ///
+    /// The response is received by a one-shot listener on a random reply topic.
+    /// Must not be called from inside a listener without a timeout, events posted
+    /// there are delivered after the listener returns.
+    pub fn request<T, R, K>(&self, topic_key: K, message: T, timeout: Option<Duration>) -> Option<R>
+    where
+        T: Sync + 'static,
+        R: Clone + Send + 'static,
+        K: Into<TopicKey>,
+    {
+        let reply_topic = TopicKey::from(format!("reply/{}", hex::encode(TopicKey::random(8))));
+        let (tx, rx) = mpsc::channel();
+        let responder = Responder {
+            tx: Mutex::new(Some(tx)),
+        };
+        let listener = self.register_once(reply_topic.clone(), responder);
 
-/// expand to :
-/// ```
-/// #[derive(Clone)]
-/// pub struct MyService {
-///     eventbus: comet_eventbus::Eventbus
-/// }
-///
-/// struct MyServiceRequest {
-///     arg0: u8,
-///     arg1: String,
-///     arg2: Vec<u8>,
-///     reply_topic: comet_eventbus::TopicKey,
-/// }
-/// struct MyServiceResponse {
-///     inner: String,
-/// }
-///
-/// impl Handler<MyServiceRequest> for MyService {
-///     fn handle(&self, event: &comet_eventbus::Event<MyServiceRequest>) {
-///         fn my_service(arg0: u8, arg1: String, arg2: Vec<u8>) -> String {
-///             String::new()
-///         }
-///         let request = event.into_inner();
-///         let inner = my_service(request.arg0, request.arg1, request.arg2);
-///         self.eventbus.post(&comet_eventbus::Event::new(
-///             request.reply_topic,
-///             MyServiceResponse { inner }
-///         ));
-///     }
-/// }
-///
-/// impl MyService {
-///     pub fn new(eventbus: comet_eventbus::Eventbus)-> MyService {
-///         Self { eventbus }
-///     }
-///     pub fn register(&self) {
-///         self.eventbus.register("topic", self.clone());
-///     }
-///     pub fn call(&self, arg0: u8, arg1: String, arg2: Vec<u8>) -> String {
-///         struct OneTimeListener {
-///             tx: std::sync::mpsc::Sender<String>,
-///         }
-///
-///         impl OneTimeListener {
-///             fn new() -> (Self, std::sync::mpsc::Receiver<String>) {
-///                 let (tx, rx) = std::sync::mpsc::channel();
-///                 (Self { tx }, rx)
-///             }
-///         }
-///
-///         impl comet_eventbus::Listener<MyServiceResponse> for OneTimeListener {
-///             fn handle(&self, event: &comet_eventbus::Event<MyServiceResponse>) {
-///                 let response = event.into_inner();
-///                 self.tx.send(response.inner).unwrap();
-///             }
-///         }
-///
-///         let (listener, rx) = OneTimeListener::new();
-///         let reply_topic = comet_eventbus::TopicKey::random(16);
-///         let reply_topic = reply_topic.hash();
-///         let request = MyServiceRequest {
-///             arg0,
-///             arg1,
-///             arg2,
-///             reply_topic: reply_topic.clone(),
-///         };
-///         let listener = self.eventbus.register(reply_topic, listener);
-///         self.eventbus.post(&comet_eventbus::Event::new(reply_topic, request));
-///         let response = rx.recv().unwrap();
-///         self.eventbus.unregister(listener);
-///         response
-///     }
-/// }
-/// ```
-// #[service(MyService, "topic")]
-fn my_service(arg0: u8, arg1: String, arg2: Vec<u8>) -> String {
-    format!("{}, {}, {:?}", arg0, arg1, arg2)
-}
\ No newline at end of file
+        self.post_owned(Event::new(
+            topic_key,
+            Request {
+                reply_topic,
+                message,
+            },
+        ));
+        let response = match timeout {
+            Some(timeout) => rx.recv_timeout(timeout).ok(),
+            None => rx.recv().ok(),
+        };
+        listener.unregister();
+        response
+    }
+}
@@ -0,0 +1,215 @@
+//
+// Copyright (C) Lenovo ThinkBook Gen4 Project.
+//
+// This program is protected under international and China copyright laws as
+// an unpublished work. This program is confidential and proprietary to the
+// copyright owners. Reproduction or disclosure, in whole or in part, or the
+// production of derivative works therefrom without the express permission of
+// the copyright owners is prohibited.
+//
+// All rights reserved.
+//
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+
+use crate::{Event, Eventbus, Topic, TopicHandlers, TopicKey};
+
+/// Retained events of a sticky topic
+///
+/// The trait object is `Send` and `Sync` for any message type, so the store can be
+/// looked up from `post` which does not require `T: Send` or `T: Clone`.
+pub(crate) trait Retain<T>: Send + Sync {
+    /// keep an event, the oldest event is dropped when the capacity is reached
+    fn push(&self, event: Arc<Event<T>>);
+    /// keep a copy of a borrowed event
+    fn push_cloned(&self, event: &Event<T>);
+    /// retained events, the oldest first
+    fn values(&self) -> Vec<Arc<Event<T>>>;
+}
+
+struct Sticky<T> {
+    capacity: usize,
+    values: Mutex<VecDeque<Arc<Event<T>>>>,
+}
+
+impl<T: Clone + Send + Sync + 'static> Retain<T> for Sticky<T> {
+    fn push(&self, event: Arc<Event<T>>) {
+        let mut values = self.values.lock();
+        while values.len() >= self.capacity {
+            values.pop_front();
+        }
+        values.push_back(event);
+    }
+
+    fn push_cloned(&self, event: &Event<T>) {
+        self.push(Arc::new(event.clone()));
+    }
+
+    fn values(&self) -> Vec<Arc<Event<T>>> {
+        self.values.lock().iter().cloned().collect()
+    }
+}
+
+/// short hand of topic to retained events map
+type StickyMap<T> = Arc<Mutex<HashMap<TopicKey, Arc<dyn Retain<T>>>>>;
+
+impl Eventbus {
+    /// make a topic sticky, the last `capacity` events are retained
+    ///
+    /// Listeners registered later, including the ones registered with a matching
+    /// pattern, receive the retained events first. A listener registered while an
+    /// event is posting may receive that event twice.
+    pub fn make_sticky<T: Clone + Send + Sync + 'static, K: Into<TopicKey>>(
+        &self,
+        topic_key: K,
+        capacity: usize,
+    ) {
+        let map = self.inner.topic_handlers.get_sticky_map::<T>();
+        map.lock().entry(topic_key.into()).or_insert_with(|| {
+            Arc::new(Sticky::<T> {
+                capacity: capacity.max(1),
+                values: Default::default(),
+            })
+        });
+    }
+
+    /// create a sticky `Topic` which retains the last `capacity` events
+    pub fn create_sticky_topic<T: Clone + Send + Sync + 'static, K: Into<TopicKey>>(
+        &self,
+        topic_key: K,
+        capacity: usize,
+    ) -> Topic<T> {
+        let topic_key = topic_key.into();
+        self.make_sticky::<T, _>(topic_key.clone(), capacity);
+        self.create_topic(topic_key)
+    }
+
+    /// the last message posted to a sticky topic
+    pub fn current<T: Clone + 'static, K: Into<TopicKey>>(&self, topic_key: K) -> Option<T> {
+        self.history::<T, K>(topic_key).pop()
+    }
+
+    /// messages retained by a sticky topic, the oldest first
+    pub fn history<T: Clone + 'static, K: Into<TopicKey>>(&self, topic_key: K) -> Vec<T> {
+        self.inner
+            .topic_handlers
+            .retained::<T>(&topic_key.into())
+            .iter()
+            .map(|event| event.message.clone())
+            .collect()
+    }
+}
+
+impl TopicHandlers {
+    fn get_sticky_map<T: 'static>(&self) -> StickyMap<T> {
+        let mut guard = self.inner.lock();
+        if !guard.contains::<StickyMap<T>>() {
+            guard.insert::<StickyMap<T>>(Default::default());
+        }
+        guard.get::<StickyMap<T>>().unwrap().clone()
+    }
+
+    fn get_sticky<T: 'static>(&self, topic_key: &TopicKey) -> Option<Arc<dyn Retain<T>>> {
+        let guard = self.inner.lock();
+        let map = guard.get::<StickyMap<T>>()?;
+        let sticky = map.lock().get(topic_key).cloned();
+        sticky
+    }
+
+    /// keep an event if its topic is sticky
+    pub(crate) fn retain<T: 'static>(&self, event: &Arc<Event<T>>) {
+        if let Some(sticky) = self.get_sticky::<T>(&event.topic) {
+            sticky.push(event.clone());
+        }
+    }
+
+    /// keep a copy of a borrowed event if its topic is sticky
+    pub(crate) fn retain_cloned<T: 'static>(&self, event: &Event<T>) {
+        if let Some(sticky) = self.get_sticky::<T>(&event.topic) {
+            sticky.push_cloned(event);
+        }
+    }
+
+    /// retained events of the sticky topics matching a topic key or pattern
+    pub(crate) fn retained<T: 'static>(&self, topic_key: &TopicKey) -> Vec<Arc<Event<T>>> {
+        let map = {
+            let guard = self.inner.lock();
+            match guard.get::<StickyMap<T>>() {
+                Some(map) => map.clone(),
+                None => return Vec::new(),
+            }
+        };
+        let map = map.lock();
+        if !topic_key.is_pattern() {
+            return match map.get(topic_key) {
+                Some(sticky) => sticky.values(),
+                None => Vec::new(),
+            };
+        }
+        map.iter()
+            .filter(|(key, _)| topic_key.matches(key))
+            .flat_map(|(_, sticky)| sticky.values())
+            .collect()
+    }
+}
+
+impl<T: Clone + 'static> Topic<T> {
+    /// the last message posted to this topic, the topic must be sticky
+    pub fn current(&self) -> Option<T> {
+        self.bus.current::<T, _>(self.key.clone())
+    }
+
+    /// messages retained by this topic, the oldest first
+    pub fn history(&self) -> Vec<T> {
+        self.bus.history::<T, _>(self.key.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use parking_lot::Mutex;
+
+    use crate::{Event, Eventbus, Listener};
+
+    struct Record(Arc<Mutex<Vec<u32>>>);
+
+    impl Listener<u32> for Record {
+        fn handle(&self, event: &Event<u32>) {
+            self.0.lock().push(**event);
+        }
+    }
+
+    #[test]
+    fn test_sticky_replay() {
+        let bus = Eventbus::new();
+        let mode = bus.create_sticky_topic::<u32, _>("laptop/mode", 1);
+        let lid = bus.create_sticky_topic::<u32, _>("laptop/lid", 2);
+        assert_eq!(mode.current(), None);
+
+        mode.post_message(1);
+        mode.post(&Event::new("laptop/mode", 2));
+        for state in 0..3 {
+            lid.post_message(state);
+        }
+        assert_eq!(mode.current(), Some(2));
+        assert_eq!(lid.history(), vec![1, 2]);
+
+        // late subscribers receive the retained events first
+        let late = Arc::new(Mutex::new(Vec::new()));
+        bus.register("laptop/mode", Record(late.clone()));
+        assert_eq!(*late.lock(), vec![2]);
+        mode.post_message(3);
+        assert_eq!(*late.lock(), vec![2, 3]);
+
+        let all = Arc::new(Mutex::new(Vec::new()));
+        bus.register("laptop/*", Record(all.clone()));
+        let mut replayed = all.lock().clone();
+        replayed.sort();
+        assert_eq!(replayed, vec![1, 2, 3]);
+    }
+}
@@ -0,0 +1,208 @@
+//
+// Copyright (C) Lenovo ThinkBook Gen4 Project.
+//
+// This program is protected under international and China copyright laws as
+// an unpublished work. This program is confidential and proprietary to the
+// copyright owners. Reproduction or disclosure, in whole or in part, or the
+// production of derivative works therefrom without the express permission of
+// the copyright owners is prohibited.
+//
+// All rights reserved.
+//
+
+use std::fmt::{Debug, Formatter};
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Weak};
+
+use crate::{Event, EventListener, Eventbus, Listener, TopicHandlers, TopicKey};
+
+/// A guard which unregisters its listener when dropped
+#[must_use = "the listener is unregistered when the subscription is dropped"]
+pub struct Subscription<T: 'static> {
+    event_listener: Option<EventListener<T>>,
+}
+
+impl<T: 'static> Subscription<T> {
+    /// keep the listener registered, returns the `EventListener` to unregister it explicitly
+    pub fn detach(mut self) -> EventListener<T> {
+        self.event_listener.take().unwrap()
+    }
+
+    /// unregister the listener now
+    pub fn unregister(self) {}
+}
+
+impl<T: 'static> Drop for Subscription<T> {
+    fn drop(&mut self) {
+        if let Some(event_listener) = self.event_listener.take() {
+            event_listener.unregister();
+        }
+    }
+}
+
+impl<T: 'static> Debug for Subscription<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Subscription")
+            .field(&self.event_listener)
+            .finish()
+    }
+}
+
+impl<T: 'static> EventListener<T> {
+    /// turn into a guard which unregisters the listener when dropped
+    pub fn into_subscription(self) -> Subscription<T> {
+        Subscription {
+            event_listener: Some(self),
+        }
+    }
+}
+
+/// Remove a listener from inside its own `handle`
+///
+/// Only a weak reference to the handlers is held, so a listener stored in the
+/// eventbus does not keep the eventbus alive.
+struct Remover<T> {
+    topic: TopicKey,
+    rand_id: u64,
+    handlers: Weak<TopicHandlers>,
+    _handler: PhantomData<fn(T)>,
+}
+
+impl<T: 'static> Remover<T> {
+    fn new(event_listener: &EventListener<T>) -> Self {
+        Self {
+            topic: event_listener.topic.clone(),
+            rand_id: event_listener.rand_id,
+            handlers: Arc::downgrade(&event_listener.bus.inner.topic_handlers),
+            _handler: PhantomData,
+        }
+    }
+
+    fn remove(&self) {
+        if let Some(handlers) = self.handlers.upgrade() {
+            handlers.remove_listener::<T, _>(self.rand_id, self.topic.clone());
+        }
+    }
+}
+
+/// forward to a listener as long as its owner keeps it alive
+struct WeakListener<T, L> {
+    listener: Weak<L>,
+    remover: Remover<T>,
+}
+
+impl<T: 'static, L: Listener<T>> Listener<T> for WeakListener<T, L> {
+    fn handle(&self, event: &Event<T>) {
+        match self.listener.upgrade() {
+            Some(listener) => listener.handle(event),
+            None => self.remover.remove(),
+        }
+    }
+}
+
+/// forward the first event to a listener and remove itself
+struct OnceListener<T, L> {
+    listener: L,
+    fired: AtomicBool,
+    remover: Remover<T>,
+}
+
+impl<T: 'static, L: Listener<T>> Listener<T> for OnceListener<T, L> {
+    fn handle(&self, event: &Event<T>) {
+        // a snapshot taken by a concurrent post may still contain this listener
+        if self.fired.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        self.remover.remove();
+        self.listener.handle(event);
+    }
+}
+
+impl Eventbus {
+    /// register a listener and return a guard which unregisters it when dropped
+    pub fn subscribe<T: 'static, K: Into<TopicKey>, L: Listener<T>>(
+        &self,
+        topic_key: K,
+        listener: L,
+    ) -> Subscription<T> {
+        self.register(topic_key, listener).into_subscription()
+    }
+
+    /// register a listener without keeping it alive
+    ///
+    /// Once all strong references of the listener are dropped, it is removed at the
+    /// next event of the topic.
+    pub fn register_weak<T: 'static, K: Into<TopicKey>, L: Listener<T>>(
+        &self,
+        topic_key: K,
+        listener: &Arc<L>,
+    ) -> EventListener<T> {
+        let event_listener = EventListener::<T>::new(topic_key, self.clone());
+        let listener = WeakListener {
+            listener: Arc::downgrade(listener),
+            remover: Remover::new(&event_listener),
+        };
+        self.add_event_listener(&event_listener, Arc::new(listener));
+        event_listener
+    }
+
+    /// register a listener which is removed after handling the first event
+    pub fn register_once<T: 'static, K: Into<TopicKey>, L: Listener<T>>(
+        &self,
+        topic_key: K,
+        listener: L,
+    ) -> EventListener<T> {
+        let event_listener = EventListener::<T>::new(topic_key, self.clone());
+        let listener = OnceListener {
+            listener,
+            fired: AtomicBool::new(false),
+            remover: Remover::new(&event_listener),
+        };
+        self.add_event_listener(&event_listener, Arc::new(listener));
+        event_listener
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use parking_lot::Mutex;
+
+    use crate::{Event, Eventbus, Listener};
+
+    struct Record(Arc<Mutex<Vec<u32>>>);
+
+    impl Listener<u32> for Record {
+        fn handle(&self, event: &Event<u32>) {
+            self.0.lock().push(**event);
+        }
+    }
+
+    #[test]
+    fn test_listener_lifetimes() {
+        let bus = Eventbus::new();
+        let topic = bus.create_topic::<u32, _>("lid");
+
+        let guarded = Arc::new(Mutex::new(Vec::new()));
+        let subscription = bus.subscribe("lid", Record(guarded.clone()));
+        let once = Arc::new(Mutex::new(Vec::new()));
+        bus.register_once("lid", Record(once.clone()));
+        let weak = Arc::new(Mutex::new(Vec::new()));
+        let owner = Arc::new(Record(weak.clone()));
+        bus.register_weak("lid", &owner);
+
+        topic.post_message(0);
+        topic.post_message(1);
+        drop(subscription);
+        drop(owner);
+        topic.post_message(2);
+
+        assert_eq!(*guarded.lock(), vec![0, 1]);
+        assert_eq!(*once.lock(), vec![0]);
+        assert_eq!(*weak.lock(), vec![0, 1]);
+        // the weak listener removed itself at the last event
+        assert!(topic.event_listeners.lock().is_empty());
+    }
+}
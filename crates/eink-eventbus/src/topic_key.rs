@@ -17,17 +17,31 @@ use std::hash::Hash;
 use std::ops::Deref;
 use std::str::Utf8Error;
 
+/// separator of topic levels
+pub(crate) const LEVEL_SEPARATOR: u8 = b'/';
+
+/// wildcard level which matches exactly one level
+pub(crate) const SINGLE_LEVEL_WILDCARD: &[u8] = b"*";
+
+/// wildcard level which matches the remaining levels, including none
+pub(crate) const MULTI_LEVEL_WILDCARD: &[u8] = b"#";
+
 /// Wrapper of bytes represent a `Topic`
 ///
+/// Topics are hierarchical, levels are separated by `/` (e.g. `display/mode/eink`).
+/// A listener registered with a pattern receives the events of all matching topics:
+/// `*` matches exactly one level (`display/*`), `#` as the last level matches the
+/// remaining levels (`wmi/#` matches `wmi`, `wmi/lid` and `wmi/lid/open`).
+///
 /// ## Example:
 /// ```
-/// use comet_eventbus::TopicKey;
+/// use eink_eventbus::TopicKey;
 ///
 /// // create topic from str literal
 /// TopicKey::from("my awsome topic");
 ///
 /// // crate topic from bytes literal
-/// TopicKey::from(b"deafbeef");
+/// TopicKey::from(&b"deafbeef"[..]);
 ///
 /// // create topic from Vec<u8>
 /// TopicKey::from(vec![0xde, 0xaf, 0xbe, 0xef]);
@@ -41,10 +55,35 @@ impl TopicKey {
         std::str::from_utf8(self.as_ref())
     }
 
+    /// levels of the topic separated by `/`
+    pub fn levels(&self) -> impl Iterator<Item = &[u8]> {
+        self.as_ref().split(|byte| *byte == LEVEL_SEPARATOR)
+    }
+
+    /// whether the topic contains a wildcard level
+    pub fn is_pattern(&self) -> bool {
+        self.levels()
+            .any(|level| level == SINGLE_LEVEL_WILDCARD || level == MULTI_LEVEL_WILDCARD)
+    }
+
+    /// whether the topic pattern matches a topic
+    pub fn matches(&self, topic: &TopicKey) -> bool {
+        let mut levels = topic.levels();
+        for pattern in self.levels() {
+            if pattern == MULTI_LEVEL_WILDCARD {
+                return true;
+            }
+            match levels.next() {
+                Some(level) if pattern == SINGLE_LEVEL_WILDCARD || pattern == level => {}
+                _ => return false,
+            }
+        }
+        levels.next().is_none()
+    }
+
     /// Generate a random topic
     pub fn random(len: usize) -> Self {
-        let mut buf = Vec::new();
-        buf.resize(len, 0);
+        let mut buf = vec![0; len];
         thread_rng().fill_bytes(&mut buf);
         Self::from(buf)
     }
@@ -82,6 +121,12 @@ impl From<&'static [u8]> for TopicKey {
     }
 }
 
+impl From<String> for TopicKey {
+    fn from(value: String) -> Self {
+        Self(Cow::from(value.into_bytes()))
+    }
+}
+
 impl From<&'static str> for TopicKey {
     fn from(value: &'static str) -> Self {
         Self(Cow::from(value.as_bytes()))
@@ -0,0 +1,187 @@
+//
+// Copyright (C) Lenovo ThinkBook Gen4 Project.
+//
+// This program is protected under international and China copyright laws as
+// an unpublished work. This program is confidential and proprietary to the
+// copyright owners. Reproduction or disclosure, in whole or in part, or the
+// production of derivative works therefrom without the express permission of
+// the copyright owners is prohibited.
+//
+// All rights reserved.
+//
+
+use std::collections::HashMap;
+
+use crate::topic_key::{MULTI_LEVEL_WILDCARD, SINGLE_LEVEL_WILDCARD};
+use crate::TopicKey;
+
+/// Wildcard subscriptions indexed by topic levels
+///
+/// Matching walks the levels of a topic once, the cost depends on the depth of
+/// the topic and the number of wildcard branches, not on the number of subscribers.
+pub(crate) struct TopicTree<V> {
+    root: Node<V>,
+}
+
+struct Node<V> {
+    children: HashMap<Vec<u8>, Node<V>>,
+    /// `*` matches exactly one level
+    single: Option<Box<Node<V>>>,
+    /// `#` matches the remaining levels, including none
+    multi: HashMap<u64, V>,
+    /// subscriptions ending at this level
+    values: HashMap<u64, V>,
+}
+
+impl<V> Default for Node<V> {
+    fn default() -> Self {
+        Self {
+            children: HashMap::new(),
+            single: None,
+            multi: HashMap::new(),
+            values: HashMap::new(),
+        }
+    }
+}
+
+impl<V> Default for TopicTree<V> {
+    fn default() -> Self {
+        Self {
+            root: Node::default(),
+        }
+    }
+}
+
+impl<V: Clone> TopicTree<V> {
+    /// add a subscription for a topic pattern
+    pub(crate) fn insert(&mut self, pattern: &TopicKey, id: u64, value: V) {
+        let mut node = &mut self.root;
+        for level in pattern.levels() {
+            match level {
+                MULTI_LEVEL_WILDCARD => {
+                    node.multi.insert(id, value);
+                    return;
+                }
+                SINGLE_LEVEL_WILDCARD => {
+                    node = &mut **node.single.get_or_insert_with(Default::default);
+                }
+                level => {
+                    node = node.children.entry(level.to_vec()).or_default();
+                }
+            }
+        }
+        node.values.insert(id, value);
+    }
+
+    /// remove a subscription, empty levels are pruned
+    pub(crate) fn remove(&mut self, pattern: &TopicKey, id: u64) {
+        let levels: Vec<&[u8]> = pattern.levels().collect();
+        Self::remove_from(&mut self.root, &levels, id);
+    }
+
+    fn remove_from(node: &mut Node<V>, levels: &[&[u8]], id: u64) {
+        let (level, rest) = match levels.split_first() {
+            Some(split) => split,
+            None => {
+                node.values.remove(&id);
+                return;
+            }
+        };
+        match *level {
+            MULTI_LEVEL_WILDCARD => {
+                node.multi.remove(&id);
+            }
+            SINGLE_LEVEL_WILDCARD => {
+                if let Some(single) = node.single.as_mut() {
+                    Self::remove_from(single, rest, id);
+                    if single.is_empty() {
+                        node.single = None;
+                    }
+                }
+            }
+            level => {
+                if let Some(child) = node.children.get_mut(level) {
+                    Self::remove_from(child, rest, id);
+                    if child.is_empty() {
+                        node.children.remove(level);
+                    }
+                }
+            }
+        }
+    }
+
+    /// subscriptions whose pattern matches a topic
+    pub(crate) fn matches(&self, topic: &TopicKey) -> Vec<V> {
+        let levels: Vec<&[u8]> = topic.levels().collect();
+        let mut matched = Vec::new();
+        Self::collect(&self.root, &levels, &mut matched);
+        matched
+    }
+
+    fn collect(node: &Node<V>, levels: &[&[u8]], matched: &mut Vec<V>) {
+        matched.extend(node.multi.values().cloned());
+        match levels.split_first() {
+            None => matched.extend(node.values.values().cloned()),
+            Some((level, rest)) => {
+                if let Some(child) = node.children.get(*level) {
+                    Self::collect(child, rest, matched);
+                }
+                if let Some(single) = &node.single {
+                    Self::collect(single, rest, matched);
+                }
+            }
+        }
+    }
+
+    /// whether there is no subscription
+    pub(crate) fn is_empty(&self) -> bool {
+        self.root.is_empty()
+    }
+}
+
+impl<V> Node<V> {
+    fn is_empty(&self) -> bool {
+        self.children.is_empty()
+            && self.single.is_none()
+            && self.multi.is_empty()
+            && self.values.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::TopicTree;
+    use crate::TopicKey;
+
+    fn matches(tree: &TopicTree<u32>, topic: &'static str) -> Vec<u32> {
+        let mut matched = tree.matches(&TopicKey::from(topic));
+        matched.sort();
+        matched
+    }
+
+    #[test]
+    fn test_wildcard_matching() {
+        let mut tree = TopicTree::default();
+        tree.insert(&TopicKey::from("display/*"), 1, 1);
+        tree.insert(&TopicKey::from("display/#"), 2, 2);
+        tree.insert(&TopicKey::from("*/lid/open"), 3, 3);
+        tree.insert(&TopicKey::from("#"), 4, 4);
+        tree.insert(&TopicKey::from("wmi/lid/open"), 5, 5);
+
+        assert_eq!(matches(&tree, "display/mode"), vec![1, 2, 4]);
+        assert_eq!(matches(&tree, "display/mode/eink"), vec![2, 4]);
+        assert_eq!(matches(&tree, "display"), vec![2, 4]);
+        assert_eq!(matches(&tree, "wmi/lid/open"), vec![3, 4, 5]);
+        assert_eq!(matches(&tree, "wmi/lid/close"), vec![4]);
+
+        tree.remove(&TopicKey::from("display/#"), 2);
+        tree.remove(&TopicKey::from("*/lid/open"), 3);
+        assert_eq!(matches(&tree, "display/mode/eink"), vec![4]);
+        assert_eq!(matches(&tree, "wmi/lid/open"), vec![4, 5]);
+
+        tree.remove(&TopicKey::from("display/*"), 1);
+        tree.remove(&TopicKey::from("#"), 4);
+        tree.remove(&TopicKey::from("wmi/lid/open"), 5);
+        assert!(tree.is_empty());
+    }
+}
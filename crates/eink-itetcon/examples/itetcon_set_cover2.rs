@@ -16,7 +16,7 @@ fn main() -> anyhow::Result<()> {
     let mut device = IteTconDevice::new()?;
     device.open()?;
     device.set_speed_mode();
-    device.set_cover_image("cover.jpg");
+    device.set_cover_image("cover.jpg")?;
     device.close();
     Ok(())
 }
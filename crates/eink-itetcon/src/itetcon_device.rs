@@ -14,7 +14,7 @@ use std::ffi::CString;
 use std::mem::zeroed;
 
 use anyhow::{bail, Result};
-use log::info;
+use log::{info, warn};
 use widestring::U16CString;
 use windows::Win32::Foundation::INVALID_HANDLE_VALUE;
 
@@ -88,7 +88,11 @@ impl IteTconDevice {
         self.sysinfo = sysinfo;
         self.is_open = true;
 
-        self.set_cover_image("C:\\Program Files\\Lenovo\\ThinkbookEinkPlus\\default_cover.bmp");
+        if let Err(err) =
+            self.set_cover_image("C:\\Program Files\\Lenovo\\ThinkbookEinkPlus\\default_cover.bmp")
+        {
+            warn!("EinkTcon: cannot set default cover image: {err}");
+        }
         //self.set_cover_image("C:\\ProgramData\\Lenovo\\ThinkbookEinkPlus\\ColorCover.bmp");
         Ok(())
     }
@@ -165,7 +169,21 @@ impl IteTconDevice {
     }
 
     /// 设置为 Cover 图像（SLOW，需要在后台线程运行）
-    pub fn set_cover_image(&mut self, img_path: &str) {
+    ///
+    /// 图片无法加载时返回错误
+    pub fn set_cover_image(&mut self, img_path: &str) -> Result<()> {
+        self.set_cover_image_with_progress(img_path, |_, _| {})
+    }
+
+    /// 设置为 Cover 图像，每个阶段开始时回调进度百分比与阶段名称
+    pub fn set_cover_image_with_progress<F>(
+        &mut self,
+        img_path: &str,
+        mut on_progress: F,
+    ) -> Result<()>
+    where
+        F: FnMut(u32, &str),
+    {
         //
         // 计算当前可用图片地址
         let image_idx = if self.latest_image_idx == u32::max_value() {
@@ -205,8 +223,9 @@ impl IteTconDevice {
 
         self.set_speed_mode();
 
+        on_progress(10, "load");
         info!("EicLoadImage: {img_path}");
-        let img_path_cstring = U16CString::from_str(img_path).unwrap();
+        let img_path_cstring = U16CString::from_str(img_path)?;
         let mut img_width: u32 = 0;
         let mut img_height: u32 = 0;
         let img_buf = unsafe {
@@ -221,46 +240,52 @@ impl IteTconDevice {
             )
         };
 
-        if !img_buf.is_null() {
-            unsafe {
-                info!("EicConvertToT1000Format");
-                //EicConvertToT1000Format(img_buf, img_width, img_height);
-
-                info!("EiTurn180");
-                //EiTurn180(img_buf, img_width, img_height);
-            }
-
-            info!("ITELoadImage");
-            let ret = unsafe {
-                ITELoadImage(
-                    img_buf,
-                    img_addr,
-                    0,
-                    0,
-                    self.screen_width,
-                    self.screen_height,
-                )
-            };
-            info!("ITELoadImage: {ret}");
-
-            // 保存新的可用图片序号
-            self.latest_image_idx = image_idx;
-
-            // let ret = unsafe {
-            //     ITEDisplayAreaAPI(
-            //         0,
-            //         0,
-            //         self.screen_width,
-            //         self.screen_height,
-            //         GI_MIPI_BROWSER, // TODO: ?? 确认此接口的模式指定
-            //         img_addr,
-            //         0,
-            //     )
-            // };
-            //info!("ITEDisplayAreaAPI: {ret}");
-            self.set_gybrid_mode();
-            unsafe { EicReleaseImage(img_buf) };
+        if img_buf.is_null() {
+            bail!("Cannot load cover image: {img_path}");
+        }
+
+        on_progress(40, "convert");
+        unsafe {
+            info!("EicConvertToT1000Format");
+            //EicConvertToT1000Format(img_buf, img_width, img_height);
+
+            info!("EiTurn180");
+            //EiTurn180(img_buf, img_width, img_height);
         }
+
+        on_progress(60, "upload");
+        info!("ITELoadImage");
+        let ret = unsafe {
+            ITELoadImage(
+                img_buf,
+                img_addr,
+                0,
+                0,
+                self.screen_width,
+                self.screen_height,
+            )
+        };
+        info!("ITELoadImage: {ret}");
+
+        // 保存新的可用图片序号
+        self.latest_image_idx = image_idx;
+
+        // let ret = unsafe {
+        //     ITEDisplayAreaAPI(
+        //         0,
+        //         0,
+        //         self.screen_width,
+        //         self.screen_height,
+        //         GI_MIPI_BROWSER, // TODO: ?? 确认此接口的模式指定
+        //         img_addr,
+        //         0,
+        //     )
+        // };
+        //info!("ITEDisplayAreaAPI: {ret}");
+        self.set_gybrid_mode();
+        unsafe { EicReleaseImage(img_buf) };
+        on_progress(100, "done");
+        Ok(())
     }
 
     /// 设置 Eink TP 区域
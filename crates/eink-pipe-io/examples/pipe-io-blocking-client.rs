@@ -1,10 +1,5 @@
-use std::time::Duration;
-
-use jsonrpc_lite::{JsonRpc, Params};
-use remoc::rch;
+use jsonrpc_lite::JsonRpc;
 use serde_json::json;
-use tokio::{net::windows::named_pipe::ClientOptions, time};
-use windows::Win32::Foundation::ERROR_PIPE_BUSY;
 
 const PIPE_NAME: &str = r"\\.\pipe\pipe-io-idiomatic-server";
 
@@ -12,7 +7,7 @@ fn main() {
     let mut client = eink_pipe_io::blocking::connect(PIPE_NAME).unwrap();
     {
         let _on_request_conn = client
-            .on_request(|_id, _req| JsonRpc::error(0, jsonrpc_lite::Error::internal_error()))
+            .on_request(|id, _req| JsonRpc::error(id, jsonrpc_lite::Error::internal_error()))
             .scoped();
     }
 
@@ -1,84 +1,64 @@
-use std::time::Duration;
-
+use eink_pipe_io::raw::Framing;
+use eink_pipe_io::transport;
 use jsonrpc_lite::JsonRpc;
-use remoc::rch;
 use serde_json::json;
-use tokio::{net::windows::named_pipe::ClientOptions, time};
-use windows::Win32::Foundation::ERROR_PIPE_BUSY;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
-const PIPE_NAME: &str = r"\\.\pipe\pipe-io-idiomatic-server";
+const PIPE_NAME: &str = r"\\.\pipe\lenovo\thinbook-eink-plus\eink-service";
 
-// User-defined data structures needs to implement Serialize
-// and Deserialize.
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
-struct IoMsg {
-    payload: JsonRpc,
-    // Most Remoc types like channels can be included in serializable
-    // data structures for transmission to remote endpoints.
-    reply_tx: rch::mpsc::Sender<JsonRpc>,
+// 与 .NET 等非 Rust 客户端相同，直接收发长度前缀分帧的 JSON-RPC 消息，
+// 服务端为 pipe-io-server 示例
+async fn write_frame<W: AsyncWrite + Unpin>(writer: &mut W, msg: &JsonRpc) {
+    let frame = serde_json::to_vec(msg).unwrap();
+    writer.write_u32_le(frame.len() as u32).await.unwrap();
+    writer.write_all(&frame).await.unwrap();
 }
 
-// This would be run on the client.
-// It sends a count request to the server and receives each number
-// as it is counted over a newly established MPSC channel.
-async fn run_client(mut tx: rch::base::Sender<IoMsg>) {
-    for i in 0..10 {
-        // By sending seq_tx over an existing remote channel, a new remote
-        // channel is automatically created and connected to the server.
-        // This all happens inside the existing TCP connection.
-        let (reply_tx, mut reply_rx) = rch::mpsc::channel(1);
-
-        tx.send(IoMsg {
-            payload: jsonrpc_lite::JsonRpc::request_with_params(
-                0,
-                "set_window_topmost",
-                json!({"name":"Jiang Lu", "mail": "jianglu@ensurebit.com"}),
-            ),
-            reply_tx,
-        })
-        .await
-        .unwrap();
-
-        let reply = reply_rx.recv().await.unwrap();
-
-        println!("reply : {reply:?}");
-    }
-
-    // Receive counted numbers over new channel.
-    // for i in 0..5 {
-    // }
-    // assert_eq!(seq_rx.recv().await.unwrap(), Some(0));
-    // assert_eq!(seq_rx.recv().await.unwrap(), Some(1));
-    // assert_eq!(seq_rx.recv().await.unwrap(), Some(2));
-    // assert_eq!(seq_rx.recv().await.unwrap(), Some(3));
-    // assert_eq!(seq_rx.recv().await.unwrap(), None);
+async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> JsonRpc {
+    let len = reader.read_u32_le().await.unwrap();
+    let mut frame = vec![0; len as usize];
+    reader.read_exact(&mut frame).await.unwrap();
+    serde_json::from_slice(&frame).unwrap()
 }
 
 #[tokio::main]
 async fn main() {
     // Establish named-pipe connection.
-    let pipe_client = loop {
-        match ClientOptions::new().open(PIPE_NAME) {
-            Ok(client) => break client,
-            Err(e) if e.raw_os_error() == Some(ERROR_PIPE_BUSY.0 as i32) => (),
-            Err(e) => return,
-        }
-
-        time::sleep(Duration::from_millis(50)).await;
+    let pipe_client = match transport::default_connector(PIPE_NAME).connect().await {
+        Ok(client) => client,
+        Err(_) => return,
     };
 
-    let (pipe_rx, pipe_tx) = tokio::io::split(pipe_client);
+    let (mut pipe_rx, mut pipe_tx) = tokio::io::split(pipe_client);
 
-    // Establish Remoc connection over TCP.
-    // The connection is always bidirectional, but we can just drop
-    // the unneeded receiver.
-    let (conn, tx, _rx): (_, _, rch::base::Receiver<JsonRpc>) =
-        remoc::Connect::io(remoc::Cfg::default(), pipe_rx, pipe_tx)
-            .await
-            .unwrap();
+    // 连接后先发送前导，选择分帧方式
+    pipe_tx
+        .write_all(&Framing::LengthPrefixed.preamble())
+        .await
+        .unwrap();
 
-    tokio::spawn(conn);
+    for i in 0..10 {
+        let request = JsonRpc::request_with_params(
+            i,
+            "set_window_topmost",
+            json!({"name":"Jiang Lu", "mail": "jianglu@ensurebit.com"}),
+        );
+        write_frame(&mut pipe_tx, &request).await;
 
-    // Run client.
-    run_client(tx).await;
+        // 服务端处理请求时会反向调用 client-method
+        loop {
+            let msg = read_frame(&mut pipe_rx).await;
+            match &msg {
+                JsonRpc::Request(_) => {
+                    let reply = JsonRpc::success(msg.get_id().unwrap(), &json!({}));
+                    write_frame(&mut pipe_tx, &reply).await;
+                }
+                JsonRpc::Notification(_) => println!("notification : {msg:?}"),
+                JsonRpc::Success(_) | JsonRpc::Error(_) => {
+                    println!("reply : {msg:?}");
+                    break;
+                }
+            }
+        }
+    }
 }
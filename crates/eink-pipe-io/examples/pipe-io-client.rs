@@ -1,10 +1,5 @@
-use std::time::Duration;
-
-use jsonrpc_lite::{JsonRpc, Params};
-use remoc::rch;
+use jsonrpc_lite::JsonRpc;
 use serde_json::json;
-use tokio::{net::windows::named_pipe::ClientOptions, time};
-use windows::Win32::Foundation::ERROR_PIPE_BUSY;
 
 const PIPE_NAME: &str = r"\\.\pipe\pipe-io-idiomatic-server";
 
@@ -14,8 +9,8 @@ async fn main() {
     {
         let _on_request_conn = client
             .on_request(|id, _req| {
-                println!("Call from server: id: {id}");
-                JsonRpc::error(0, jsonrpc_lite::Error::method_not_found())
+                println!("Call from server: id: {id:?}");
+                JsonRpc::error(id, jsonrpc_lite::Error::method_not_found())
             })
             .await;
         // .scoped();
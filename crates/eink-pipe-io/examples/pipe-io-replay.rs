@@ -0,0 +1,41 @@
+//! 回放记录文件并比较回复
+//!
+//! cargo run --example pipe-io-replay -- trace.jsonl \\.\pipe\lenovo\eink-service\tcon [ignored_method...]
+
+use eink_pipe_io::client::Client;
+use eink_pipe_io::recorder;
+use eink_pipe_io::replay::Replayer;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let mut args = std::env::args().skip(1);
+    let (trace, pipe_name) = match (args.next(), args.next()) {
+        (Some(trace), Some(pipe_name)) => (trace, pipe_name),
+        _ => {
+            eprintln!("usage: pipe-io-replay <trace.jsonl> <pipe_name> [ignored_method...]");
+            std::process::exit(2);
+        }
+    };
+
+    let records = recorder::read_records(&trace)?;
+    let mut replayer = Replayer::new(|| Client::new(&pipe_name));
+    for method in args {
+        replayer = replayer.ignore(&method);
+    }
+    let report = replayer.run(&records).await?;
+
+    for mismatch in &report.mismatches {
+        println!("{mismatch}");
+    }
+    println!(
+        "replayed: {}, unanswered: {}, mismatches: {}",
+        report.replayed,
+        report.unanswered,
+        report.mismatches.len()
+    );
+
+    if !report.is_clean() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
@@ -1,14 +1,10 @@
 use std::time::Duration;
 
+use eink_pipe_io::transport;
 use jsonrpc_lite::JsonRpc;
 use remoc::rch;
 use serde_json::json;
-use tokio::{
-    io::ReadHalf,
-    net::windows::named_pipe::{ClientOptions, NamedPipeServer, ServerOptions},
-    time,
-};
-use windows::Win32::Foundation::ERROR_PIPE_BUSY;
+use tokio::time;
 
 const PIPE_NAME: &str = r"\\.\pipe\pipe-io-idiomatic-server";
 
@@ -24,38 +20,20 @@ struct JsonRpcMsg {
 
 #[tokio::main]
 async fn main() {
-    // The first server needs to be constructed early so that clients can
-    // be correctly connected. Otherwise calling .wait will cause the client to
-    // error.
-    //
-    // Here we also make use of `first_pipe_instance`, which will ensure that
-    // there are no other servers up and running already.
-    let mut server = ServerOptions::new()
-        .first_pipe_instance(true)
-        .create(PIPE_NAME)
-        .unwrap();
+    let mut listener = transport::default_listener(PIPE_NAME);
 
     // Spawn the server loop.
     let server = tokio::spawn(async move {
         loop {
             // Wait for a client to connect.
-            let _connected = match server.connect().await {
-                Ok(res) => res,
+            let stream = match listener.accept().await {
+                Ok((stream, _credentials)) => stream,
                 Err(err) => panic!("err: {err}"),
             };
 
-            let pipe_server = server;
-
-            // Construct the next server to be connected before sending the one
-            // we already have of onto a task. This ensures that the server
-            // isn't closed (after it's done in the task) before a new one is
-            // available. Otherwise the client might error with
-            // `io::ErrorKind::NotFound`.
-            server = ServerOptions::new().create(PIPE_NAME).unwrap();
-
-            let client = tokio::spawn(async move {
+            tokio::spawn(async move {
                 /* use the connected client */
-                let (pipe_rx, pipe_tx) = tokio::io::split(pipe_server);
+                let (pipe_rx, pipe_tx) = tokio::io::split(stream);
 
                 // Establish Remoc connection over pipe connection.
                 // The connection is always bidirectional, but we can just drop
@@ -87,7 +65,7 @@ async fn main() {
 // as it is counted over the MPSC channel sender provided by the client.
 async fn run_server(
     conn_id: u128,
-    _tx: rch::base::Sender<JsonRpcMsg>,
+    tx: rch::base::Sender<JsonRpcMsg>,
     mut rx: rch::base::Receiver<JsonRpcMsg>,
 ) {
     println!("\n\n[{conn_id}] New connection !!!!");
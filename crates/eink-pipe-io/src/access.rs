@@ -0,0 +1,405 @@
+//
+// Copyright (C) Lenovo ThinkBook Gen4 Project.
+//
+// This program is protected under international and China copyright laws as
+// an unpublished work. This program is confidential and proprietary to the
+// copyright owners. Reproduction or disclosure, in whole or in part, or the
+// production of derivative works therefrom without the express permission of
+// the copyright owners is prohibited.
+//
+// All rights reserved.
+//
+
+//! 方法级访问控制
+//!
+//! 服务器建立连接时根据对端进程凭据解析对端身份（用户、进程路径、是否提权），
+//! 每次调用方法前使用 `AccessPolicy` 检查。被拒绝的请求回复 `ACCESS_DENIED` 错误，
+//! 被拒绝的通知直接丢弃，两者都会记录审计日志。`rpc.` 开头的保留方法不受策略限制。
+
+use std::path::{Path, PathBuf};
+
+use jsonrpc_lite::{Id, JsonRpc};
+use serde_json::json;
+
+use crate::registry::ConnectionInfo;
+use crate::transport::PeerCredentials;
+
+/// 访问被拒绝的错误码，错误数据为被拒绝的方法名
+pub const ACCESS_DENIED: i64 = -32011;
+
+/// 对端身份，无法获取的字段为 None
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PeerIdentity {
+    /// 对端进程的用户名
+    pub user: Option<String>,
+    /// 对端进程的可执行文件路径
+    pub process_path: Option<PathBuf>,
+    /// 对端进程是否提权运行（Windows 管理员令牌 / Unix root）
+    pub elevated: bool,
+}
+
+impl PeerIdentity {
+    /// 根据进程凭据解析对端身份
+    pub fn resolve(credentials: &PeerCredentials) -> Self {
+        platform::resolve(credentials)
+    }
+}
+
+/// 允许调用方法的对端
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Principal {
+    /// 任意对端
+    Any,
+    /// 指定用户名，Windows 下不区分大小写
+    User(String),
+    /// 指定 Unix 用户 id
+    Uid(u32),
+    /// 指定可执行文件路径
+    ProcessPath(PathBuf),
+    /// 可执行文件位于指定目录及其子目录
+    ProcessDir(PathBuf),
+    /// 提权运行的进程
+    Elevated,
+}
+
+impl Principal {
+    pub fn matches(&self, connection: &ConnectionInfo) -> bool {
+        let identity = &connection.identity;
+        match self {
+            Principal::Any => true,
+            Principal::User(user) => identity
+                .user
+                .as_deref()
+                .is_some_and(|name| same_name(name, user)),
+            Principal::Uid(uid) => connection.credentials.uid == Some(*uid),
+            Principal::ProcessPath(path) => identity
+                .process_path
+                .as_deref()
+                .is_some_and(|process| same_path(process, path)),
+            Principal::ProcessDir(dir) => identity
+                .process_path
+                .as_deref()
+                .and_then(Path::parent)
+                .is_some_and(|parent| {
+                    parent.ancestors().any(|ancestor| same_path(ancestor, dir))
+                }),
+            Principal::Elevated => identity.elevated,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Rule {
+    /// 方法名，`*` 匹配所有方法
+    method: String,
+    principals: Vec<Principal>,
+}
+
+/// 服务端点的访问策略
+///
+/// 方法可以出现在多条规则中，满足任意一条规则即允许调用；没有规则的方法默认允许，
+/// 调用 `deny_unlisted` 后默认拒绝
+#[derive(Clone, Debug, Default)]
+pub struct AccessPolicy {
+    rules: Vec<Rule>,
+    deny_unlisted: bool,
+}
+
+impl AccessPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 允许指定的对端调用方法，`*` 表示所有方法
+    pub fn allow<I: IntoIterator<Item = Principal>>(mut self, method: &str, principals: I) -> Self {
+        self.rules.push(Rule {
+            method: method.to_owned(),
+            principals: principals.into_iter().collect(),
+        });
+        self
+    }
+
+    /// 拒绝调用没有规则的方法
+    pub fn deny_unlisted(mut self) -> Self {
+        self.deny_unlisted = true;
+        self
+    }
+
+    /// 检查连接是否可以调用方法
+    pub fn check(&self, method: &str, connection: &ConnectionInfo) -> bool {
+        if method.starts_with("rpc.") {
+            return true;
+        }
+
+        let mut listed = false;
+        for rule in self
+            .rules
+            .iter()
+            .filter(|rule| rule.method == method || rule.method == "*")
+        {
+            listed = true;
+            if rule
+                .principals
+                .iter()
+                .any(|principal| principal.matches(connection))
+            {
+                return true;
+            }
+        }
+        !listed && !self.deny_unlisted
+    }
+}
+
+/// 记录审计日志
+pub(crate) fn audit_denied(server: &str, method: &str, connection: &ConnectionInfo) {
+    log::warn!(
+        "PipeIo::Audit: server '{server}' denied '{method}' from connection {}, pid: {:?}, uid: {:?}, user: {:?}, path: {:?}, elevated: {}",
+        connection.id,
+        connection.credentials.pid,
+        connection.credentials.uid,
+        connection.identity.user,
+        connection.identity.process_path,
+        connection.identity.elevated,
+    );
+}
+
+/// 访问被拒绝的回复
+pub(crate) fn denied_reply(id: Id, method: &str) -> JsonRpc {
+    JsonRpc::error(
+        id,
+        jsonrpc_lite::Error {
+            code: ACCESS_DENIED,
+            message: "Access denied".to_owned(),
+            data: Some(json!(method)),
+        },
+    )
+}
+
+fn same_name(a: &str, b: &str) -> bool {
+    if cfg!(windows) {
+        a.eq_ignore_ascii_case(b)
+    } else {
+        a == b
+    }
+}
+
+fn same_path(a: &Path, b: &Path) -> bool {
+    if cfg!(windows) {
+        same_name(&a.to_string_lossy(), &b.to_string_lossy())
+    } else {
+        a == b
+    }
+}
+
+#[cfg(unix)]
+mod platform {
+    use super::PeerIdentity;
+    use crate::transport::PeerCredentials;
+
+    pub fn resolve(credentials: &PeerCredentials) -> PeerIdentity {
+        let process_path = credentials
+            .pid
+            .and_then(|pid| std::fs::read_link(format!("/proc/{pid}/exe")).ok());
+        PeerIdentity {
+            user: credentials.uid.and_then(user_name),
+            process_path,
+            elevated: credentials.uid == Some(0),
+        }
+    }
+
+    /// 从 /etc/passwd 查找用户名
+    fn user_name(uid: u32) -> Option<String> {
+        let passwd = std::fs::read_to_string("/etc/passwd").ok()?;
+        passwd.lines().find_map(|line| {
+            let mut fields = line.split(':');
+            let name = fields.next()?;
+            let id = fields.nth(1)?.parse::<u32>().ok()?;
+            (id == uid).then(|| name.to_owned())
+        })
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::ffi::c_void;
+    use std::path::PathBuf;
+
+    use windows::core::{PCWSTR, PWSTR};
+    use windows::Win32::Foundation::{CloseHandle, HANDLE};
+    use windows::Win32::Security::{
+        GetTokenInformation, LookupAccountSidW, TokenElevation, TokenUser, SID_NAME_USE,
+        TOKEN_ELEVATION, TOKEN_QUERY, TOKEN_USER,
+    };
+    use windows::Win32::System::Threading::{
+        OpenProcess, OpenProcessToken, QueryFullProcessImageNameW, PROCESS_NAME_WIN32,
+        PROCESS_QUERY_LIMITED_INFORMATION,
+    };
+
+    use super::PeerIdentity;
+    use crate::transport::PeerCredentials;
+
+    pub fn resolve(credentials: &PeerCredentials) -> PeerIdentity {
+        let pid = match credentials.pid {
+            Some(pid) => pid,
+            None => return PeerIdentity::default(),
+        };
+
+        let process = match unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid) } {
+            Ok(process) => process,
+            Err(err) => {
+                log::warn!("PipeIo: cannot open peer process {pid}: {err}");
+                return PeerIdentity::default();
+            }
+        };
+
+        let mut identity = PeerIdentity {
+            process_path: process_path(process),
+            ..Default::default()
+        };
+
+        let mut token = HANDLE::default();
+        if unsafe { OpenProcessToken(process, TOKEN_QUERY, &mut token) }.as_bool() {
+            identity.user = token_user(token);
+            identity.elevated = token_elevated(token);
+            unsafe { CloseHandle(token) };
+        }
+
+        unsafe { CloseHandle(process) };
+        identity
+    }
+
+    fn process_path(process: HANDLE) -> Option<PathBuf> {
+        let mut buffer = vec![0u16; 1024];
+        let mut size = buffer.len() as u32;
+        let ok = unsafe {
+            QueryFullProcessImageNameW(
+                process,
+                PROCESS_NAME_WIN32,
+                PWSTR(buffer.as_mut_ptr()),
+                &mut size,
+            )
+        };
+        ok.as_bool()
+            .then(|| PathBuf::from(String::from_utf16_lossy(&buffer[..size as usize])))
+    }
+
+    fn token_elevated(token: HANDLE) -> bool {
+        let mut elevation = TOKEN_ELEVATION::default();
+        let mut size = 0u32;
+        let ok = unsafe {
+            GetTokenInformation(
+                token,
+                TokenElevation,
+                Some(&mut elevation as *mut _ as *mut c_void),
+                std::mem::size_of::<TOKEN_ELEVATION>() as u32,
+                &mut size,
+            )
+        };
+        ok.as_bool() && elevation.TokenIsElevated != 0
+    }
+
+    fn token_user(token: HANDLE) -> Option<String> {
+        // 第一次调用获取缓冲区大小
+        let mut size = 0u32;
+        unsafe { GetTokenInformation(token, TokenUser, None, 0, &mut size) };
+        if size == 0 {
+            return None;
+        }
+
+        let mut buffer = vec![0u8; size as usize];
+        let ok = unsafe {
+            GetTokenInformation(
+                token,
+                TokenUser,
+                Some(buffer.as_mut_ptr() as *mut c_void),
+                size,
+                &mut size,
+            )
+        };
+        if !ok.as_bool() {
+            return None;
+        }
+        let user = unsafe { &*(buffer.as_ptr() as *const TOKEN_USER) };
+
+        let mut name = vec![0u16; 256];
+        let mut name_len = name.len() as u32;
+        let mut domain = vec![0u16; 256];
+        let mut domain_len = domain.len() as u32;
+        let mut sid_type = SID_NAME_USE::default();
+        let ok = unsafe {
+            LookupAccountSidW(
+                PCWSTR::null(),
+                user.User.Sid,
+                PWSTR(name.as_mut_ptr()),
+                &mut name_len,
+                PWSTR(domain.as_mut_ptr()),
+                &mut domain_len,
+                &mut sid_type,
+            )
+        };
+        ok.as_bool()
+            .then(|| String::from_utf16_lossy(&name[..name_len as usize]))
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use jsonrpc_lite::JsonRpc;
+    use serde_json::json;
+
+    use super::{AccessPolicy, Principal, ACCESS_DENIED};
+    use crate::server::Server;
+    use crate::transport::unix::UnixSocketListener;
+
+    #[tokio::test]
+    async fn test_access_policy_with_peer_credentials() {
+        let path = std::env::temp_dir().join(format!("eink-pipe-io-{}.sock", uuid::Uuid::new_v4()));
+        let path = path.to_str().unwrap().to_owned();
+
+        let exe = std::env::current_exe().unwrap();
+        let policy = AccessPolicy::new()
+            .allow("own_process", [Principal::ProcessPath(exe)])
+            .allow("other_user", [Principal::Uid(u32::MAX)]);
+
+        let listener = UnixSocketListener::bind(&path).unwrap();
+        let mut server = Server::with_listener(&path, listener);
+        server.set_access_policy(policy);
+        server.on_connection(|socket, _| {
+            socket
+                .lock()
+                .on_request(|_, id, _| JsonRpc::success(id, &json!(true)));
+            0
+        });
+        let registry = server.registry();
+        tokio::spawn(async move { server.listen().await });
+
+        let mut client = crate::client::connect(&path).await.unwrap();
+
+        // SO_PEERCRED 获取到的是当前进程
+        let connection = registry.connections().pop().unwrap();
+        assert_eq!(connection.pid(), Some(std::process::id()));
+        assert!(connection.credentials.uid.is_some());
+
+        let reply = client
+            .call_with_params("own_process", json!({}))
+            .await
+            .unwrap();
+        assert_eq!(reply.get_result(), Some(&json!(true)));
+
+        let reply = client
+            .call_with_params("unlisted", json!({}))
+            .await
+            .unwrap();
+        assert_eq!(reply.get_result(), Some(&json!(true)));
+
+        let reply = client
+            .call_with_params("other_user", json!({}))
+            .await
+            .unwrap();
+        let error = reply.get_error().unwrap();
+        assert_eq!(error.code, ACCESS_DENIED);
+        assert_eq!(error.data, Some(json!("other_user")));
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,204 @@
+//
+// Copyright (C) Lenovo ThinkBook Gen4 Project.
+//
+// This program is protected under international and China copyright laws as
+// an unpublished work. This program is confidential and proprietary to the
+// copyright owners. Reproduction or disclosure, in whole or in part, or the
+// production of derivative works therefrom without the express permission of
+// the copyright owners is prohibited.
+//
+// All rights reserved.
+//
+
+//! JSON-RPC 2.0 批量请求
+//!
+//! 批量请求通过保留方法 `rpc.batch` 发送，参数为 `{"requests": [...], "atomic": bool}`，
+//! 也可以直接使用 JSON-RPC 批量数组作为参数（非原子执行）。回复为按请求顺序排列的
+//! 回复数组，通知不产生回复。原子执行的批量请求在执行期间不会处理其它连接的请求。
+//! 原始链路上的 JSON-RPC 批量数组同样按 `rpc.batch` 处理，见 `raw`。
+
+use jsonrpc_lite::{JsonRpc, Params};
+use serde_json::{json, Value};
+
+/// 批量请求
+pub const METHOD_BATCH: &str = "rpc.batch";
+
+/// 批量请求构造器
+#[derive(Clone, Debug, Default)]
+pub struct Batch {
+    items: Vec<JsonRpc>,
+    atomic: bool,
+}
+
+impl Batch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 原子执行，执行期间服务端不会处理其它连接的请求
+    pub fn atomic(mut self) -> Self {
+        self.atomic = true;
+        self
+    }
+
+    /// 添加请求，回复按添加顺序返回
+    pub fn call<P: Into<Params>>(mut self, method: &str, params: P) -> Self {
+        let id = self.items.len() as i64;
+        self.items
+            .push(JsonRpc::request_with_params(id, method, params));
+        self
+    }
+
+    /// 添加通知，通知不产生回复
+    pub fn notify<P: Into<Params>>(mut self, method: &str, params: P) -> Self {
+        self.items
+            .push(JsonRpc::notification_with_params(method, params));
+        self
+    }
+
+    /// 批量请求中的请求与通知数量
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// 期望的回复数量
+    pub(crate) fn reply_count(&self) -> usize {
+        self.items
+            .iter()
+            .filter(|item| matches!(item, JsonRpc::Request(_)))
+            .count()
+    }
+
+    pub(crate) fn to_params(&self) -> Value {
+        json!({
+            "requests": self.items,
+            "atomic": self.atomic,
+        })
+    }
+}
+
+/// 解析 `rpc.batch` 请求，非批量请求返回 None
+pub(crate) fn parse_batch(
+    req: &JsonRpc,
+) -> Option<Result<(Vec<JsonRpc>, bool), jsonrpc_lite::Error>> {
+    if req.get_method() != Some(METHOD_BATCH) {
+        return None;
+    }
+
+    let (requests, atomic) = match req.get_params() {
+        Some(Params::Map(mut map)) => {
+            let atomic = map
+                .get("atomic")
+                .and_then(|atomic| atomic.as_bool())
+                .unwrap_or(false);
+            (map.remove("requests"), atomic)
+        }
+        Some(Params::Array(requests)) => (Some(Value::Array(requests)), false),
+        _ => (None, false),
+    };
+
+    let items: Vec<JsonRpc> = match requests.map(serde_json::from_value) {
+        Some(Ok(items)) => items,
+        _ => return Some(Err(jsonrpc_lite::Error::invalid_params())),
+    };
+
+    // 空的批量请求是无效请求
+    if items.is_empty() {
+        return Some(Err(jsonrpc_lite::Error::invalid_request()));
+    }
+
+    Some(Ok((items, atomic)))
+}
+
+/// 解析批量请求的回复
+pub(crate) fn parse_replies(reply: &JsonRpc) -> anyhow::Result<Vec<JsonRpc>> {
+    match reply.get_result() {
+        Some(result) => Ok(serde_json::from_value(result.clone())?),
+        None => match reply.get_error() {
+            Some(error) => anyhow::bail!("Batch failed: {} {}", error.code, error.message),
+            None => anyhow::bail!("Invalid batch reply: {reply:?}"),
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use jsonrpc_lite::JsonRpc;
+    use serde_json::json;
+
+    use super::Batch;
+    use crate::client::Client;
+    use crate::server::Server;
+    use crate::transport::memory;
+
+    #[tokio::test]
+    async fn test_atomic_batch_is_ordered_and_exclusive() {
+        let (listener, connector) = memory::channel();
+
+        // 原子批量执行期间，其它连接的请求不应被处理
+        let in_batch = Arc::new(AtomicBool::new(false));
+        let interleaved = Arc::new(AtomicBool::new(false));
+
+        let mut server = Server::with_listener("memory", listener);
+        {
+            let in_batch = in_batch.clone();
+            let interleaved = interleaved.clone();
+            server.on_connection(move |socket, _| {
+                let in_batch = in_batch.clone();
+                let interleaved = interleaved.clone();
+                socket.lock().on_request(move |_, id, req| {
+                    match req.get_method() {
+                        Some("begin") => in_batch.store(true, Ordering::SeqCst),
+                        Some("end") => in_batch.store(false, Ordering::SeqCst),
+                        Some("other") if in_batch.load(Ordering::SeqCst) => {
+                            interleaved.store(true, Ordering::SeqCst)
+                        }
+                        _ => std::thread::sleep(Duration::from_millis(20)),
+                    }
+                    JsonRpc::success(id, &json!(req.get_method()))
+                });
+                0
+            });
+        }
+        tokio::spawn(async move { server.listen().await });
+
+        let mut client = Client::with_connector(connector.clone());
+        client.connect().await.unwrap();
+        let mut other = Client::with_connector(connector);
+        other.connect().await.unwrap();
+
+        let other_task = tokio::spawn(async move {
+            for _ in 0..10 {
+                other.call_with_params("other", json!({})).await.unwrap();
+            }
+        });
+
+        let batch = Batch::new()
+            .atomic()
+            .call("begin", json!({}))
+            .call("slow", json!({}))
+            .notify("ignored", json!({}))
+            .call("slow", json!({}))
+            .call("end", json!({}));
+        let replies = client.call_batch(batch).await.unwrap();
+        other_task.await.unwrap();
+
+        let methods: Vec<_> = replies
+            .iter()
+            .map(|reply| reply.get_result().cloned().unwrap())
+            .collect();
+        assert_eq!(
+            methods,
+            vec![json!("begin"), json!("slow"), json!("slow"), json!("end")]
+        );
+        assert!(!interleaved.load(Ordering::SeqCst));
+    }
+}
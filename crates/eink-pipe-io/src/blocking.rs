@@ -1,14 +1,154 @@
-use jsonrpc_lite::{JsonRpc, Params};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use anyhow::bail;
+use jsonrpc_lite::{Id, JsonRpc, Params};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
 use signals2::Connection;
-use tokio::runtime::Runtime;
+use tokio::runtime::{Handle, Runtime};
 
-use crate::client::Client;
+use crate::batch::Batch;
+use crate::codec::Codec;
+use crate::deadline::CallOptions;
+use crate::handshake::{ProtocolVersion, ServerInfo};
+use crate::metrics::EndpointStats;
+use crate::raw::Framing;
+use crate::retry::{ConnectionState, ReconnectPolicy, RetryPolicy};
+use crate::server::{Server, ShutdownHandle};
+use crate::service::CallResult;
+use crate::stream::CallStream;
+use crate::transport::Listener;
 
-struct BlockingServer {}
+/// 阻塞式服务器，拥有独立的 tokio 运行时，使用同步回调处理请求
+///
+/// ```ignore
+/// let mut server = BlockingServer::new(PIPE_NAME)?;
+/// tcon::serve(server.server(), Arc::new(service));
+/// let handle = server.spawn()?;
+/// // ...
+/// handle.stop();
+/// handle.join()?;
+/// ```
+pub struct BlockingServer {
+    inner: Server,
+    rt: Runtime,
+}
 
 impl BlockingServer {
-    pub fn new() -> Self {
-        Self {}
+    /// 使用当前平台默认传输创建服务器
+    pub fn new(pipe_name: &str) -> anyhow::Result<Self> {
+        Self::with_server(Server::new(pipe_name))
+    }
+
+    /// 使用自定义传输创建服务器
+    pub fn with_listener<L: Listener>(name: &str, listener: L) -> anyhow::Result<Self> {
+        Self::with_server(Server::with_listener(name, listener))
+    }
+
+    fn with_server(inner: Server) -> anyhow::Result<Self> {
+        let rt = build_runtime(format!("PipeIo::BlockingServer '{}'", inner.name()))?;
+        Ok(Self { inner, rt })
+    }
+
+    /// 内部的异步服务器，用于设置访问策略、构建信息、注册服务等
+    pub fn server(&mut self) -> &mut Server {
+        &mut self.inner
+    }
+
+    /// 设置请求回调，每个连接的请求在 blocking 线程中回调
+    pub fn on_request<Callback>(&mut self, cb: Callback) -> Connection
+    where
+        Callback: Fn(Id, JsonRpc) -> JsonRpc + Send + Sync + 'static,
+    {
+        let cb = Arc::new(cb);
+        self.inner.on_connection(move |socket, _| {
+            let cb = cb.clone();
+            socket.lock().on_request(move |_, id, req| cb(id, req));
+            0
+        })
+    }
+
+    /// 设置通知回调
+    pub fn on_notification<Callback>(&mut self, cb: Callback) -> Connection
+    where
+        Callback: Fn(JsonRpc) + Send + Sync + 'static,
+    {
+        let cb = Arc::new(cb);
+        self.inner.on_connection(move |socket, _| {
+            let cb = cb.clone();
+            socket.lock().on_notification(move |_, req| cb(req));
+            0
+        })
+    }
+
+    /// 在后台线程中接受连接并处理请求，返回停止与等待句柄
+    pub fn spawn(self) -> anyhow::Result<ServerHandle> {
+        let Self { mut inner, rt } = self;
+        let shutdown = inner.shutdown_handle();
+        let rt = Arc::new(rt);
+        let runtime = rt.clone();
+        let name = inner.name().to_owned();
+
+        let thread = std::thread::Builder::new()
+            .name("pipe-io-server".to_owned())
+            .spawn(move || {
+                log::info!("PipeIo::BlockingServer: '{name}' start listen");
+                let result = rt.block_on(inner.listen());
+                if let Err(err) = &result {
+                    log::error!("PipeIo::BlockingServer: '{name}' listen failed: {err}");
+                }
+                log::info!("PipeIo::BlockingServer: '{name}' stop listen");
+                result
+            })?;
+
+        Ok(ServerHandle {
+            shutdown,
+            runtime,
+            thread,
+        })
+    }
+}
+
+/// 后台运行的服务器句柄
+pub struct ServerHandle {
+    shutdown: ShutdownHandle,
+    /// 与后台线程共享，后台线程退出后运行时仍然可用
+    runtime: Arc<Runtime>,
+    thread: JoinHandle<anyhow::Result<()>>,
+}
+
+impl ServerHandle {
+    /// 停止接受新连接，正在处理的请求完成后后台线程退出，不等待
+    pub fn stop(&self) {
+        self.shutdown.shutdown();
+    }
+
+    /// 等待后台线程退出，返回 `listen` 的结果
+    pub fn join(self) -> anyhow::Result<()> {
+        match self.thread.join() {
+            Ok(result) => result,
+            Err(_) => bail!("Server thread panicked"),
+        }
+    }
+
+    /// 后台线程是否已经退出
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    /// 关闭句柄，可以克隆到其它线程中使用
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    /// 服务器的运行时，服务可以在其中执行定时器等异步任务
+    ///
+    /// 运行时在句柄释放前一直有效，即使服务器已经停止
+    pub fn runtime(&self) -> &Handle {
+        self.runtime.handle()
     }
 }
 
@@ -20,11 +160,68 @@ pub struct BlockingClient {
 impl BlockingClient {
     pub fn on_request<Callback>(&mut self, cb: Callback) -> Connection
     where
-        Callback: Fn(i32, JsonRpc) -> JsonRpc + Send + Sync + 'static,
+        Callback: Fn(Id, JsonRpc) -> JsonRpc + Send + Sync + 'static,
     {
         self.rt.block_on(self.inner.on_request(cb))
     }
 
+    /// 订阅服务端主题，每条通知在 blocking 线程中回调
+    pub fn on_topic<Callback>(&mut self, topic: &str, cb: Callback) -> anyhow::Result<()>
+    where
+        Callback: Fn(JsonRpc) + Send + Sync + 'static,
+    {
+        self.rt.block_on(self.inner.on_topic(topic, cb))
+    }
+
+    /// 设置断线重连策略，None 表示链路断开后不再自动重连
+    pub fn set_reconnect_policy(&mut self, policy: Option<ReconnectPolicy>) {
+        self.inner.set_reconnect_policy(policy)
+    }
+
+    /// 设置请求重试策略
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.inner.set_retry_policy(policy)
+    }
+
+    /// 当前连接状态
+    pub fn state(&self) -> ConnectionState {
+        self.inner.state()
+    }
+
+    /// 设置 `call_with_params` 的默认超时时间，None 表示一直等待
+    pub fn set_default_timeout(&mut self, timeout: Option<Duration>) {
+        self.inner.set_default_timeout(timeout)
+    }
+
+    /// 设置类型化调用的编码偏好，默认只使用 JSON
+    pub fn set_codecs(&mut self, codecs: Vec<Codec>) {
+        self.inner.set_codecs(codecs)
+    }
+
+    /// 与服务端协商的编码
+    pub fn codec(&self) -> Codec {
+        self.inner.codec()
+    }
+
+    /// 使用原始 JSON-RPC 分帧代替 remoc 链路，下次连接时生效
+    pub fn set_raw_framing(&mut self, framing: Option<Framing>) {
+        self.inner.set_raw_framing(framing)
+    }
+
+    /// 设置服务端推送的请求与通知的最大长度，下次连接时生效
+    pub fn set_max_message_size(&mut self, size: Option<usize>) {
+        self.inner.set_max_message_size(size)
+    }
+
+    /// 连接状态变化时在 blocking 线程中回调
+    pub fn on_state_changed<Callback>(&self, cb: Callback)
+    where
+        Callback: Fn(ConnectionState) + Send + Sync + 'static,
+    {
+        let _guard = self.rt.enter();
+        self.inner.on_state_changed(cb)
+    }
+
     pub fn call_with_params<P: Into<Params>>(
         &mut self,
         method: &str,
@@ -33,6 +230,109 @@ impl BlockingClient {
         self.rt
             .block_on(self.inner.call_with_params(method, params))
     }
+
+    /// 使用协商的编码调用远端方法
+    pub fn call_encoded<P, R>(&mut self, method: &str, params: &P) -> CallResult<R>
+    where
+        P: Serialize,
+        R: DeserializeOwned,
+    {
+        self.rt.block_on(self.inner.call_encoded(method, params))
+    }
+
+    /// 调用远端方法，超时后返回 `CallError::Timeout`
+    pub fn call_with_timeout<P: Into<Params>>(
+        &mut self,
+        method: &str,
+        params: P,
+        timeout: Duration,
+    ) -> anyhow::Result<JsonRpc> {
+        self.rt
+            .block_on(self.inner.call_with_timeout(method, params, timeout))
+    }
+
+    /// 使用指定的超时与取消选项调用远端方法，可在其它线程中取消
+    pub fn call_with_options<P: Into<Params>>(
+        &mut self,
+        method: &str,
+        params: P,
+        options: CallOptions,
+    ) -> anyhow::Result<JsonRpc> {
+        self.rt
+            .block_on(self.inner.call_with_options(method, params, options))
+    }
+
+    /// 流式调用远端方法，不设置超时
+    pub fn call_stream<P: Into<Params>>(
+        &mut self,
+        method: &str,
+        params: P,
+    ) -> anyhow::Result<BlockingCallStream> {
+        self.call_stream_with_options(method, params, CallOptions::new())
+    }
+
+    /// 使用指定的超时与取消选项流式调用远端方法
+    pub fn call_stream_with_options<P: Into<Params>>(
+        &mut self,
+        method: &str,
+        params: P,
+        options: CallOptions,
+    ) -> anyhow::Result<BlockingCallStream> {
+        let inner = self
+            .rt
+            .block_on(self.inner.call_stream_with_options(method, params, options))?;
+        Ok(BlockingCallStream {
+            inner,
+            handle: self.rt.handle().clone(),
+        })
+    }
+
+    pub fn server_info(&self) -> Option<&ServerInfo> {
+        self.inner.server_info()
+    }
+
+    pub fn protocol_version(&self) -> ProtocolVersion {
+        self.inner.protocol_version()
+    }
+
+    pub fn discover(&mut self) -> anyhow::Result<ServerInfo> {
+        self.rt.block_on(self.inner.discover())
+    }
+
+    pub fn stats(&mut self) -> anyhow::Result<EndpointStats> {
+        self.rt.block_on(self.inner.stats())
+    }
+
+    pub fn call_batch(&mut self, batch: Batch) -> anyhow::Result<Vec<JsonRpc>> {
+        self.rt.block_on(self.inner.call_batch(batch))
+    }
+}
+
+/// 阻塞式流式调用
+pub struct BlockingCallStream {
+    inner: CallStream,
+    handle: Handle,
+}
+
+impl Iterator for BlockingCallStream {
+    type Item = Value;
+
+    /// 下一条进度，所有进度接收完成、超时或取消后返回 None
+    fn next(&mut self) -> Option<Value> {
+        self.handle.block_on(self.inner.next())
+    }
+}
+
+impl BlockingCallStream {
+    /// 丢弃剩余的进度，等待最终回复
+    pub fn finish(self) -> anyhow::Result<JsonRpc> {
+        self.handle.block_on(self.inner.finish())
+    }
+
+    /// 取消调用
+    pub fn cancel(self) {
+        self.inner.cancel()
+    }
 }
 
 // /// Establish a connection with the Redis server located at `addr`.
@@ -55,24 +355,60 @@ impl BlockingClient {
 // /// }
 // /// ```
 pub fn connect(pipe_name: &str) -> anyhow::Result<BlockingClient> {
-    let rt = tokio::runtime::Builder::new_multi_thread()
+    let rt = build_runtime("PipeIo::BlockingClient".to_owned())?;
+
+    let inner = rt.block_on(crate::client::connect(pipe_name))?;
+
+    Ok(BlockingClient { inner, rt })
+}
+
+/// 创建阻塞接口使用的多线程运行时
+fn build_runtime(name: String) -> std::io::Result<Runtime> {
+    let name = Arc::new(name);
+    let name_cloned = name.clone();
+    tokio::runtime::Builder::new_multi_thread()
         .worker_threads(3)
-        .on_thread_start(|| {
-            log::info!(
-                "PipeIo::BlockingClient thread [{:?}] started",
-                std::thread::current().id()
-            );
+        .on_thread_start(move || {
+            log::info!("{name} thread [{:?}] started", std::thread::current().id());
         })
-        .on_thread_stop(|| {
+        .on_thread_stop(move || {
             log::info!(
-                "PipeIo::BlockingClient thread [{:?}] stopping",
+                "{name_cloned} thread [{:?}] stopping",
                 std::thread::current().id()
             );
         })
         .enable_all()
-        .build()?;
+        .build()
+}
 
-    let inner = rt.block_on(crate::client::connect(pipe_name))?;
+#[cfg(test)]
+mod test {
+    use jsonrpc_lite::JsonRpc;
+    use serde_json::json;
 
-    Ok(BlockingClient { inner, rt })
+    use super::BlockingServer;
+    use crate::client::Client;
+    use crate::transport::memory;
+
+    #[test]
+    fn test_blocking_server_spawn_and_join() {
+        let (listener, connector) = memory::channel();
+
+        let mut server = BlockingServer::with_listener("memory", listener).unwrap();
+        server.on_request(|id, req| JsonRpc::success(id, &json!(req.get_method())));
+        let handle = server.spawn().unwrap();
+
+        // 客户端使用独立的运行时
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            // 保留连接器，Listener 在服务器停止前不会被关闭
+            let mut client = Client::with_connector(connector.clone());
+            client.connect().await.unwrap();
+            let reply = client.call_with_params("ping", json!({})).await.unwrap();
+            assert_eq!(reply.get_result(), Some(&json!("ping")));
+        });
+
+        handle.stop();
+        handle.join().unwrap();
+    }
 }
@@ -1,112 +1,594 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
 
-use anyhow::bail;
-use jsonrpc_lite::{JsonRpc, Params};
+use anyhow::{anyhow, bail};
+use jsonrpc_lite::{Id, JsonRpc, Params};
 use remoc::rch;
-use signals2::connect::ConnectionImpl;
-use signals2::{Connect2, Connection, Emit2, Signal};
-use tokio::net::windows::named_pipe::ClientOptions;
-use tokio::sync::Mutex;
-use tokio::time;
-use windows::Win32::Foundation::ERROR_PIPE_BUSY;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::json;
+use signals2::{Connect1, Connect2, Connection, Emit1, Emit2, Signal};
+use tokio::sync::{watch, Mutex};
+use tokio::time::{self, Duration, Instant};
 
-use crate::msg::IpcMsg;
+use crate::batch::{self, Batch, METHOD_BATCH};
+use crate::codec::{self, Codec, Packed};
+use crate::deadline::{self, CallError, CallOptions, CancellationToken, DEFAULT_CALL_TIMEOUT};
+use crate::handshake::{
+    self, HandshakeError, HandshakeParams, ProtocolVersion, ServerInfo, HANDSHAKE_TIMEOUT,
+    METHOD_DISCOVER, METHOD_HANDSHAKE,
+};
+use crate::metrics::{EndpointStats, METHOD_STATS};
+use crate::msg::{Inbound, IpcMsg};
+use crate::peer::Peer;
+use crate::pubsub::{Subscription, Subscriptions, METHOD_SUBSCRIBE, METHOD_UNSUBSCRIBE};
+use crate::raw::{self, Framing};
+use crate::retry::{ConnectionState, ReconnectPolicy, RetryPolicy};
+use crate::server::METHOD_SHUTDOWN;
+use crate::service::{self, CallResult};
+use crate::stream::CallStream;
+use crate::transport::{self, Connector};
 
 pub struct ClientHandlers {
-    pub on_request: Signal<(i32, JsonRpc), JsonRpc>,
+    pub on_request: Signal<(Id, JsonRpc), JsonRpc>,
+    pub on_notification: Signal<(JsonRpc,)>,
 }
 
 pub struct Client {
-    pipe_name: String,
+    connector: Arc<dyn Connector>,
     handlers: Arc<Mutex<ClientHandlers>>,
-    tx: Option<rch::base::Sender<IpcMsg>>,
+    subscriptions: Subscriptions,
+    tx: Option<Peer>,
+    /// 当前链路是否存活，每次连接重新创建
+    alive: Arc<AtomicBool>,
+    /// 连接代数，用于忽略旧链路的状态变化
+    generation: Arc<AtomicU64>,
+    state_tx: Arc<watch::Sender<ConnectionState>>,
+    state_rx: watch::Receiver<ConnectionState>,
+    reconnect_policy: Option<ReconnectPolicy>,
+    retry_policy: RetryPolicy,
+    default_timeout: Option<Duration>,
+    /// 握手得到的服务端信息，旧版本服务端为 None
+    server_info: Option<ServerInfo>,
+    /// 按偏好排列的参数与返回值编码
+    codecs: Vec<Codec>,
+    /// 原始 JSON-RPC 分帧，None 时使用 remoc 链路
+    raw_framing: Option<Framing>,
+    /// 服务端推送消息的最大长度，None 时使用 remoc 的默认限制
+    max_message_size: Option<usize>,
 }
 
 impl Client {
+    /// 使用当前平台默认传输创建客户端（Windows 命名管道 / Unix domain socket）
     pub fn new(pipe_name: &str) -> Self {
+        Self::with_boxed_connector(transport::default_connector(pipe_name))
+    }
+
+    /// 使用自定义传输创建客户端
+    pub fn with_connector<C: Connector>(connector: C) -> Self {
+        Self::with_boxed_connector(Box::new(connector))
+    }
+
+    fn with_boxed_connector(connector: Box<dyn Connector>) -> Self {
+        let (state_tx, state_rx) = watch::channel(ConnectionState::Disconnected);
         Self {
-            pipe_name: pipe_name.to_string(),
+            connector: Arc::from(connector),
             handlers: Arc::new(Mutex::new(ClientHandlers {
                 on_request: Signal::new(),
+                on_notification: Signal::new(),
             })),
+            subscriptions: Subscriptions::default(),
             tx: None,
+            alive: Default::default(),
+            generation: Default::default(),
+            state_tx: Arc::new(state_tx),
+            state_rx,
+            reconnect_policy: Some(ReconnectPolicy::default()),
+            retry_policy: RetryPolicy::default(),
+            default_timeout: Some(DEFAULT_CALL_TIMEOUT),
+            server_info: None,
+            codecs: vec![Codec::Json],
+            raw_framing: None,
+            max_message_size: None,
+        }
+    }
+
+    /// 设置 `call_with_params` 的默认超时时间，None 表示一直等待
+    pub fn set_default_timeout(&mut self, timeout: Option<Duration>) {
+        self.default_timeout = timeout;
+    }
+
+    fn default_call_options(&self) -> CallOptions {
+        match self.default_timeout {
+            Some(timeout) => CallOptions::new().timeout(timeout),
+            None => CallOptions::new(),
+        }
+    }
+
+    /// 设置断线重连策略，None 表示链路断开后不再自动重连
+    pub fn set_reconnect_policy(&mut self, policy: Option<ReconnectPolicy>) {
+        self.reconnect_policy = policy;
+    }
+
+    /// 设置请求重试策略
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retry_policy = policy;
+    }
+
+    /// 当前连接状态
+    pub fn state(&self) -> ConnectionState {
+        *self.state_rx.borrow()
+    }
+
+    /// 监听连接状态变化
+    pub fn watch_state(&self) -> watch::Receiver<ConnectionState> {
+        self.state_rx.clone()
+    }
+
+    /// 链路是否可用
+    pub fn is_connected(&self) -> bool {
+        self.tx.is_some() && self.alive.load(Ordering::SeqCst)
+    }
+
+    /// 当前链路的对端句柄，可以在多个任务中并发调用
+    ///
+    /// 句柄只在当前链路上有效，链路断开后调用失败，不会自动重连或重试
+    pub fn peer(&self) -> Option<Peer> {
+        match self.is_connected() {
+            true => self.tx.clone(),
+            false => None,
         }
     }
 
+    fn set_state(&self, state: ConnectionState) {
+        let _ = self.state_tx.send(state);
+    }
+
     /// 设置请求回调，使用 signals 接口
     pub async fn on_request<Callback>(&mut self, cb: Callback) -> Connection
     where
-        Callback: Fn(i32, JsonRpc) -> JsonRpc + Send + Sync + 'static,
+        Callback: Fn(Id, JsonRpc) -> JsonRpc + Send + Sync + 'static,
     {
         self.handlers.lock().await.on_request.connect(cb)
     }
 
+    /// 设置通知回调，所有收到的通知（包括已订阅主题的通知）都会触发
+    pub async fn on_notification<Callback>(&mut self, cb: Callback) -> Connection
+    where
+        Callback: Fn(JsonRpc) + Send + Sync + 'static,
+    {
+        self.handlers.lock().await.on_notification.connect(cb)
+    }
+
+    /// 订阅服务端主题，通过返回的 `Subscription` 异步接收通知
+    pub async fn subscribe(&mut self, topic: &str) -> anyhow::Result<Subscription> {
+        // 先注册本地订阅，避免错过订阅成功后立即推送的通知
+        let subscription = self.subscriptions.add(topic);
+
+        match self
+            .call_with_params(METHOD_SUBSCRIBE, json!({ "topic": topic }))
+            .await
+        {
+            Ok(JsonRpc::Success(_)) => Ok(subscription),
+            Ok(reply) => {
+                self.subscriptions.remove(&subscription);
+                bail!("Cannot subscribe topic '{topic}': {:?}", reply.get_error())
+            }
+            Err(err) => {
+                self.subscriptions.remove(&subscription);
+                Err(err)
+            }
+        }
+    }
+
+    /// 订阅服务端主题，每条通知在 blocking 线程中回调
+    pub async fn on_topic<Callback>(&mut self, topic: &str, cb: Callback) -> anyhow::Result<()>
+    where
+        Callback: Fn(JsonRpc) + Send + Sync + 'static,
+    {
+        let mut subscription = self.subscribe(topic).await?;
+        let cb = Arc::new(cb);
+
+        tokio::spawn(async move {
+            while let Some(notification) = subscription.recv().await {
+                let cb = cb.clone();
+                let _ = tokio::task::spawn_blocking(move || cb(notification)).await;
+            }
+        });
+
+        Ok(())
+    }
+
+    /// 连接状态变化时在 blocking 线程中回调
+    pub fn on_state_changed<Callback>(&self, cb: Callback)
+    where
+        Callback: Fn(ConnectionState) + Send + Sync + 'static,
+    {
+        let mut state_rx = self.watch_state();
+        let cb = Arc::new(cb);
+
+        tokio::spawn(async move {
+            while state_rx.changed().await.is_ok() {
+                let state = *state_rx.borrow();
+                let cb = cb.clone();
+                let _ = tokio::task::spawn_blocking(move || cb(state)).await;
+            }
+        });
+    }
+
+    /// 取消订阅服务端主题，该主题的所有本地订阅都会结束
+    pub async fn unsubscribe(&mut self, topic: &str) -> anyhow::Result<()> {
+        self.subscriptions.remove_topic(topic);
+
+        match self
+            .call_with_params(METHOD_UNSUBSCRIBE, json!({ "topic": topic }))
+            .await?
+        {
+            JsonRpc::Success(_) => Ok(()),
+            reply => bail!(
+                "Cannot unsubscribe topic '{topic}': {:?}",
+                reply.get_error()
+            ),
+        }
+    }
+
+    /// 调用远端方法，使用客户端的默认超时时间
+    ///
+    /// 链路断开时按重连策略重新连接，幂等方法按重试策略重新发送
     pub async fn call_with_params<P: Into<Params>>(
         &mut self,
         method: &str,
         params: P,
     ) -> anyhow::Result<JsonRpc> {
-        let id = uuid::Uuid::new_v4().to_string();
-        let (reply_tx, mut reply_rx) = rch::mpsc::channel(1);
-        match self
-            .tx
-            .as_mut()
-            .expect("Cannot find tx link")
-            .send(IpcMsg {
-                payload: JsonRpc::request_with_params(id, method, params),
-                reply_tx: Some(reply_tx),
-            })
+        let options = self.default_call_options();
+        self.call_with_options(method, params, options).await
+    }
+
+    /// 握手得到的服务端信息，旧版本服务端不支持握手时为 None
+    pub fn server_info(&self) -> Option<&ServerInfo> {
+        self.server_info.as_ref()
+    }
+
+    /// 与服务端协商的协议版本
+    pub fn protocol_version(&self) -> ProtocolVersion {
+        match &self.server_info {
+            Some(info) => ProtocolVersion::CURRENT.min(info.protocol),
+            None => ProtocolVersion::LEGACY,
+        }
+    }
+
+    /// 设置类型化调用的编码偏好，握手时选择服务端支持的第一个编码，默认只使用 JSON
+    pub fn set_codecs(&mut self, codecs: Vec<Codec>) {
+        self.codecs = codecs;
+    }
+
+    /// 与服务端协商的编码，原始链路只使用 JSON
+    pub fn codec(&self) -> Codec {
+        match &self.server_info {
+            Some(info) if self.raw_framing.is_none() => {
+                Codec::negotiate(&self.codecs, &info.codecs)
+            }
+            _ => Codec::Json,
+        }
+    }
+
+    /// 使用原始 JSON-RPC 分帧代替 remoc 链路，下次连接时生效
+    ///
+    /// 原始链路不支持取消、进度与打包编码，主要用于验证非 Rust 客户端的行为
+    pub fn set_raw_framing(&mut self, framing: Option<Framing>) {
+        self.raw_framing = framing;
+    }
+
+    /// 设置服务端推送的请求与通知的最大长度，超过限制的消息被丢弃，下次连接时生效
+    pub fn set_max_message_size(&mut self, size: Option<usize>) {
+        self.max_message_size = size;
+    }
+
+    /// 使用协商的编码调用远端方法，使用客户端的默认超时时间
+    ///
+    /// 协商的编码为 JSON 时与 `call_with_params` 相同；否则参数与返回值打包传输，
+    /// 链路断开时按重连策略重新连接，但不会自动重试
+    pub async fn call_encoded<P, R>(&mut self, method: &str, params: &P) -> CallResult<R>
+    where
+        P: Serialize,
+        R: DeserializeOwned,
+    {
+        if !self.is_connected() {
+            self.reconnect().await?;
+        }
+
+        let codec = self.codec();
+        if codec == Codec::Json {
+            let params = service::encode_params(params)?;
+            let reply = self.call_with_params(method, params).await?;
+            return service::decode_reply(reply);
+        }
+
+        let body = Packed::encode(codec, params)?;
+        let options = self.default_call_options();
+        let (reply, body) = match &self.tx {
+            Some(peer) => peer.call_packed(method, body, options).await?,
+            None => bail!("Client is not connected"),
+        };
+        codec::decode_reply(reply, body)
+    }
+
+    /// 查询服务端构建信息、方法列表与参数 schema
+    pub async fn discover(&mut self) -> anyhow::Result<ServerInfo> {
+        let reply = self.call_with_params(METHOD_DISCOVER, json!({})).await?;
+        service::decode_reply(reply)
+    }
+
+    /// 查询服务端每个方法的调用次数、错误次数与耗时分布
+    pub async fn stats(&mut self) -> anyhow::Result<EndpointStats> {
+        let reply = self.call_with_params(METHOD_STATS, json!({})).await?;
+        service::decode_reply(reply)
+    }
+
+    /// 发送批量请求，按请求顺序返回回复，通知不产生回复
+    pub async fn call_batch(&mut self, batch: Batch) -> anyhow::Result<Vec<JsonRpc>> {
+        let reply = self
+            .call_with_params(METHOD_BATCH, batch.to_params())
+            .await?;
+        let replies = batch::parse_replies(&reply)?;
+        if replies.len() != batch.reply_count() {
+            bail!(
+                "Batch expects {} replies, got {}",
+                batch.reply_count(),
+                replies.len()
+            );
+        }
+        Ok(replies)
+    }
+
+    /// 调用远端方法，超时后返回 `CallError::Timeout`
+    pub async fn call_with_timeout<P: Into<Params>>(
+        &mut self,
+        method: &str,
+        params: P,
+        timeout: Duration,
+    ) -> anyhow::Result<JsonRpc> {
+        self.call_with_options(method, params, CallOptions::new().timeout(timeout))
             .await
-        {
-            Ok(_) => match reply_rx.recv().await {
+    }
+
+    /// 使用指定的超时与取消选项调用远端方法
+    ///
+    /// 超时、取消或丢弃返回的 future 都会通知服务端该请求已经被放弃
+    pub async fn call_with_options<P: Into<Params>>(
+        &mut self,
+        method: &str,
+        params: P,
+        options: CallOptions,
+    ) -> anyhow::Result<JsonRpc> {
+        let params: Params = params.into();
+        let deadline = options.deadline();
+        let mut retries = 0;
+
+        loop {
+            if !self.is_connected() {
+                match deadline {
+                    Some(deadline) => match time::timeout_at(deadline, self.reconnect()).await {
+                        Ok(res) => res?,
+                        Err(_) => bail!(CallError::Timeout {
+                            method: method.to_owned(),
+                            timeout: options.get_timeout().unwrap_or_default(),
+                        }),
+                    },
+                    None => self.reconnect().await?,
+                }
+            }
+
+            let err = match self
+                .call_once(method, params.clone(), deadline, &options)
+                .await
+            {
                 Ok(Some(reply)) => return Ok(reply),
-                Ok(None) => bail!("Reply is empty"),
-                Err(err) => bail!(err),
-            },
-            Err(err) => bail!(err),
+                // 回复通道在回复前关闭，链路已经断开，此时 is_closed 可能尚未更新
+                Ok(None) => anyhow!("Connection was closed"),
+                // 超时或取消，链路仍然可用
+                Err(err) if err.is::<CallError>() => return Err(err),
+                // 发送或接收失败，认为链路已经断开
+                Err(err) => err,
+            };
+
+            log::warn!("PipeIo::Client: call '{method}' failed: {err}");
+            self.disconnect();
+
+            if retries < self.retry_policy.max_retries && self.retry_policy.is_retryable(method) {
+                retries += 1;
+                continue;
+            }
+            return Err(err);
         }
     }
 
+    /// 流式调用远端方法，不设置超时，适用于耗时较长并报告进度的操作
+    pub async fn call_stream<P: Into<Params>>(
+        &mut self,
+        method: &str,
+        params: P,
+    ) -> anyhow::Result<CallStream> {
+        self.call_stream_with_options(method, params, CallOptions::new())
+            .await
+    }
+
+    /// 使用指定的超时与取消选项流式调用远端方法
+    ///
+    /// 链路断开时按重连策略重新连接，流式调用不会自动重试
+    pub async fn call_stream_with_options<P: Into<Params>>(
+        &mut self,
+        method: &str,
+        params: P,
+        options: CallOptions,
+    ) -> anyhow::Result<CallStream> {
+        if !self.is_connected() {
+            self.reconnect().await?;
+        }
+        match &self.tx {
+            Some(peer) => peer.call_stream(method, params, options).await,
+            None => bail!("Client is not connected"),
+        }
+    }
+
+    /// 发送一次请求，对端未回复时返回 None
+    async fn call_once(
+        &mut self,
+        method: &str,
+        params: Params,
+        deadline: Option<Instant>,
+        options: &CallOptions,
+    ) -> anyhow::Result<Option<JsonRpc>> {
+        match &self.tx {
+            Some(peer) => peer.call_once(method, params, deadline, options).await,
+            None => bail!("Client is not connected"),
+        }
+    }
+
+    /// 按重连策略重新建立连接
+    async fn reconnect(&mut self) -> anyhow::Result<()> {
+        let policy = match &self.reconnect_policy {
+            Some(policy) => policy.clone(),
+            None if self.tx.is_none() => bail!("Client is not connected"),
+            None => bail!("Connection was lost"),
+        };
+
+        let mut attempt = 0;
+        loop {
+            self.set_state(ConnectionState::Reconnecting);
+            match self.connect().await {
+                Ok(_) => return Ok(()),
+                // 协议版本不兼容，重连不会改变结果
+                Err(err) if err.is::<HandshakeError>() => return Err(err),
+                Err(err) => {
+                    attempt += 1;
+                    if policy.max_attempts.is_some_and(|max| attempt >= max) {
+                        return Err(err);
+                    }
+                    log::warn!("PipeIo::Client: reconnect attempt {attempt} failed: {err}");
+                    time::sleep(policy.backoff(attempt - 1)).await;
+                }
+            }
+        }
+    }
+
+    /// 断开当前链路
+    pub fn disconnect(&mut self) {
+        self.alive.store(false, Ordering::SeqCst);
+        self.tx = None;
+        self.set_state(ConnectionState::Disconnected);
+    }
+
     pub async fn connect(&mut self) -> anyhow::Result<()> {
+        if self.state() != ConnectionState::Reconnecting {
+            self.set_state(ConnectionState::Connecting);
+        }
+
         // 创建底层 pipe 连接
-        let pipe_client = loop {
-            match ClientOptions::new().open(&self.pipe_name) {
-                Ok(client) => break client,
-                Err(e) if e.raw_os_error() == Some(ERROR_PIPE_BUSY.0 as i32) => (),
-                Err(e) => bail!("Cannot connect: {e:?}"),
+        let pipe_client = match self.connector.connect().await {
+            Ok(client) => client,
+            Err(e) => {
+                self.set_state(ConnectionState::Disconnected);
+                bail!("Cannot connect: {e:?}")
             }
-
-            time::sleep(Duration::from_millis(50)).await;
         };
 
         // 将 pipe 连接分离为 rx, tx
         let (pipe_rx, pipe_tx) = tokio::io::split(pipe_client);
 
-        // 创建 Remoc 双向链路
-        let (conn, tx, mut rx): (_, rch::base::Sender<IpcMsg>, rch::base::Receiver<IpcMsg>) =
-            remoc::Connect::io(remoc::Cfg::default(), pipe_rx, pipe_tx)
-                .await
-                .unwrap();
+        let (peer, mut rx) = match self.raw_framing {
+            Some(framing) => match raw::connect(framing, pipe_rx, pipe_tx).await {
+                Ok(link) => link,
+                Err(err) => {
+                    self.set_state(ConnectionState::Disconnected);
+                    bail!("Cannot establish raw JSON-RPC link: {err}")
+                }
+            },
+            None => {
+                // 创建 Remoc 双向链路
+                let (conn, tx, mut rx): (
+                    _,
+                    rch::base::Sender<IpcMsg>,
+                    rch::base::Receiver<IpcMsg>,
+                ) = match remoc::Connect::io(remoc::Cfg::default(), pipe_rx, pipe_tx).await {
+                    Ok(link) => link,
+                    Err(err) => {
+                        self.set_state(ConnectionState::Disconnected);
+                        bail!("Cannot establish remoc link: {err}")
+                    }
+                };
+                if let Some(max) = self.max_message_size {
+                    rx.set_max_item_size(max);
+                }
 
-        tokio::spawn(conn);
+                tokio::spawn(conn);
+                (Peer::spawn(tx), Inbound::Remoc(Box::new(rx)))
+            }
+        };
 
-        self.tx = Some(tx);
+        self.tx = Some(peer);
+
+        // 交换协议版本与服务端信息，版本不兼容时拒绝该连接
+        match self.handshake().await {
+            Ok(info) => self.server_info = info,
+            Err(err) => {
+                self.tx = None;
+                self.set_state(ConnectionState::Disconnected);
+                return Err(err);
+            }
+        }
+
+        let alive = Arc::new(AtomicBool::new(true));
+        self.alive = alive.clone();
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        self.set_state(ConnectionState::Connected);
 
         let handlers = self.handlers.clone();
+        let subscriptions = self.subscriptions.clone();
+        let current_generation = self.generation.clone();
+        let state_tx = self.state_tx.clone();
 
         // 接收对端请求
         tokio::spawn(async move {
-            Self::process_incoming(handlers, &mut rx).await;
+            Self::process_incoming(handlers, subscriptions.clone(), &alive, &mut rx).await;
+
+            // 链路断开，结束本地订阅并报告状态
+            alive.store(false, Ordering::SeqCst);
+            if current_generation.load(Ordering::SeqCst) == generation {
+                subscriptions.clear();
+                let _ = state_tx.send(ConnectionState::Disconnected);
+            }
         });
 
         Ok(())
     }
 
+    /// 连接握手，旧版本服务端返回 None
+    async fn handshake(&mut self) -> anyhow::Result<Option<ServerInfo>> {
+        let params = json!(HandshakeParams {
+            protocol: ProtocolVersion::CURRENT,
+        });
+        let options = CallOptions::new().timeout(HANDSHAKE_TIMEOUT);
+        match self
+            .call_once(
+                METHOD_HANDSHAKE,
+                params.into(),
+                options.deadline(),
+                &options,
+            )
+            .await?
+        {
+            Some(reply) => handshake::negotiate(reply),
+            None => bail!("Handshake reply is empty"),
+        }
+    }
+
     /// 处理输入的请求
     async fn process_incoming(
         handlers: Arc<Mutex<ClientHandlers>>,
-        rx: &mut rch::base::Receiver<IpcMsg>,
+        subscriptions: Subscriptions,
+        alive: &AtomicBool,
+        rx: &mut Inbound,
     ) {
         loop {
             match rx.recv().await {
@@ -119,11 +601,23 @@ impl Client {
                             let on_request = { handlers.lock().await.on_request.clone() };
 
                             // 事件处理可能是耗时操作，分离到 blocking 线程进行
-                            let blocking_res = tokio::task::spawn_blocking(move || {
-                                on_request.emit(0, rpc_msg.payload)
-                            })
+                            let id2 = id.clone();
+                            let handler = tokio::task::spawn_blocking(move || {
+                                on_request.emit(id2, rpc_msg.payload)
+                            });
+
+                            // 对端取消请求时不再等待处理结果
+                            let blocking_res = match deadline::run_cancellable(
+                                &id,
+                                rpc_msg.cancel_rx,
+                                CancellationToken::new(),
+                                handler,
+                            )
                             .await
-                            .unwrap();
+                            {
+                                Some(res) => res.unwrap(),
+                                None => continue,
+                            };
 
                             match blocking_res {
                                 Some(reply) => {
@@ -145,14 +639,18 @@ impl Client {
                             }
                         }
                         JsonRpc::Notification(_) => {
-                            if let Some(tx) = rpc_msg.reply_tx {
-                                tx.send(jsonrpc_lite::JsonRpc::error(
-                                    0,
-                                    jsonrpc_lite::Error::invalid_request(),
-                                ))
-                                .await
-                                .unwrap();
+                            // 服务器即将关闭，后续调用重新建立连接
+                            if rpc_msg.payload.get_method() == Some(METHOD_SHUTDOWN) {
+                                log::info!("PipeIo::Client: server is shutting down");
+                                alive.store(false, Ordering::SeqCst);
                             }
+
+                            subscriptions.dispatch(&rpc_msg.payload);
+
+                            let on_notification = { handlers.lock().await.on_notification.clone() };
+                            tokio::task::spawn_blocking(move || {
+                                on_notification.emit(rpc_msg.payload)
+                            });
                         }
                         JsonRpc::Success(_) | JsonRpc::Error(_) => {
                             panic!("单向链路只应该收到 Reuquest 和 Notification");
@@ -183,3 +681,110 @@ pub async fn connect(pipe_name: &str) -> anyhow::Result<Client> {
     client.connect().await?;
     Ok(client)
 }
+
+#[cfg(test)]
+mod test {
+    use std::io;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+    use jsonrpc_lite::JsonRpc;
+    use parking_lot::Mutex;
+    use serde_json::json;
+
+    use crate::client::Client;
+    use crate::retry::{ConnectionState, RetryPolicy};
+    use crate::server::{Server, ShutdownHandle};
+    use crate::transport::memory::{self, MemoryConnector, MemoryListener};
+    use crate::transport::{BoxStream, Connector};
+
+    #[tokio::test]
+    async fn test_reconnect_after_link_lost() {
+        let (listener, connector) = memory::channel();
+
+        let mut server = Server::with_listener("memory", listener);
+        server.on_connection(|socket, _| {
+            socket
+                .lock()
+                .on_request(|_, id, _| JsonRpc::success(id, &json!(true)));
+            0
+        });
+        tokio::spawn(async move { server.listen().await });
+
+        let mut client = Client::with_connector(connector);
+        assert_eq!(client.state(), ConnectionState::Disconnected);
+
+        client.connect().await.unwrap();
+        assert_eq!(client.state(), ConnectionState::Connected);
+
+        client.disconnect();
+        assert_eq!(client.state(), ConnectionState::Disconnected);
+
+        // 链路断开后调用会自动重连
+        let reply = client.call_with_params("ping", json!({})).await.unwrap();
+        assert_eq!(reply.get_result(), Some(&json!(true)));
+        assert_eq!(client.state(), ConnectionState::Connected);
+
+        // 关闭重连后调用直接失败
+        client.set_reconnect_policy(None);
+        client.disconnect();
+        assert!(client.call_with_params("ping", json!({})).await.is_err());
+    }
+
+    /// 可切换的端点，模拟服务重启后在同一管道名上重新监听
+    struct Endpoint(Arc<Mutex<MemoryConnector>>);
+
+    #[async_trait]
+    impl Connector for Endpoint {
+        async fn connect(&self) -> io::Result<BoxStream> {
+            let connector = self.0.lock().clone();
+            connector.connect().await
+        }
+    }
+
+    fn spawn_server(listener: MemoryListener, mode: &'static str) -> ShutdownHandle {
+        let mut server = Server::with_listener("memory", listener);
+        server.set_drain_timeout(Duration::from_millis(10));
+        server.on_connection(move |socket, _| {
+            socket.lock().on_request_async(move |ctx, _| async move {
+                // 旧的服务进程在回复前被结束
+                if mode == "old" {
+                    tokio::time::sleep(Duration::from_secs(10)).await;
+                }
+                JsonRpc::success(ctx.id, &json!(mode))
+            });
+            0
+        });
+        let shutdown = server.shutdown_handle();
+        tokio::spawn(async move { server.listen().await });
+        shutdown
+    }
+
+    #[tokio::test]
+    async fn test_retry_when_server_killed_mid_call() {
+        let (old_listener, old_connector) = memory::channel();
+        let (new_listener, new_connector) = memory::channel();
+        let old_server = spawn_server(old_listener, "old");
+        spawn_server(new_listener, "new");
+
+        let endpoint = Arc::new(Mutex::new(old_connector));
+        let mut client = Client::with_connector(Endpoint(endpoint.clone()));
+        client.set_retry_policy(RetryPolicy::new(1).idempotent("get_mode"));
+        client.connect().await.unwrap();
+
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            *endpoint.lock() = new_connector;
+            old_server.shutdown();
+        });
+
+        // 链路在调用过程中断开，幂等方法重连后重试
+        let reply = client
+            .call_with_params("get_mode", json!({}))
+            .await
+            .unwrap();
+        assert_eq!(reply.get_result(), Some(&json!("new")));
+        assert_eq!(client.state(), ConnectionState::Connected);
+    }
+}
@@ -0,0 +1,391 @@
+//
+// Copyright (C) Lenovo ThinkBook Gen4 Project.
+//
+// This program is protected under international and China copyright laws as
+// an unpublished work. This program is confidential and proprietary to the
+// copyright owners. Reproduction or disclosure, in whole or in part, or the
+// production of derivative works therefrom without the express permission of
+// the copyright owners is prohibited.
+//
+// All rights reserved.
+//
+
+//! 参数与返回值的编码协商
+//!
+//! 服务端在握手回复的 `ServerInfo::codecs` 中列出支持的编码，客户端按自己的偏好
+//! 选择双方都支持的第一个编码，旧版本服务端只支持 JSON。协商结果不是 JSON 时，
+//! 类型化调用的参数与返回值按协商的编码打包为 `Packed`，通过请求携带的二进制通道
+//! 传输，JSON-RPC 消息本身不携带参数与结果；错误回复、取消与进度仍然走 JSON-RPC。
+//! `Blob` 在 JSON 中编码为 base64 字符串，在 CBOR 与 bincode 中直接传输字节。
+
+use std::cell::RefCell;
+use std::fmt;
+use std::sync::Arc;
+
+use anyhow::bail;
+use base64::Engine;
+use jsonrpc_lite::JsonRpc;
+use parking_lot::Mutex;
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use schemars::JsonSchema;
+use serde::de::{DeserializeOwned, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::service::{self, CallResult, RemoteError};
+
+/// 打包数据在 remoc 链路上使用的编码，字节数组不会展开为 JSON 数组
+pub type PackedCodec = remoc::codec::Bincode;
+
+thread_local! {
+    /// 当前线程正在处理的请求的打包数据，用于同步处理函数
+    static CURRENT: RefCell<Option<Body>> = const { RefCell::new(None) };
+}
+
+/// 参数与返回值的编码
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Codec {
+    /// JSON-RPC 原有的编码，所有端点都支持
+    #[default]
+    Json,
+    Cbor,
+    Bincode,
+}
+
+impl Codec {
+    /// 服务端默认支持的编码
+    pub const ALL: [Codec; 3] = [Codec::Json, Codec::Cbor, Codec::Bincode];
+
+    /// 按客户端的偏好选择服务端支持的第一个编码，没有共同支持的编码时使用 JSON
+    pub fn negotiate(preferred: &[Codec], supported: &[Codec]) -> Codec {
+        preferred
+            .iter()
+            .copied()
+            .find(|codec| supported.contains(codec))
+            .unwrap_or_default()
+    }
+
+    pub fn encode<T: Serialize>(&self, value: &T) -> anyhow::Result<Vec<u8>> {
+        Ok(match self {
+            Codec::Json => serde_json::to_vec(value)?,
+            Codec::Cbor => {
+                let mut data = Vec::new();
+                ciborium::ser::into_writer(value, &mut data)?;
+                data
+            }
+            Codec::Bincode => bincode::serialize(value)?,
+        })
+    }
+
+    pub fn decode<T: DeserializeOwned>(&self, data: &[u8]) -> anyhow::Result<T> {
+        Ok(match self {
+            Codec::Json => serde_json::from_slice(data)?,
+            Codec::Cbor => ciborium::de::from_reader(data)?,
+            Codec::Bincode => bincode::deserialize(data)?,
+        })
+    }
+}
+
+impl fmt::Display for Codec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Codec::Json => write!(f, "json"),
+            Codec::Cbor => write!(f, "cbor"),
+            Codec::Bincode => write!(f, "bincode"),
+        }
+    }
+}
+
+/// 按指定编码打包的参数或返回值
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Packed {
+    pub codec: Codec,
+    pub data: Vec<u8>,
+}
+
+impl Packed {
+    pub fn encode<T: Serialize>(codec: Codec, value: &T) -> anyhow::Result<Self> {
+        Ok(Self {
+            codec,
+            data: codec.encode(value)?,
+        })
+    }
+
+    pub fn decode<T: DeserializeOwned>(&self) -> anyhow::Result<T> {
+        self.codec.decode(&self.data)
+    }
+}
+
+impl fmt::Debug for Packed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Packed({}, {} bytes)", self.codec, self.data.len())
+    }
+}
+
+/// 二进制数据，JSON 中编码为 base64 字符串，CBOR 与 bincode 中直接传输字节
+#[derive(Clone, Default, PartialEq, Eq, Hash)]
+pub struct Blob(pub Vec<u8>);
+
+impl fmt::Debug for Blob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Blob({} bytes)", self.0.len())
+    }
+}
+
+impl From<Vec<u8>> for Blob {
+    fn from(data: Vec<u8>) -> Self {
+        Self(data)
+    }
+}
+
+impl Serialize for Blob {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            let encoded = base64::engine::general_purpose::STANDARD.encode(&self.0);
+            serializer.serialize_str(&encoded)
+        } else {
+            serializer.serialize_bytes(&self.0)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Blob {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            let encoded = String::deserialize(deserializer)?;
+            base64::engine::general_purpose::STANDARD
+                .decode(encoded)
+                .map(Blob)
+                .map_err(serde::de::Error::custom)
+        } else {
+            deserializer.deserialize_byte_buf(BlobVisitor)
+        }
+    }
+}
+
+struct BlobVisitor;
+
+impl<'de> Visitor<'de> for BlobVisitor {
+    type Value = Blob;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "bytes")
+    }
+
+    fn visit_bytes<E: serde::de::Error>(self, v: &[u8]) -> Result<Blob, E> {
+        Ok(Blob(v.to_vec()))
+    }
+
+    fn visit_byte_buf<E: serde::de::Error>(self, v: Vec<u8>) -> Result<Blob, E> {
+        Ok(Blob(v))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Blob, A::Error> {
+        let mut data = Vec::with_capacity(seq.size_hint().unwrap_or_default());
+        while let Some(byte) = seq.next_element()? {
+            data.push(byte);
+        }
+        Ok(Blob(data))
+    }
+}
+
+impl JsonSchema for Blob {
+    fn schema_name() -> String {
+        "Blob".to_owned()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        String::json_schema(gen)
+    }
+}
+
+#[derive(Debug)]
+struct BodyInner {
+    request: Packed,
+    reply: Mutex<Option<Packed>>,
+}
+
+/// 请求的打包数据，可以克隆到任意任务或线程中
+///
+/// 调用方没有打包参数时 `is_packed` 返回 false，参数在 JSON-RPC 消息中
+#[derive(Clone, Debug, Default)]
+pub struct Body {
+    inner: Option<Arc<BodyInner>>,
+}
+
+impl Body {
+    pub(crate) fn new(request: Packed) -> Self {
+        Self {
+            inner: Some(Arc::new(BodyInner {
+                request,
+                reply: Mutex::new(None),
+            })),
+        }
+    }
+
+    /// 当前线程正在处理的请求的打包数据，在同步请求回调中使用
+    pub fn current() -> Self {
+        CURRENT.with(|current| current.borrow().clone().unwrap_or_default())
+    }
+
+    /// 在 `f` 执行期间设置当前线程的打包数据
+    pub(crate) fn scope<R>(body: Body, f: impl FnOnce() -> R) -> R {
+        let previous = CURRENT.with(|current| current.replace(Some(body)));
+        let result = f();
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+        result
+    }
+
+    /// 调用方是否打包了参数并等待打包的返回值
+    pub fn is_packed(&self) -> bool {
+        self.inner.is_some()
+    }
+
+    /// 调用方使用的编码
+    pub fn codec(&self) -> Codec {
+        self.inner
+            .as_ref()
+            .map(|inner| inner.request.codec)
+            .unwrap_or_default()
+    }
+
+    /// 解析打包的参数
+    pub fn decode<T: DeserializeOwned>(&self) -> anyhow::Result<T> {
+        match &self.inner {
+            Some(inner) => inner.request.decode(),
+            None => bail!("Request is not packed"),
+        }
+    }
+
+    /// 使用调用方的编码打包返回值，在回复之前调用
+    pub fn set_reply<T: Serialize>(&self, reply: &T) -> anyhow::Result<()> {
+        match &self.inner {
+            Some(inner) => {
+                *inner.reply.lock() = Some(Packed::encode(inner.request.codec, reply)?);
+                Ok(())
+            }
+            None => bail!("Request is not packed"),
+        }
+    }
+
+    pub(crate) fn take_reply(&self) -> Option<Packed> {
+        self.inner.as_ref()?.reply.lock().take()
+    }
+}
+
+/// 解析打包调用的回复，服务端没有打包返回值时按 JSON 解析
+pub(crate) fn decode_reply<T: DeserializeOwned>(
+    reply: JsonRpc,
+    body: Option<Packed>,
+) -> CallResult<T> {
+    match (&reply, body) {
+        (JsonRpc::Success(_), Some(body)) => body.decode(),
+        (JsonRpc::Error(_), _) => match reply.get_error() {
+            Some(error) => Err(RemoteError(error.clone()).into()),
+            None => bail!("Invalid error reply"),
+        },
+        _ => service::decode_reply(reply),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use schemars::JsonSchema;
+    use serde::{Deserialize, Serialize};
+    use serde_json::json;
+
+    use super::{Blob, Codec};
+    use crate::client::Client;
+    use crate::server::Server;
+    use crate::service::{Ack, RpcResult};
+    use crate::transport::memory;
+
+    #[derive(Serialize, Deserialize, JsonSchema)]
+    struct RegionParams {
+        x: u32,
+        y: u32,
+        pixels: Blob,
+    }
+
+    crate::rpc_service! {
+        mod canvas {
+            fn checksum(RegionParams) -> u64;
+            fn invert(Blob) -> Blob;
+            fn clear(RegionParams) -> Ack;
+        }
+    }
+
+    struct Canvas;
+
+    impl canvas::Service for Canvas {
+        fn checksum(&self, params: RegionParams) -> RpcResult<u64> {
+            let sum: u64 = params.pixels.0.iter().map(|p| *p as u64).sum();
+            Ok(sum + (params.x + params.y) as u64)
+        }
+
+        fn invert(&self, params: Blob) -> RpcResult<Blob> {
+            Ok(Blob(params.0.iter().map(|p| !p).collect()))
+        }
+
+        fn clear(&self, _params: RegionParams) -> RpcResult<Ack> {
+            Ok(Ack)
+        }
+    }
+
+    #[test]
+    fn test_blob_encoding() {
+        let blob = Blob(vec![0, 1, 2, 255]);
+        assert_eq!(json!(blob), json!("AAEC/w=="));
+        for codec in Codec::ALL {
+            let data = codec.encode(&blob).unwrap();
+            assert_eq!(codec.decode::<Blob>(&data).unwrap(), blob);
+        }
+
+        let preferred = [Codec::Bincode, Codec::Cbor];
+        assert_eq!(Codec::negotiate(&preferred, &Codec::ALL), Codec::Bincode);
+        assert_eq!(
+            Codec::negotiate(&preferred, &[Codec::Json, Codec::Cbor]),
+            Codec::Cbor
+        );
+        // 旧版本服务端
+        assert_eq!(Codec::negotiate(&preferred, &[]), Codec::Json);
+    }
+
+    #[tokio::test]
+    async fn test_packed_calls() {
+        let (listener, connector) = memory::channel();
+
+        let mut server = Server::with_listener("memory", listener);
+        canvas::serve(&mut server, Arc::new(Canvas));
+        tokio::spawn(async move { server.listen().await });
+
+        let pixels: Vec<u8> = (0..=255).collect();
+        for codec in Codec::ALL {
+            let mut client = Client::with_connector(connector.clone());
+            client.set_codecs(vec![codec]);
+            client.connect().await.unwrap();
+            assert_eq!(client.codec(), codec);
+
+            let mut canvas = canvas::Client::new(&mut client);
+            let params = RegionParams {
+                x: 1,
+                y: 2,
+                pixels: Blob(pixels.clone()),
+            };
+            assert_eq!(canvas.checksum(params).await.unwrap(), 32643);
+
+            let inverted = canvas.invert(Blob(vec![0x0f, 0xf0])).await.unwrap();
+            assert_eq!(inverted, Blob(vec![0xf0, 0x0f]));
+
+            let params = RegionParams {
+                x: 0,
+                y: 0,
+                pixels: Blob::default(),
+            };
+            assert_eq!(canvas.clear(params).await.unwrap(), Ack);
+        }
+    }
+}
@@ -0,0 +1,234 @@
+//
+// Copyright (C) Lenovo ThinkBook Gen4 Project.
+//
+// This program is protected under international and China copyright laws as
+// an unpublished work. This program is confidential and proprietary to the
+// copyright owners. Reproduction or disclosure, in whole or in part, or the
+// production of derivative works therefrom without the express permission of
+// the copyright owners is prohibited.
+//
+// All rights reserved.
+//
+
+//! 调用超时与取消
+//!
+//! 每个请求携带一个 remoc oneshot 取消通道，调用方超时、主动取消或丢弃调用时，
+//! 对端会收到取消信号并停止等待处理结果。
+
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+
+use jsonrpc_lite::{Id, JsonRpc};
+use remoc::rch;
+use tokio::time::{self, Instant};
+pub use tokio_util::sync::CancellationToken;
+
+/// 默认调用超时时间
+pub const DEFAULT_CALL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 单次调用的选项
+#[derive(Clone, Debug, Default)]
+pub struct CallOptions {
+    timeout: Option<Duration>,
+    cancel: Option<CancellationToken>,
+}
+
+impl CallOptions {
+    /// 不设置超时与取消
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 设置调用超时
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// 设置取消令牌，令牌被取消时调用立即返回 `CallError::Cancelled`
+    pub fn cancel_token(mut self, token: CancellationToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    pub(crate) fn get_timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    pub(crate) fn get_cancel(&self) -> Option<CancellationToken> {
+        self.cancel.clone()
+    }
+
+    pub(crate) fn deadline(&self) -> Option<Instant> {
+        self.timeout.map(|timeout| Instant::now() + timeout)
+    }
+}
+
+/// 调用未完成的原因，通过 `anyhow::Error::downcast_ref` 获取
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CallError {
+    /// 在超时时间内没有收到回复
+    Timeout { method: String, timeout: Duration },
+    /// 调用被取消
+    Cancelled { method: String },
+}
+
+impl fmt::Display for CallError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CallError::Timeout { method, timeout } => {
+                write!(f, "Call '{method}' timed out after {timeout:?}")
+            }
+            CallError::Cancelled { method } => write!(f, "Call '{method}' was cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for CallError {}
+
+/// 等待到截止时间，None 表示永不超时
+pub(crate) async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// 等待令牌被取消，None 表示不可取消
+pub(crate) async fn cancelled(token: Option<CancellationToken>) {
+    match token {
+        Some(token) => token.cancelled().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// 等待对端回复，超时或取消时通知对端
+///
+/// Err(CallError) 表示超时或取消，其它错误表示链路错误，对端未回复时返回 None
+pub(crate) async fn wait_reply(
+    method: &str,
+    reply_rx: &mut rch::mpsc::Receiver<JsonRpc>,
+    cancel_tx: rch::oneshot::Sender<()>,
+    deadline: Option<Instant>,
+    options: &CallOptions,
+) -> anyhow::Result<Option<JsonRpc>> {
+    tokio::select! {
+        reply = reply_rx.recv() => match reply {
+            Ok(reply) => Ok(reply),
+            Err(err) => Err(err.into()),
+        },
+        _ = sleep_until(deadline) => {
+            let _ = cancel_tx.send(());
+            Err(CallError::Timeout {
+                method: method.to_owned(),
+                timeout: options.get_timeout().unwrap_or_default(),
+            }
+            .into())
+        }
+        _ = cancelled(options.cancel.clone()) => {
+            let _ = cancel_tx.send(());
+            Err(CallError::Cancelled {
+                method: method.to_owned(),
+            }
+            .into())
+        }
+    }
+}
+
+/// 执行请求处理，对端取消请求时取消 `token` 并返回 None
+///
+/// 调用方丢弃取消通道的发送端同样视为取消；`token` 被其它方取消（例如服务器
+/// 强制关闭）时同样放弃处理并返回 None
+pub(crate) async fn run_cancellable<F: Future>(
+    id: &Id,
+    cancel_rx: Option<rch::oneshot::Receiver<()>>,
+    token: CancellationToken,
+    fut: F,
+) -> Option<F::Output> {
+    let cancelled_by_peer = async {
+        match cancel_rx {
+            Some(cancel_rx) => {
+                let _ = cancel_rx.await;
+            }
+            None => std::future::pending().await,
+        }
+    };
+
+    tokio::select! {
+        output = fut => Some(output),
+        _ = cancelled_by_peer => {
+            log::info!("PipeIo: request {id:?} was cancelled by peer");
+            token.cancel();
+            None
+        }
+        _ = token.cancelled() => {
+            log::info!("PipeIo: request {id:?} was aborted");
+            None
+        }
+    }
+}
+
+/// 将请求 id 转换为 map 的键
+pub(crate) fn id_key(id: &Id) -> String {
+    match id {
+        Id::Str(id) => id.clone(),
+        Id::Num(id) => id.to_string(),
+        Id::None(_) => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use jsonrpc_lite::JsonRpc;
+    use serde_json::json;
+
+    use super::CallError;
+    use crate::client::Client;
+    use crate::server::Server;
+    use crate::transport::memory;
+
+    #[tokio::test]
+    async fn test_timeout_cancels_server_request() {
+        let (listener, connector) = memory::channel();
+        let (cancelled_tx, cancelled_rx) = std::sync::mpsc::channel();
+        let cancelled_tx = parking_lot::Mutex::new(cancelled_tx);
+
+        let mut server = Server::with_listener("memory", listener);
+        server.on_connection(move |socket, _| {
+            let cancelled_tx = cancelled_tx.lock().clone();
+            socket.lock().on_request(move |socket, id, _| {
+                let token = socket.lock().cancellation_token(&id).unwrap();
+                for _ in 0..100 {
+                    if token.is_cancelled() {
+                        break;
+                    }
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+                let _ = cancelled_tx.send(token.is_cancelled());
+                JsonRpc::success(id, &json!(true))
+            });
+            0
+        });
+        tokio::spawn(async move { server.listen().await });
+
+        let mut client = Client::with_connector(connector);
+        client.connect().await.unwrap();
+
+        let err = client
+            .call_with_timeout("slow", json!({}), Duration::from_millis(50))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CallError>(),
+            Some(CallError::Timeout { .. })
+        ));
+
+        let cancelled = tokio::task::spawn_blocking(move || cancelled_rx.recv().unwrap())
+            .await
+            .unwrap();
+        assert!(cancelled);
+    }
+}
@@ -0,0 +1,256 @@
+//
+// Copyright (C) Lenovo ThinkBook Gen4 Project.
+//
+// This program is protected under international and China copyright laws as
+// an unpublished work. This program is confidential and proprietary to the
+// copyright owners. Reproduction or disclosure, in whole or in part, or the
+// production of derivative works therefrom without the express permission of
+// the copyright owners is prohibited.
+//
+// All rights reserved.
+//
+
+//! 连接握手、协议版本与方法发现
+//!
+//! 客户端建立链路后首先调用保留方法 `rpc.handshake` 交换协议版本，服务端回复
+//! `ServerInfo`（协议版本、构建信息与方法列表）。主版本号不同视为不兼容，双方
+//! 都会拒绝该连接；旧版本服务端不支持握手时客户端降级为 `ProtocolVersion::LEGACY`。
+//! 任意时刻都可以调用 `rpc.discover` 获取 `ServerInfo`。
+
+use std::fmt;
+use std::time::Duration;
+
+use jsonrpc_lite::JsonRpc;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::codec::Codec;
+use crate::service;
+
+/// 连接握手
+pub const METHOD_HANDSHAKE: &str = "rpc.handshake";
+
+/// 获取服务端信息与方法列表
+pub const METHOD_DISCOVER: &str = "rpc.discover";
+
+/// 协议版本不兼容的错误码，错误数据为服务端协议版本
+pub const INCOMPATIBLE_PROTOCOL: i64 = -32010;
+
+/// 握手超时时间
+pub(crate) const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 协议版本，主版本号相同即可互通
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct ProtocolVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl ProtocolVersion {
+    /// 当前协议版本
+    pub const CURRENT: Self = Self { major: 1, minor: 0 };
+
+    /// 不支持握手的旧版本端点
+    pub const LEGACY: Self = Self { major: 0, minor: 0 };
+
+    pub fn is_compatible(&self, other: &Self) -> bool {
+        self.major == other.major
+    }
+}
+
+impl fmt::Display for ProtocolVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// 服务端构建信息
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildInfo {
+    pub name: String,
+    pub version: String,
+    pub commit: String,
+    pub commit_date: String,
+    pub build_time: String,
+}
+
+/// 方法描述，参数与返回值为 JSON Schema
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MethodInfo {
+    pub name: String,
+    pub params: Value,
+    pub reply: Value,
+}
+
+impl MethodInfo {
+    pub fn new<P: JsonSchema, R: JsonSchema>(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            params: json!(schemars::schema_for!(P)),
+            reply: json!(schemars::schema_for!(R)),
+        }
+    }
+}
+
+/// 握手与 `rpc.discover` 的回复
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ServerInfo {
+    pub protocol: ProtocolVersion,
+    #[serde(default)]
+    pub build: Option<BuildInfo>,
+    #[serde(default)]
+    pub methods: Vec<MethodInfo>,
+    /// 支持的参数与返回值编码，旧版本服务端只支持 JSON
+    #[serde(default)]
+    pub codecs: Vec<Codec>,
+}
+
+impl Default for ServerInfo {
+    fn default() -> Self {
+        Self {
+            protocol: ProtocolVersion::CURRENT,
+            build: None,
+            methods: Vec::new(),
+            codecs: Codec::ALL.to_vec(),
+        }
+    }
+}
+
+impl ServerInfo {
+    /// 查找方法描述
+    pub fn method(&self, name: &str) -> Option<&MethodInfo> {
+        self.methods.iter().find(|method| method.name == name)
+    }
+
+    /// 服务端是否提供该方法
+    pub fn supports(&self, name: &str) -> bool {
+        self.method(name).is_some()
+    }
+}
+
+/// 握手失败的原因，通过 `anyhow::Error::downcast_ref` 获取
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HandshakeError {
+    /// 协议主版本号不同
+    Incompatible {
+        client: ProtocolVersion,
+        server: ProtocolVersion,
+    },
+}
+
+impl fmt::Display for HandshakeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HandshakeError::Incompatible { client, server } => write!(
+                f,
+                "Incompatible protocol version: client {client}, server {server}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for HandshakeError {}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct HandshakeParams {
+    pub protocol: ProtocolVersion,
+}
+
+/// 处理握手与方法发现请求，其它方法返回 None
+pub(crate) fn handle_request(info: &ServerInfo, req: &JsonRpc) -> Option<JsonRpc> {
+    let method = req.get_method()?;
+    if method != METHOD_HANDSHAKE && method != METHOD_DISCOVER {
+        return None;
+    }
+
+    let id = req.get_id()?;
+    if method == METHOD_HANDSHAKE {
+        let params: HandshakeParams = match service::decode_params(req) {
+            Ok(params) => params,
+            Err(error) => return Some(JsonRpc::error(id, error)),
+        };
+
+        if !info.protocol.is_compatible(&params.protocol) {
+            log::warn!(
+                "PipeIo: reject client with protocol {}, server protocol {}",
+                params.protocol,
+                info.protocol
+            );
+            let error = jsonrpc_lite::Error {
+                code: INCOMPATIBLE_PROTOCOL,
+                message: "Incompatible protocol version".to_owned(),
+                data: Some(json!(info.protocol)),
+            };
+            return Some(JsonRpc::error(id, error));
+        }
+    }
+
+    Some(JsonRpc::success(id, &json!(info)))
+}
+
+/// 解析握手回复，旧版本服务端返回 None
+pub(crate) fn negotiate(reply: JsonRpc) -> anyhow::Result<Option<ServerInfo>> {
+    let client = ProtocolVersion::CURRENT;
+
+    if let Some(error) = reply.get_error() {
+        if error.code == INCOMPATIBLE_PROTOCOL {
+            let server = error
+                .data
+                .clone()
+                .and_then(|data| serde_json::from_value(data).ok())
+                .unwrap_or(ProtocolVersion::LEGACY);
+            return Err(HandshakeError::Incompatible { client, server }.into());
+        }
+    }
+
+    let info: ServerInfo = match service::decode_reply(reply) {
+        Ok(info) => info,
+        Err(err) => {
+            log::warn!(
+                "PipeIo::Client: server does not support handshake, use legacy protocol: {err}"
+            );
+            return Ok(None);
+        }
+    };
+
+    if !client.is_compatible(&info.protocol) {
+        return Err(HandshakeError::Incompatible {
+            client,
+            server: info.protocol,
+        }
+        .into());
+    }
+
+    Ok(Some(info))
+}
+
+#[cfg(test)]
+mod test {
+    use jsonrpc_lite::JsonRpc;
+    use serde_json::json;
+
+    use super::{negotiate, HandshakeError, ProtocolVersion, ServerInfo};
+
+    #[test]
+    fn test_negotiate() {
+        let info = ServerInfo::default();
+        let reply = JsonRpc::success(1, &json!(info));
+        assert_eq!(negotiate(reply).unwrap(), Some(info));
+
+        // 旧版本服务端不认识握手方法
+        let reply = JsonRpc::error(1, jsonrpc_lite::Error::method_not_found());
+        assert_eq!(negotiate(reply).unwrap(), None);
+
+        let info = ServerInfo {
+            protocol: ProtocolVersion { major: 2, minor: 0 },
+            ..Default::default()
+        };
+        let reply = JsonRpc::success(1, &json!(info));
+        let err = negotiate(reply).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<HandshakeError>(),
+            Some(HandshakeError::Incompatible { .. })
+        ));
+    }
+}
@@ -10,14 +10,33 @@
 // All rights reserved.
 //
 
+#[cfg(windows)]
 pub use pipe_ipc::*;
 
 pub mod jsonrpc {
     pub use jsonrpc_lite::*;
 }
 
+pub mod access;
+pub mod batch;
 pub mod blocking;
 pub mod msg;
 
 pub mod client;
+pub mod codec;
+pub mod deadline;
+pub mod handshake;
+pub mod limits;
+pub mod metrics;
+pub mod middleware;
+pub mod peer;
+pub mod pubsub;
+pub mod raw;
+pub mod recorder;
+pub mod registry;
+pub mod replay;
+pub mod retry;
 pub mod server;
+pub mod service;
+pub mod stream;
+pub mod transport;
@@ -0,0 +1,342 @@
+//
+// Copyright (C) Lenovo ThinkBook Gen4 Project.
+//
+// This program is protected under international and China copyright laws as
+// an unpublished work. This program is confidential and proprietary to the
+// copyright owners. Reproduction or disclosure, in whole or in part, or the
+// production of derivative works therefrom without the express permission of
+// the copyright owners is prohibited.
+//
+// All rights reserved.
+//
+
+//! 消息大小、并发与调用频率限制
+//!
+//! 超过大小限制的原始链路消息与打包参数回复 `MESSAGE_TOO_LARGE`，remoc 链路上超过
+//! 大小限制的消息在完整读入前被丢弃，调用方收到链路错误。连接的并发请求达到上限时按
+//! `Overflow` 排队或回复 `TOO_MANY_REQUESTS`，超过频率限制的调用回复 `RATE_LIMITED`。
+//! 被限制的通知直接丢弃。频率限制使用令牌桶，`rpc.` 开头的保留方法不受频率限制。
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use jsonrpc_lite::{Id, JsonRpc};
+use parking_lot::Mutex;
+use serde_json::json;
+use tokio::time::Instant;
+
+use crate::raw;
+use crate::registry::ConnectionId;
+
+/// 消息超过大小限制的错误码，错误数据为 `{"size", "max"}`
+pub const MESSAGE_TOO_LARGE: i64 = -32012;
+
+/// 连接的并发请求达到上限的错误码
+pub const TOO_MANY_REQUESTS: i64 = -32013;
+
+/// 超过方法调用频率限制的错误码，错误数据为方法名
+pub const RATE_LIMITED: i64 = -32014;
+
+/// 连接的并发请求达到上限后的处理方式
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Overflow {
+    /// 暂停读取该连接的后续消息，直到有请求处理完成
+    #[default]
+    Queue,
+    /// 立即回复 `TOO_MANY_REQUESTS`
+    Reject,
+}
+
+/// 方法调用频率限制，允许在 `period` 内调用 `burst` 次
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimit {
+    burst: u32,
+    period: Duration,
+    per_connection: bool,
+}
+
+impl RateLimit {
+    /// 所有连接共享的频率限制，用于保护硬件等全局资源
+    pub fn new(burst: u32, period: Duration) -> Self {
+        Self {
+            burst: burst.max(1),
+            period,
+            per_connection: false,
+        }
+    }
+
+    /// 每个连接单独计算频率
+    pub fn per_connection(mut self) -> Self {
+        self.per_connection = true;
+        self
+    }
+
+    /// 每秒补充的令牌数量
+    fn refill_rate(&self) -> f64 {
+        self.burst as f64 / self.period.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+/// 服务端点的限制配置
+///
+/// ```ignore
+/// server.set_limits(
+///     Limits::new()
+///         .max_message_size(64 * 1024)
+///         .overflow(Overflow::Reject)
+///         .rate_limit("refresh", RateLimit::new(10, Duration::from_secs(1))),
+/// );
+/// ```
+///
+/// 每个连接的并发请求数量由 `Server::set_max_concurrent_requests` 设置
+#[derive(Clone, Debug, Default)]
+pub struct Limits {
+    max_message_size: Option<usize>,
+    overflow: Overflow,
+    rate_limits: HashMap<String, RateLimit>,
+}
+
+impl Limits {
+    /// 不限制消息大小与调用频率，并发请求达到上限时排队
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 设置单条消息序列化后的最大字节数
+    pub fn max_message_size(mut self, size: usize) -> Self {
+        self.max_message_size = Some(size);
+        self
+    }
+
+    /// 设置并发请求达到上限后的处理方式
+    pub fn overflow(mut self, overflow: Overflow) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    /// 设置方法的调用频率限制，`*` 表示没有单独设置的方法，每个方法分别计算
+    pub fn rate_limit(mut self, method: &str, limit: RateLimit) -> Self {
+        self.rate_limits.insert(method.to_owned(), limit);
+        self
+    }
+
+    pub(crate) fn get_overflow(&self) -> Overflow {
+        self.overflow
+    }
+
+    fn find_rate_limit(&self, method: &str) -> Option<&RateLimit> {
+        if method.starts_with("rpc.") {
+            return None;
+        }
+        self.rate_limits
+            .get(method)
+            .or_else(|| self.rate_limits.get("*"))
+    }
+}
+
+/// 令牌桶
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(limit: &RateLimit) -> Self {
+        Self {
+            tokens: limit.burst as f64,
+            updated_at: Instant::now(),
+        }
+    }
+
+    fn try_take(&mut self, limit: &RateLimit) -> bool {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.updated_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.refill_rate()).min(limit.burst as f64);
+        self.updated_at = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// 服务器共享的限制状态
+#[derive(Debug, Default)]
+pub(crate) struct Limiter {
+    limits: Limits,
+    /// 令牌桶，共享的频率限制连接 id 为 None
+    buckets: Mutex<HashMap<(Option<ConnectionId>, String), TokenBucket>>,
+}
+
+impl Limiter {
+    pub(crate) fn new(limits: Limits) -> Self {
+        Self {
+            limits,
+            buckets: Default::default(),
+        }
+    }
+
+    pub(crate) fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// 原始链路单条消息的最大长度，在读入内存前检查
+    pub(crate) fn max_frame_len(&self) -> usize {
+        match self.limits.max_message_size {
+            Some(max) => max.min(raw::MAX_FRAME_LEN),
+            None => raw::MAX_FRAME_LEN,
+        }
+    }
+
+    /// remoc 链路单条消息的最大长度，超过限制的消息在接收时被丢弃
+    pub(crate) fn max_message_size(&self) -> Option<usize> {
+        self.limits.max_message_size
+    }
+
+    /// 检查打包数据等二进制内容的大小
+    pub(crate) fn check_len(&self, size: usize) -> Result<(), (usize, usize)> {
+        match self.limits.max_message_size {
+            Some(max) if size > max => Err((size, max)),
+            _ => Ok(()),
+        }
+    }
+
+    /// 消耗一个令牌，超过频率限制时返回 false
+    pub(crate) fn check_rate(&self, conn_id: ConnectionId, method: &str) -> bool {
+        let limit = match self.limits.find_rate_limit(method) {
+            Some(limit) => limit,
+            None => return true,
+        };
+        let key = (limit.per_connection.then_some(conn_id), method.to_owned());
+        self.buckets
+            .lock()
+            .entry(key)
+            .or_insert_with(|| TokenBucket::new(limit))
+            .try_take(limit)
+    }
+
+    /// 连接断开后移除该连接的令牌桶
+    pub(crate) fn remove_connection(&self, conn_id: ConnectionId) {
+        self.buckets
+            .lock()
+            .retain(|(id, _), _| *id != Some(conn_id));
+    }
+}
+
+/// 消息过大的回复
+pub(crate) fn too_large_reply(id: Id, size: usize, max: usize) -> JsonRpc {
+    JsonRpc::error(
+        id,
+        jsonrpc_lite::Error {
+            code: MESSAGE_TOO_LARGE,
+            message: "Message too large".to_owned(),
+            data: Some(json!({ "size": size, "max": max })),
+        },
+    )
+}
+
+/// 并发请求达到上限的回复
+pub(crate) fn too_many_requests_reply(id: Id) -> JsonRpc {
+    JsonRpc::error(
+        id,
+        jsonrpc_lite::Error {
+            code: TOO_MANY_REQUESTS,
+            message: "Too many requests".to_owned(),
+            data: None,
+        },
+    )
+}
+
+/// 超过调用频率限制的回复
+pub(crate) fn rate_limited_reply(id: Id, method: &str) -> JsonRpc {
+    JsonRpc::error(
+        id,
+        jsonrpc_lite::Error {
+            code: RATE_LIMITED,
+            message: "Rate limited".to_owned(),
+            data: Some(json!(method)),
+        },
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use jsonrpc_lite::JsonRpc;
+    use serde_json::json;
+
+    use super::{Limits, Overflow, RateLimit, RATE_LIMITED, TOO_MANY_REQUESTS};
+    use crate::client::Client;
+    use crate::server::Server;
+    use crate::transport::memory;
+
+    fn error_code(reply: &JsonRpc) -> Option<i64> {
+        reply.get_error().map(|error| error.code)
+    }
+
+    #[tokio::test]
+    async fn test_size_rate_and_overflow_limits() {
+        let (listener, connector) = memory::channel();
+
+        let mut server = Server::with_listener("memory", listener);
+        server.set_max_concurrent_requests(1);
+        server.set_limits(
+            Limits::new()
+                .max_message_size(1024)
+                .overflow(Overflow::Reject)
+                .rate_limit("refresh", RateLimit::new(2, Duration::from_secs(60))),
+        );
+        server.on_connection(|socket, _| {
+            socket.lock().on_request_async(|ctx, req| async move {
+                if req.get_method() == Some("slow") {
+                    tokio::time::sleep(Duration::from_millis(200)).await;
+                }
+                JsonRpc::success(ctx.id, &json!(true))
+            });
+            0
+        });
+        tokio::spawn(async move { server.listen().await });
+
+        let mut client = Client::with_connector(connector);
+        client.connect().await.unwrap();
+
+        // 令牌用完后被限制
+        for _ in 0..2 {
+            let reply = client.call_with_params("refresh", json!({})).await.unwrap();
+            assert_eq!(reply.get_result(), Some(&json!(true)));
+        }
+        let reply = client.call_with_params("refresh", json!({})).await.unwrap();
+        assert_eq!(error_code(&reply), Some(RATE_LIMITED));
+
+        // 其它方法不受影响
+        let reply = client.call_with_params("other", json!({})).await.unwrap();
+        assert_eq!(reply.get_result(), Some(&json!(true)));
+
+        // 超过大小限制的消息被丢弃，链路仍然可用
+        let blob = "x".repeat(4096);
+        assert!(client
+            .call_with_params("other", json!({ "blob": blob }))
+            .await
+            .is_err());
+        let reply = client.call_with_params("other", json!({})).await.unwrap();
+        assert_eq!(reply.get_result(), Some(&json!(true)));
+
+        // 并发请求达到上限时拒绝
+        let peer = client.peer().unwrap();
+        let slow = tokio::spawn({
+            let peer = peer.clone();
+            async move { peer.call_with_params("slow", json!({})).await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let reply = peer.call_with_params("other", json!({})).await.unwrap();
+        assert_eq!(error_code(&reply), Some(TOO_MANY_REQUESTS));
+
+        let reply = slow.await.unwrap().unwrap();
+        assert_eq!(reply.get_result(), Some(&json!(true)));
+    }
+}
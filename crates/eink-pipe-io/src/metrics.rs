@@ -0,0 +1,295 @@
+//
+// Copyright (C) Lenovo ThinkBook Gen4 Project.
+//
+// This program is protected under international and China copyright laws as
+// an unpublished work. This program is confidential and proprietary to the
+// copyright owners. Reproduction or disclosure, in whole or in part, or the
+// production of derivative works therefrom without the express permission of
+// the copyright owners is prohibited.
+//
+// All rights reserved.
+//
+
+//! 调用统计
+//!
+//! 服务器为每个方法记录调用次数、错误次数、取消次数与耗时直方图，客户端可以通过
+//! 保留方法 `rpc.stats` 获取 `EndpointStats`，服务器也可以定期将统计写入日志。
+//! 耗时从收到请求开始计算，包含排队等待的时间。
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use jsonrpc_lite::JsonRpc;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// 获取调用统计
+pub const METHOD_STATS: &str = "rpc.stats";
+
+/// 耗时直方图的桶上限，单位毫秒，最后一个桶统计超过最大上限的调用
+pub const LATENCY_BUCKETS_MS: [u64; 8] = [1, 5, 10, 50, 100, 500, 1000, 5000];
+
+/// 单个方法的调用统计
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct MethodStats {
+    pub calls: u64,
+    /// 回复为错误的调用次数
+    pub errors: u64,
+    /// 对端取消或超时放弃的调用次数
+    pub cancelled: u64,
+    pub total_ms: f64,
+    pub max_ms: f64,
+    /// 与 `LATENCY_BUCKETS_MS` 对应的计数，多出的一个桶统计超过最大上限的调用
+    pub histogram: Vec<u64>,
+}
+
+impl MethodStats {
+    fn record(&mut self, elapsed: Duration, outcome: Outcome) {
+        let ms = elapsed.as_secs_f64() * 1000.0;
+        self.calls += 1;
+        match outcome {
+            Outcome::Ok => {}
+            Outcome::Error => self.errors += 1,
+            Outcome::Cancelled => self.cancelled += 1,
+        }
+        self.total_ms += ms;
+        self.max_ms = self.max_ms.max(ms);
+
+        if self.histogram.is_empty() {
+            self.histogram = vec![0; LATENCY_BUCKETS_MS.len() + 1];
+        }
+        let bucket = LATENCY_BUCKETS_MS
+            .iter()
+            .position(|upper| ms <= *upper as f64)
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+        self.histogram[bucket] += 1;
+    }
+
+    /// 平均耗时，单位毫秒
+    pub fn mean_ms(&self) -> f64 {
+        match self.calls {
+            0 => 0.0,
+            calls => self.total_ms / calls as f64,
+        }
+    }
+
+    /// 分位数所在桶的上限，单位毫秒，超过最大上限时返回 None
+    pub fn percentile_ms(&self, quantile: f64) -> Option<u64> {
+        let target = (self.calls as f64 * quantile).ceil().max(1.0) as u64;
+        let mut count = 0;
+        for (bucket, n) in self.histogram.iter().enumerate() {
+            count += n;
+            if count >= target {
+                return LATENCY_BUCKETS_MS.get(bucket).copied();
+            }
+        }
+        None
+    }
+}
+
+/// 服务端点的调用统计
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct EndpointStats {
+    pub endpoint: String,
+    pub uptime_secs: u64,
+    /// 当前连接数量
+    pub connections: u64,
+    /// 累计连接数量
+    pub total_connections: u64,
+    /// 按方法名排序的统计
+    pub methods: BTreeMap<String, MethodStats>,
+}
+
+impl EndpointStats {
+    pub fn calls(&self) -> u64 {
+        self.methods.values().map(|stats| stats.calls).sum()
+    }
+
+    pub fn errors(&self) -> u64 {
+        self.methods.values().map(|stats| stats.errors).sum()
+    }
+}
+
+/// 调用结果
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Outcome {
+    Ok,
+    Error,
+    Cancelled,
+}
+
+impl Outcome {
+    pub(crate) fn of(reply: Option<&JsonRpc>) -> Self {
+        match reply {
+            Some(JsonRpc::Error(_)) => Outcome::Error,
+            Some(_) => Outcome::Ok,
+            None => Outcome::Cancelled,
+        }
+    }
+}
+
+struct Inner {
+    started_at: SystemTime,
+    connections: u64,
+    total_connections: u64,
+    methods: BTreeMap<String, MethodStats>,
+}
+
+/// 调用统计，可以克隆到任意任务或线程中使用
+#[derive(Clone)]
+pub struct Metrics {
+    endpoint: Arc<str>,
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl Metrics {
+    pub(crate) fn new(endpoint: &str) -> Self {
+        Self {
+            endpoint: Arc::from(endpoint),
+            inner: Arc::new(Mutex::new(Inner {
+                started_at: SystemTime::now(),
+                connections: 0,
+                total_connections: 0,
+                methods: BTreeMap::new(),
+            })),
+        }
+    }
+
+    pub(crate) fn record(&self, method: &str, elapsed: Duration, outcome: Outcome) {
+        let mut inner = self.inner.lock();
+        match inner.methods.get_mut(method) {
+            Some(stats) => stats.record(elapsed, outcome),
+            None => {
+                let mut stats = MethodStats::default();
+                stats.record(elapsed, outcome);
+                inner.methods.insert(method.to_owned(), stats);
+            }
+        }
+    }
+
+    pub(crate) fn connection_opened(&self) {
+        let mut inner = self.inner.lock();
+        inner.connections += 1;
+        inner.total_connections += 1;
+    }
+
+    pub(crate) fn connection_closed(&self) {
+        let mut inner = self.inner.lock();
+        inner.connections = inner.connections.saturating_sub(1);
+    }
+
+    /// 当前统计
+    pub fn snapshot(&self) -> EndpointStats {
+        let inner = self.inner.lock();
+        EndpointStats {
+            endpoint: self.endpoint.to_string(),
+            uptime_secs: inner
+                .started_at
+                .elapsed()
+                .map(|uptime| uptime.as_secs())
+                .unwrap_or_default(),
+            connections: inner.connections,
+            total_connections: inner.total_connections,
+            methods: inner.methods.clone(),
+        }
+    }
+
+    /// 清空方法统计，连接数量保持不变
+    pub fn reset(&self) {
+        self.inner.lock().methods.clear();
+    }
+
+    /// 处理 `rpc.stats` 请求，其它方法返回 None
+    pub(crate) fn handle_request(&self, req: &JsonRpc) -> Option<JsonRpc> {
+        if req.get_method()? != METHOD_STATS {
+            return None;
+        }
+        let id = req.get_id()?;
+        Some(JsonRpc::success(id, &json!(self.snapshot())))
+    }
+
+    /// 将统计写入日志，每个方法一行
+    pub fn log_summary(&self) {
+        let stats = self.snapshot();
+        log::info!(
+            "PipeIo::Stats: '{}' uptime {}s, connections {}/{}, calls {}, errors {}",
+            stats.endpoint,
+            stats.uptime_secs,
+            stats.connections,
+            stats.total_connections,
+            stats.calls(),
+            stats.errors(),
+        );
+        for (method, method_stats) in &stats.methods {
+            let p99 = match method_stats.percentile_ms(0.99) {
+                Some(ms) => format!("<={ms}ms"),
+                None => format!(">{}ms", LATENCY_BUCKETS_MS[LATENCY_BUCKETS_MS.len() - 1]),
+            };
+            log::info!(
+                "PipeIo::Stats: '{}' {method}: calls {}, errors {}, cancelled {}, mean {:.1}ms, max {:.1}ms, p99 {p99}",
+                stats.endpoint,
+                method_stats.calls,
+                method_stats.errors,
+                method_stats.cancelled,
+                method_stats.mean_ms(),
+                method_stats.max_ms,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use jsonrpc_lite::JsonRpc;
+    use serde_json::json;
+
+    use crate::client::Client;
+    use crate::server::Server;
+    use crate::transport::memory;
+
+    #[tokio::test]
+    async fn test_stats_per_method() {
+        let (listener, connector) = memory::channel();
+
+        let mut server = Server::with_listener("memory", listener);
+        server.on_connection(|socket, _| {
+            socket.lock().on_request_async(|ctx, req| async move {
+                match req.get_method() {
+                    Some("slow") => {
+                        tokio::time::sleep(Duration::from_millis(20)).await;
+                        JsonRpc::success(ctx.id, &json!(true))
+                    }
+                    Some("fail") => JsonRpc::error(ctx.id, jsonrpc_lite::Error::internal_error()),
+                    _ => JsonRpc::success(ctx.id, &json!(true)),
+                }
+            });
+            0
+        });
+        tokio::spawn(async move { server.listen().await });
+
+        let mut client = Client::with_connector(connector);
+        client.connect().await.unwrap();
+        for method in ["fast", "fast", "slow", "fail"] {
+            client.call_with_params(method, json!({})).await.unwrap();
+        }
+
+        let stats = client.stats().await.unwrap();
+        assert_eq!(stats.endpoint, "memory");
+        assert_eq!(stats.connections, 1);
+
+        let fast = &stats.methods["fast"];
+        assert_eq!(fast.calls, 2);
+        assert_eq!(fast.errors, 0);
+
+        let slow = &stats.methods["slow"];
+        assert!(slow.max_ms >= 20.0);
+        assert_eq!(slow.percentile_ms(0.5), Some(50));
+
+        assert_eq!(stats.methods["fail"].errors, 1);
+        assert_eq!(stats.errors(), 1);
+    }
+}
@@ -0,0 +1,329 @@
+//
+// Copyright (C) Lenovo ThinkBook Gen4 Project.
+//
+// This program is protected under international and China copyright laws as
+// an unpublished work. This program is confidential and proprietary to the
+// copyright owners. Reproduction or disclosure, in whole or in part, or the
+// production of derivative works therefrom without the express permission of
+// the copyright owners is prohibited.
+//
+// All rights reserved.
+//
+
+//! 请求中间件
+//!
+//! 服务器按添加顺序将中间件串联在请求处理函数之前，先添加的中间件位于最外层。
+//! 每个中间件可以检查或修改请求、直接回复，或者调用 `Next::run` 交给后续中间件，
+//! 并在返回后处理回复。访问策略与频率限制在中间件之前检查，保留方法不经过中间件。
+//!
+//! ```ignore
+//! server.add_middleware(RequestId);
+//! server.add_middleware(Logging);
+//! server.add_middleware(Timing::new(Duration::from_millis(500)));
+//! server.add_middleware(CatchPanic);
+//! ```
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use jsonrpc_lite::JsonRpc;
+use serde_json::json;
+
+use crate::server::{BoxFuture, RequestContext};
+use crate::{access, deadline};
+
+/// 中间件链末端的请求处理函数
+pub(crate) type Endpoint = Box<dyn FnOnce(RequestContext, JsonRpc) -> BoxFuture<JsonRpc> + Send>;
+
+tokio::task_local! {
+    /// 当前任务正在处理的请求 id，用于异步处理函数
+    static TASK_REQUEST_ID: String;
+}
+
+thread_local! {
+    /// 当前线程正在处理的请求 id，用于同步处理函数
+    static THREAD_REQUEST_ID: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// 请求中间件
+#[async_trait]
+pub trait Middleware: Send + Sync + 'static {
+    /// 处理请求，调用 `next.run` 交给后续中间件与请求处理函数
+    async fn handle(&self, ctx: RequestContext, req: JsonRpc, next: Next) -> JsonRpc;
+}
+
+/// 后续的中间件与请求处理函数，只能运行一次
+pub struct Next {
+    chain: Arc<[Arc<dyn Middleware>]>,
+    index: usize,
+    endpoint: Endpoint,
+}
+
+impl Next {
+    pub(crate) fn new(chain: Arc<[Arc<dyn Middleware>]>, endpoint: Endpoint) -> Self {
+        Self {
+            chain,
+            index: 0,
+            endpoint,
+        }
+    }
+
+    pub async fn run(self, ctx: RequestContext, req: JsonRpc) -> JsonRpc {
+        match self.chain.get(self.index).cloned() {
+            Some(middleware) => {
+                let next = Next {
+                    index: self.index + 1,
+                    ..self
+                };
+                middleware.handle(ctx, req, next).await
+            }
+            None => (self.endpoint)(ctx, req).await,
+        }
+    }
+}
+
+/// 当前正在处理的请求 id，需要添加 `RequestId` 中间件
+///
+/// 在异步与同步请求处理函数中都可以使用，用于在日志中关联同一个请求
+pub fn request_id() -> Option<String> {
+    TASK_REQUEST_ID
+        .try_with(|id| id.clone())
+        .ok()
+        .or_else(|| THREAD_REQUEST_ID.with(|id| id.borrow().clone()))
+}
+
+/// 在 `f` 执行期间设置当前任务的请求 id
+pub(crate) async fn scope_async<F: Future>(id: Option<String>, f: F) -> F::Output {
+    match id {
+        Some(id) => TASK_REQUEST_ID.scope(id, f).await,
+        None => f.await,
+    }
+}
+
+/// 在 `f` 执行期间设置当前线程的请求 id
+pub(crate) fn scope_sync<R>(id: Option<String>, f: impl FnOnce() -> R) -> R {
+    let previous = THREAD_REQUEST_ID.with(|current| current.replace(id));
+    let result = f();
+    THREAD_REQUEST_ID.with(|current| *current.borrow_mut() = previous);
+    result
+}
+
+/// 方法名称，用于日志
+fn method_of(req: &JsonRpc) -> String {
+    req.get_method().unwrap_or_default().to_owned()
+}
+
+/// 回复结果，用于日志
+fn outcome_of(reply: &JsonRpc) -> String {
+    match reply.get_error() {
+        Some(error) => format!("error {}", error.code),
+        None => "ok".to_owned(),
+    }
+}
+
+/// 将请求 id 传递给后续的中间件与请求处理函数，使用 `request_id()` 获取
+///
+/// 请求 id 为 JSON-RPC 请求的 id，客户端为每个请求生成唯一的 uuid
+pub struct RequestId;
+
+#[async_trait]
+impl Middleware for RequestId {
+    async fn handle(&self, ctx: RequestContext, req: JsonRpc, next: Next) -> JsonRpc {
+        let id = deadline::id_key(&ctx.id);
+        TASK_REQUEST_ID.scope(id, next.run(ctx, req)).await
+    }
+}
+
+/// 记录每个请求与回复
+pub struct Logging;
+
+#[async_trait]
+impl Middleware for Logging {
+    async fn handle(&self, ctx: RequestContext, req: JsonRpc, next: Next) -> JsonRpc {
+        let method = method_of(&req);
+        let request_id = request_id().unwrap_or_else(|| deadline::id_key(&ctx.id));
+        log::info!(
+            "PipeIo: [{request_id}] on request '{method}' from client[{}], pid: {:?}",
+            ctx.connection.id,
+            ctx.connection.credentials.pid,
+        );
+        let reply = next.run(ctx, req).await;
+        log::info!(
+            "PipeIo: [{request_id}] reply '{method}': {}",
+            outcome_of(&reply)
+        );
+        reply
+    }
+}
+
+/// 记录请求耗时，超过 `slow` 的请求记录为警告
+pub struct Timing {
+    slow: Duration,
+}
+
+impl Timing {
+    pub fn new(slow: Duration) -> Self {
+        Self { slow }
+    }
+}
+
+#[async_trait]
+impl Middleware for Timing {
+    async fn handle(&self, ctx: RequestContext, req: JsonRpc, next: Next) -> JsonRpc {
+        let method = method_of(&req);
+        let request_id = request_id().unwrap_or_else(|| deadline::id_key(&ctx.id));
+        let started_at = Instant::now();
+        let reply = next.run(ctx, req).await;
+        let elapsed = started_at.elapsed();
+        if elapsed >= self.slow {
+            log::warn!("PipeIo: [{request_id}] slow request '{method}' took {elapsed:?}");
+        } else {
+            log::debug!("PipeIo: [{request_id}] request '{method}' took {elapsed:?}");
+        }
+        reply
+    }
+}
+
+/// 按请求上下文与方法名称授权，拒绝时回复 `ACCESS_DENIED`
+///
+/// 与 `AccessPolicy` 不同，授权函数可以使用连接信息之外的服务状态
+pub struct Authorize<F> {
+    authorize: F,
+}
+
+impl<F> Authorize<F>
+where
+    F: Fn(&RequestContext, &str) -> bool + Send + Sync + 'static,
+{
+    pub fn new(authorize: F) -> Self {
+        Self { authorize }
+    }
+}
+
+#[async_trait]
+impl<F> Middleware for Authorize<F>
+where
+    F: Fn(&RequestContext, &str) -> bool + Send + Sync + 'static,
+{
+    async fn handle(&self, ctx: RequestContext, req: JsonRpc, next: Next) -> JsonRpc {
+        let method = method_of(&req);
+        if !(self.authorize)(&ctx, &method) {
+            log::warn!(
+                "PipeIo: client[{}] was not authorized to call '{method}'",
+                ctx.connection.id
+            );
+            return access::denied_reply(ctx.id, &method);
+        }
+        next.run(ctx, req).await
+    }
+}
+
+/// 捕获后续中间件中的 panic，回复 `internal_error`，错误数据为 panic 信息
+///
+/// 请求处理函数的 panic 总是转换为 `internal_error`，不需要该中间件
+pub struct CatchPanic;
+
+#[async_trait]
+impl Middleware for CatchPanic {
+    async fn handle(&self, ctx: RequestContext, req: JsonRpc, next: Next) -> JsonRpc {
+        let id = ctx.id.clone();
+        let method = method_of(&req);
+        let task = scope_async(request_id(), next.run(ctx, req));
+        match tokio::spawn(task).await {
+            Ok(reply) => reply,
+            Err(err) => {
+                let message = match err.try_into_panic() {
+                    Ok(panic) => panic_message(panic),
+                    Err(_) => "Request was cancelled".to_owned(),
+                };
+                log::error!("PipeIo: request '{method}' panicked: {message}");
+                let mut error = jsonrpc_lite::Error::internal_error();
+                error.data = Some(json!(message));
+                JsonRpc::error(id, error)
+            }
+        }
+    }
+}
+
+/// panic 信息
+pub(crate) fn panic_message(panic: Box<dyn Any + Send>) -> String {
+    match panic.downcast::<String>() {
+        Ok(message) => *message,
+        Err(panic) => match panic.downcast::<&'static str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "Unknown panic".to_owned(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
+    use jsonrpc_lite::JsonRpc;
+    use parking_lot::Mutex;
+    use serde_json::json;
+
+    use super::{request_id, Authorize, CatchPanic, Middleware, Next, RequestId};
+    use crate::access::ACCESS_DENIED;
+    use crate::client::Client;
+    use crate::server::{RequestContext, Server};
+    use crate::transport::memory;
+
+    /// 记录经过的中间件
+    struct Trace(&'static str, Arc<Mutex<Vec<&'static str>>>);
+
+    #[async_trait]
+    impl Middleware for Trace {
+        async fn handle(&self, ctx: RequestContext, req: JsonRpc, next: Next) -> JsonRpc {
+            self.1.lock().push(self.0);
+            next.run(ctx, req).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_middleware_chain() {
+        let (listener, connector) = memory::channel();
+        let trace = Arc::new(Mutex::new(Vec::new()));
+
+        let mut server = Server::with_listener("memory", listener);
+        server.add_middleware(RequestId);
+        server.add_middleware(Trace("outer", trace.clone()));
+        server.add_middleware(Trace("inner", trace.clone()));
+        server.add_middleware(CatchPanic);
+        server.add_middleware(Authorize::new(|_: &RequestContext, method: &str| {
+            method != "secret"
+        }));
+        server.on_connection(|socket, _| {
+            socket
+                .lock()
+                .on_request(|_, id, req| match req.get_method() {
+                    Some("panic") => panic!("handler failed"),
+                    _ => JsonRpc::success(id, &json!(request_id())),
+                });
+            0
+        });
+        tokio::spawn(async move { server.listen().await });
+
+        let mut client = Client::with_connector(connector);
+        client.connect().await.unwrap();
+
+        // 同步处理函数也可以获取请求 id
+        let reply = client.call_with_params("echo", json!({})).await.unwrap();
+        assert!(reply.get_result().unwrap().is_string());
+        assert_eq!(*trace.lock(), vec!["outer", "inner"]);
+
+        let reply = client.call_with_params("secret", json!({})).await.unwrap();
+        assert_eq!(reply.get_error().unwrap().code, ACCESS_DENIED);
+
+        let reply = client.call_with_params("panic", json!({})).await.unwrap();
+        let error = reply.get_error().unwrap();
+        assert_eq!(error.code, -32603);
+        assert_eq!(error.data, Some(json!("handler failed")));
+    }
+}
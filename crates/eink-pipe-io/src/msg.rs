@@ -1,5 +1,9 @@
 use jsonrpc_lite::JsonRpc;
 use remoc::rch;
+use serde_json::Value;
+use tokio::sync::mpsc;
+
+use crate::codec::{Packed, PackedCodec};
 
 // User-defined data structures needs to implement Serialize
 // and Deserialize.
@@ -9,4 +13,42 @@ pub struct IpcMsg {
     // Most Remoc types like channels can be included in serializable
     // data structures for transmission to remote endpoints.
     pub reply_tx: Option<rch::mpsc::Sender<JsonRpc>>,
+    // 请求的取消通道，发送端被触发或丢弃表示调用方不再等待回复
+    #[serde(default)]
+    pub cancel_rx: Option<rch::oneshot::Receiver<()>>,
+    // 流式调用的进度通道，服务端在最终回复之前发送进度
+    #[serde(default)]
+    pub progress_tx: Option<rch::mpsc::Sender<Value>>,
+    // 打包的参数，此时 payload 不携带参数
+    #[serde(default)]
+    pub body_rx: Option<rch::oneshot::Receiver<Packed, PackedCodec>>,
+    // 打包的返回值，服务端在最终回复之前发送
+    #[serde(default)]
+    pub reply_body_tx: Option<rch::oneshot::Sender<Packed, PackedCodec>>,
+}
+
+/// 链路输入的消息，remoc 链路与原始 JSON-RPC 链路产生相同的 `IpcMsg`
+pub(crate) enum Inbound {
+    Remoc(Box<rch::base::Receiver<IpcMsg>>),
+    Raw(mpsc::UnboundedReceiver<IpcMsg>),
+}
+
+impl Inbound {
+    /// 接收下一条消息，链路正常关闭时返回 None
+    ///
+    /// 超过大小限制等无法解析的消息被丢弃，链路继续可用
+    pub(crate) async fn recv(&mut self) -> anyhow::Result<Option<IpcMsg>> {
+        match self {
+            Inbound::Remoc(rx) => loop {
+                match rx.recv().await {
+                    Ok(msg) => return Ok(msg),
+                    Err(err) if !err.is_final() => {
+                        log::warn!("PipeIo: drop invalid message: {err}");
+                    }
+                    Err(err) => return Err(err.into()),
+                }
+            },
+            Inbound::Raw(rx) => Ok(rx.recv().await),
+        }
+    }
 }
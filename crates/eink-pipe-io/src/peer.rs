@@ -0,0 +1,228 @@
+//
+// Copyright (C) Lenovo ThinkBook Gen4 Project.
+//
+// This program is protected under international and China copyright laws as
+// an unpublished work. This program is confidential and proprietary to the
+// copyright owners. Reproduction or disclosure, in whole or in part, or the
+// production of derivative works therefrom without the express permission of
+// the copyright owners is prohibited.
+//
+// All rights reserved.
+//
+
+//! 链路对端句柄
+//!
+//! 消息通过发送队列由独立任务写入 remoc 链路，句柄可以克隆到任意任务或线程中，
+//! 多个调用可以同时等待回复，不需要持有 `Socket` 或 `Client` 的锁。
+
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use jsonrpc_lite::{JsonRpc, Params};
+use remoc::rch;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+use crate::codec::Packed;
+use crate::deadline::{self, CallOptions, DEFAULT_CALL_TIMEOUT};
+use crate::msg::IpcMsg;
+use crate::stream::{CallStream, PROGRESS_BUFFER};
+
+/// 链路对端句柄
+#[derive(Clone)]
+pub struct Peer {
+    outbound: mpsc::UnboundedSender<IpcMsg>,
+}
+
+impl Peer {
+    /// 使用已有的发送队列创建句柄，由原始链路写入消息
+    pub(crate) fn new(outbound: mpsc::UnboundedSender<IpcMsg>) -> Self {
+        Self { outbound }
+    }
+
+    /// 启动发送任务
+    pub(crate) fn spawn(mut tx: rch::base::Sender<IpcMsg>) -> Self {
+        let (outbound, mut outbound_rx) = mpsc::unbounded_channel::<IpcMsg>();
+        tokio::spawn(async move {
+            while let Some(msg) = outbound_rx.recv().await {
+                if let Err(err) = tx.send(msg).await {
+                    log::warn!("PipeIo: cannot send message to peer: {err}");
+                    break;
+                }
+            }
+        });
+        Self { outbound }
+    }
+
+    /// 发送队列，用于向订阅的连接推送通知
+    pub(crate) fn outbound(&self) -> &mpsc::UnboundedSender<IpcMsg> {
+        &self.outbound
+    }
+
+    /// 链路是否已经关闭
+    pub fn is_closed(&self) -> bool {
+        self.outbound.is_closed()
+    }
+
+    /// 向对端发送通知，不等待回复
+    pub fn notify<P: Into<Params>>(&self, method: &str, params: P) -> anyhow::Result<()> {
+        self.outbound
+            .send(IpcMsg {
+                payload: JsonRpc::notification_with_params(method, params),
+                reply_tx: None,
+                cancel_rx: None,
+                progress_tx: None,
+                body_rx: None,
+                reply_body_tx: None,
+            })
+            .map_err(|_| anyhow!("Connection was closed"))
+    }
+
+    /// 调用对端方法，使用默认超时时间
+    pub async fn call_with_params<P: Into<Params>>(
+        &self,
+        method: &str,
+        params: P,
+    ) -> anyhow::Result<JsonRpc> {
+        self.call_with_timeout(method, params, DEFAULT_CALL_TIMEOUT)
+            .await
+    }
+
+    /// 调用对端方法，超时后返回 `CallError::Timeout`
+    pub async fn call_with_timeout<P: Into<Params>>(
+        &self,
+        method: &str,
+        params: P,
+        timeout: Duration,
+    ) -> anyhow::Result<JsonRpc> {
+        self.call_with_options(method, params, CallOptions::new().timeout(timeout))
+            .await
+    }
+
+    /// 使用指定的超时与取消选项调用对端方法
+    pub async fn call_with_options<P: Into<Params>>(
+        &self,
+        method: &str,
+        params: P,
+        options: CallOptions,
+    ) -> anyhow::Result<JsonRpc> {
+        let deadline = options.deadline();
+        match self
+            .call_once(method, params.into(), deadline, &options)
+            .await?
+        {
+            Some(reply) => Ok(reply),
+            None => bail!("Reply is empty"),
+        }
+    }
+
+    /// 流式调用对端方法，先接收进度，再获取最终回复
+    pub async fn call_stream<P: Into<Params>>(
+        &self,
+        method: &str,
+        params: P,
+        options: CallOptions,
+    ) -> anyhow::Result<CallStream> {
+        let id = uuid::Uuid::new_v4().to_string();
+        let (reply_tx, reply_rx) = rch::mpsc::channel(1);
+        let (progress_tx, progress_rx) = rch::mpsc::channel(PROGRESS_BUFFER);
+        let (cancel_tx, cancel_rx) = rch::oneshot::channel();
+        self.outbound
+            .send(IpcMsg {
+                payload: JsonRpc::request_with_params(id, method, params.into()),
+                reply_tx: Some(reply_tx),
+                cancel_rx: Some(cancel_rx),
+                progress_tx: Some(progress_tx),
+                body_rx: None,
+                reply_body_tx: None,
+            })
+            .map_err(|_| anyhow!("Connection was closed"))?;
+
+        let deadline = options.deadline();
+        Ok(CallStream::new(
+            method,
+            progress_rx,
+            reply_rx,
+            cancel_tx,
+            deadline,
+            options,
+        ))
+    }
+
+    /// 使用打包的参数调用对端方法，返回回复与打包的返回值
+    ///
+    /// 对端返回错误或不支持打包时返回值为 None
+    pub async fn call_packed(
+        &self,
+        method: &str,
+        body: Packed,
+        options: CallOptions,
+    ) -> anyhow::Result<(JsonRpc, Option<Packed>)> {
+        let id = uuid::Uuid::new_v4().to_string();
+        let (reply_tx, mut reply_rx) = rch::mpsc::channel(1);
+        let (cancel_tx, cancel_rx) = rch::oneshot::channel();
+        let (body_tx, body_rx) = rch::oneshot::channel();
+        let (reply_body_tx, reply_body_rx) = rch::oneshot::channel();
+        body_tx
+            .send(body)
+            .map_err(|_| anyhow!("Connection was closed"))?;
+        self.outbound
+            .send(IpcMsg {
+                payload: JsonRpc::request(id, method),
+                reply_tx: Some(reply_tx),
+                cancel_rx: Some(cancel_rx),
+                progress_tx: None,
+                body_rx: Some(body_rx),
+                reply_body_tx: Some(reply_body_tx),
+            })
+            .map_err(|_| anyhow!("Connection was closed"))?;
+
+        let deadline = options.deadline();
+        let reply = match deadline::wait_reply(method, &mut reply_rx, cancel_tx, deadline, &options)
+            .await?
+        {
+            Some(reply) => reply,
+            None if self.is_closed() => bail!("Connection was closed"),
+            None => bail!("Reply is empty"),
+        };
+
+        // 服务端在最终回复之前发送返回值，没有发送时发送端已经被丢弃
+        let body = match &reply {
+            JsonRpc::Success(_) => reply_body_rx.await.ok(),
+            _ => None,
+        };
+        Ok((reply, body))
+    }
+
+    /// 发送一次请求，对端未回复时返回 None
+    ///
+    /// 链路在等待期间断开时返回错误，而不是 None
+    pub(crate) async fn call_once(
+        &self,
+        method: &str,
+        params: Params,
+        deadline: Option<Instant>,
+        options: &CallOptions,
+    ) -> anyhow::Result<Option<JsonRpc>> {
+        let id = uuid::Uuid::new_v4().to_string();
+        let (reply_tx, mut reply_rx) = rch::mpsc::channel(1);
+        let (cancel_tx, cancel_rx) = rch::oneshot::channel();
+        self.outbound
+            .send(IpcMsg {
+                payload: JsonRpc::request_with_params(id, method, params),
+                reply_tx: Some(reply_tx),
+                cancel_rx: Some(cancel_rx),
+                progress_tx: None,
+                body_rx: None,
+                reply_body_tx: None,
+            })
+            .map_err(|_| anyhow!("Connection was closed"))?;
+
+        let reply =
+            deadline::wait_reply(method, &mut reply_rx, cancel_tx, deadline, options).await?;
+        if reply.is_none() && self.is_closed() {
+            bail!("Connection was closed");
+        }
+        Ok(reply)
+    }
+}
@@ -0,0 +1,253 @@
+//
+// Copyright (C) Lenovo ThinkBook Gen4 Project.
+//
+// This program is protected under international and China copyright laws as
+// an unpublished work. This program is confidential and proprietary to the
+// copyright owners. Reproduction or disclosure, in whole or in part, or the
+// production of derivative works therefrom without the express permission of
+// the copyright owners is prohibited.
+//
+// All rights reserved.
+//
+
+//! 服务端主动推送与主题订阅
+//!
+//! 客户端通过保留方法 `rpc.subscribe` / `rpc.unsubscribe` 订阅主题，
+//! 服务端通过 `Publisher` 向所有订阅该主题的连接发送 JSON-RPC Notification，
+//! Notification 的 method 即为主题名称。
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use jsonrpc_lite::{JsonRpc, Params};
+use parking_lot::Mutex;
+use serde_json::json;
+use tokio::sync::mpsc;
+
+use crate::msg::IpcMsg;
+
+/// 订阅主题
+pub const METHOD_SUBSCRIBE: &str = "rpc.subscribe";
+
+/// 取消订阅主题
+pub const METHOD_UNSUBSCRIBE: &str = "rpc.unsubscribe";
+
+/// 主题到订阅连接的推送队列
+type TopicSinks = HashMap<String, HashMap<u128, mpsc::UnboundedSender<IpcMsg>>>;
+
+/// 主题到本地订阅的接收队列
+type LocalSinks = HashMap<String, Vec<(u64, mpsc::UnboundedSender<JsonRpc>)>>;
+
+/// 从 `{"topic": "..."}` 参数中取出主题名称
+fn topic_param(req: &JsonRpc) -> Option<String> {
+    match req.get_params() {
+        Some(Params::Map(map)) => map
+            .get("topic")
+            .and_then(|topic| topic.as_str())
+            .map(|topic| topic.to_owned()),
+        _ => None,
+    }
+}
+
+/// 服务端主题发布器，可在任意线程中克隆使用
+#[derive(Clone, Default)]
+pub struct Publisher {
+    topics: Arc<Mutex<TopicSinks>>,
+}
+
+impl Publisher {
+    /// 向订阅 `topic` 的所有连接推送通知，返回成功投递的连接数
+    pub fn publish<P: Into<Params>>(&self, topic: &str, params: P) -> usize {
+        let subscribers: Vec<_> = match self.topics.lock().get(topic) {
+            Some(subscribers) => subscribers
+                .iter()
+                .map(|(conn_id, tx)| (*conn_id, tx.clone()))
+                .collect(),
+            None => return 0,
+        };
+
+        let payload = JsonRpc::notification_with_params(topic, params);
+
+        let mut delivered = 0;
+        for (conn_id, tx) in subscribers {
+            let msg = IpcMsg {
+                payload: payload.clone(),
+                reply_tx: None,
+                cancel_rx: None,
+                progress_tx: None,
+                body_rx: None,
+                reply_body_tx: None,
+            };
+            match tx.send(msg) {
+                Ok(_) => delivered += 1,
+                Err(_) => self.remove_connection(conn_id),
+            }
+        }
+        delivered
+    }
+
+    /// 当前订阅 `topic` 的连接数
+    pub fn subscriber_count(&self, topic: &str) -> usize {
+        self.topics
+            .lock()
+            .get(topic)
+            .map(|subscribers| subscribers.len())
+            .unwrap_or(0)
+    }
+
+    /// 处理订阅相关的保留方法，非保留方法返回 None
+    pub(crate) fn handle_request(
+        &self,
+        conn_id: u128,
+        outbound: &mpsc::UnboundedSender<IpcMsg>,
+        req: &JsonRpc,
+    ) -> Option<JsonRpc> {
+        let method = req.get_method()?;
+        if method != METHOD_SUBSCRIBE && method != METHOD_UNSUBSCRIBE {
+            return None;
+        }
+
+        let id = req.get_id()?;
+        let topic = match topic_param(req) {
+            Some(topic) => topic,
+            None => return Some(JsonRpc::error(id, jsonrpc_lite::Error::invalid_params())),
+        };
+
+        let mut topics = self.topics.lock();
+        if method == METHOD_SUBSCRIBE {
+            topics
+                .entry(topic)
+                .or_default()
+                .insert(conn_id, outbound.clone());
+        } else if let Some(subscribers) = topics.get_mut(&topic) {
+            subscribers.remove(&conn_id);
+            if subscribers.is_empty() {
+                topics.remove(&topic);
+            }
+        }
+
+        Some(JsonRpc::success(id, &json!(true)))
+    }
+
+    /// 连接断开时移除该连接的所有订阅
+    pub(crate) fn remove_connection(&self, conn_id: u128) {
+        let mut topics = self.topics.lock();
+        topics.retain(|_, subscribers| {
+            subscribers.remove(&conn_id);
+            !subscribers.is_empty()
+        });
+    }
+}
+
+/// 客户端本地的主题订阅表
+#[derive(Clone, Default)]
+pub(crate) struct Subscriptions {
+    next_id: Arc<AtomicU64>,
+    sinks: Arc<Mutex<LocalSinks>>,
+}
+
+impl Subscriptions {
+    pub(crate) fn add(&self, topic: &str) -> Subscription {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.sinks
+            .lock()
+            .entry(topic.to_owned())
+            .or_default()
+            .push((id, tx));
+        Subscription {
+            id,
+            topic: topic.to_owned(),
+            rx,
+        }
+    }
+
+    pub(crate) fn remove(&self, subscription: &Subscription) {
+        let mut sinks = self.sinks.lock();
+        if let Some(topic_sinks) = sinks.get_mut(&subscription.topic) {
+            topic_sinks.retain(|(id, _)| *id != subscription.id);
+            if topic_sinks.is_empty() {
+                sinks.remove(&subscription.topic);
+            }
+        }
+    }
+
+    pub(crate) fn remove_topic(&self, topic: &str) {
+        self.sinks.lock().remove(topic);
+    }
+
+    /// 连接断开时结束所有订阅
+    pub(crate) fn clear(&self) {
+        self.sinks.lock().clear();
+    }
+
+    /// 将收到的通知分发给对应主题的订阅者，返回是否存在订阅者
+    pub(crate) fn dispatch(&self, notification: &JsonRpc) -> bool {
+        let topic = match notification.get_method() {
+            Some(topic) => topic,
+            None => return false,
+        };
+
+        let mut sinks = self.sinks.lock();
+        match sinks.get_mut(topic) {
+            Some(topic_sinks) => {
+                // 顺便清理已经被丢弃的订阅
+                topic_sinks.retain(|(_, tx)| tx.send(notification.clone()).is_ok());
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// 客户端的一个主题订阅，通过 `recv` 异步接收通知
+pub struct Subscription {
+    id: u64,
+    topic: String,
+    rx: mpsc::UnboundedReceiver<JsonRpc>,
+}
+
+impl Subscription {
+    /// 订阅的主题名称
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    /// 接收下一条通知，连接断开后返回 None
+    pub async fn recv(&mut self) -> Option<JsonRpc> {
+        self.rx.recv().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use crate::client::Client;
+    use crate::server::Server;
+    use crate::transport::memory;
+
+    #[tokio::test]
+    async fn test_publish_to_subscribers() {
+        let (listener, connector) = memory::channel();
+
+        let mut server = Server::with_listener("memory", listener);
+        let publisher = server.publisher();
+        tokio::spawn(async move { server.listen().await });
+
+        let mut client = Client::with_connector(connector);
+        client.connect().await.unwrap();
+        let mut subscription = client.subscribe("lid_event").await.unwrap();
+
+        assert_eq!(publisher.subscriber_count("lid_event"), 1);
+        assert_eq!(publisher.publish("lid_event", json!({ "lid": "open" })), 1);
+        assert_eq!(publisher.publish("mode_switch_event", json!({})), 0);
+
+        let notification = subscription.recv().await.unwrap();
+        assert_eq!(notification.get_method(), Some("lid_event"));
+
+        client.unsubscribe("lid_event").await.unwrap();
+        assert_eq!(publisher.subscriber_count("lid_event"), 0);
+    }
+}
@@ -0,0 +1,647 @@
+//
+// Copyright (C) Lenovo ThinkBook Gen4 Project.
+//
+// This program is protected under international and China copyright laws as
+// an unpublished work. This program is confidential and proprietary to the
+// copyright owners. Reproduction or disclosure, in whole or in part, or the
+// production of derivative works therefrom without the express permission of
+// the copyright owners is prohibited.
+//
+// All rights reserved.
+//
+
+//! 原始 JSON-RPC 分帧
+//!
+//! Launcher 等 .NET 组件无法使用 remoc 的多路复用协议，可以在连接建立后先发送
+//! 一行 ASCII 前导 `JSONRPC length\n` 或 `JSONRPC line\n`，之后按所选分帧方式
+//! 收发普通的 JSON-RPC 2.0 消息：
+//!
+//! - `length`：每条消息前为 4 字节小端长度，与 .NET `BinaryWriter` 一致
+//! - `line`：每条消息占一行，以 `\n` 结束
+//!
+//! 服务器根据前导自动识别连接使用的协议，同一个端点同时服务 remoc 与原始客户端。
+//! 双方都可以发起请求与通知，请求 id 由发起方保证唯一。原始链路不支持取消、
+//! 进度与打包编码，客户端与服务器在该链路上只使用 JSON 编码。
+//!
+//! 原始链路支持 JSON-RPC 2.0 批量数组：按 `rpc.batch` 非原子执行，回复为数组，
+//! 空数组回复 `invalid_request`，只包含通知的批量请求没有回复。
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use std::{fmt, io};
+
+use jsonrpc_lite::{Id, JsonRpc, Params};
+use parking_lot::Mutex;
+use remoc::rch;
+use serde::Serialize;
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc;
+
+use crate::batch::METHOD_BATCH;
+use crate::deadline::{self, CancellationToken};
+use crate::limits;
+use crate::msg::{Inbound, IpcMsg};
+use crate::peer::Peer;
+
+/// 原始链路前导的固定开头
+pub const PREAMBLE_MAGIC: &[u8; 8] = b"JSONRPC ";
+
+/// 单条消息的默认最大长度，服务器设置了 `max_message_size` 时使用较小的值
+pub const MAX_FRAME_LEN: usize = 64 * 1024 * 1024;
+
+/// 前导中分帧名称的最大长度
+const MAX_FRAMING_NAME_LEN: usize = 32;
+
+/// 识别连接协议的超时时间，期间没有发送前导或 remoc 握手的连接被断开
+pub(crate) const DETECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 原始链路的分帧方式
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Framing {
+    /// 4 字节小端长度前缀
+    LengthPrefixed,
+    /// 每条消息一行
+    NewlineDelimited,
+}
+
+impl Framing {
+    /// 前导中使用的名称
+    pub fn name(&self) -> &'static str {
+        match self {
+            Framing::LengthPrefixed => "length",
+            Framing::NewlineDelimited => "line",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "length" => Some(Framing::LengthPrefixed),
+            "line" => Some(Framing::NewlineDelimited),
+            _ => None,
+        }
+    }
+
+    /// 客户端连接后发送的前导
+    pub fn preamble(&self) -> Vec<u8> {
+        let mut preamble = PREAMBLE_MAGIC.to_vec();
+        preamble.extend_from_slice(self.name().as_bytes());
+        preamble.push(b'\n');
+        preamble
+    }
+
+    /// 读取一条消息，对端关闭时返回 None
+    ///
+    /// 超过 `max_len` 的消息在分配缓冲区前被丢弃，返回 `Frame::TooLarge`
+    async fn read_frame<R: AsyncRead + Unpin>(
+        &self,
+        reader: &mut BufReader<R>,
+        max_len: usize,
+    ) -> io::Result<Option<Frame>> {
+        match self {
+            Framing::LengthPrefixed => {
+                let len = match reader.read_u32_le().await {
+                    Ok(len) => len as usize,
+                    Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                    Err(err) => return Err(err),
+                };
+                if len > max_len {
+                    let mut skipped = (&mut *reader).take(len as u64);
+                    if tokio::io::copy(&mut skipped, &mut tokio::io::sink()).await? < len as u64 {
+                        return Ok(None);
+                    }
+                    return Ok(Some(Frame::TooLarge(len)));
+                }
+                let mut frame = vec![0; len];
+                reader.read_exact(&mut frame).await?;
+                Ok(Some(Frame::Message(frame)))
+            }
+            Framing::NewlineDelimited => loop {
+                let mut frame = Vec::new();
+                let limit = max_len as u64 + 2;
+                if (&mut *reader)
+                    .take(limit)
+                    .read_until(b'\n', &mut frame)
+                    .await?
+                    == 0
+                {
+                    return Ok(None);
+                }
+                if frame.last() != Some(&b'\n') && frame.len() as u64 == limit {
+                    let len = frame.len() + skip_line(reader).await?;
+                    return Ok(Some(Frame::TooLarge(len)));
+                }
+                while matches!(frame.last(), Some(b'\n' | b'\r')) {
+                    frame.pop();
+                }
+                if frame.len() > max_len {
+                    return Ok(Some(Frame::TooLarge(frame.len())));
+                }
+                // 忽略空行
+                if !frame.is_empty() {
+                    return Ok(Some(Frame::Message(frame)));
+                }
+            },
+        }
+    }
+
+    /// 写入一条消息
+    async fn write_frame<W: AsyncWrite + Unpin>(
+        &self,
+        writer: &mut W,
+        msg: &Outgoing,
+    ) -> io::Result<()> {
+        // JSON 序列化结果不包含换行，可以直接按行分帧
+        let frame = serde_json::to_vec(msg)?;
+        match self {
+            Framing::LengthPrefixed => {
+                writer.write_u32_le(frame.len() as u32).await?;
+                writer.write_all(&frame).await?;
+            }
+            Framing::NewlineDelimited => {
+                writer.write_all(&frame).await?;
+                writer.write_all(b"\n").await?;
+            }
+        }
+        writer.flush().await
+    }
+}
+
+impl fmt::Display for Framing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// 写入对端的消息，批量请求的回复为数组
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Outgoing {
+    Message(JsonRpc),
+    Batch(Vec<JsonRpc>),
+}
+
+impl From<JsonRpc> for Outgoing {
+    fn from(msg: JsonRpc) -> Self {
+        Outgoing::Message(msg)
+    }
+}
+
+/// 服务端识别的连接协议
+pub(crate) enum Detected {
+    /// 原始 JSON-RPC 链路，前导已经读取
+    Raw(Framing),
+    /// remoc 链路，携带已经读取的字节，需要在建立链路时重新送入
+    Remoc(Vec<u8>),
+}
+
+/// 读取连接开头的字节，识别原始链路的前导
+///
+/// remoc 链路在建立时双方都会立即发送握手消息，不会因为等待前导而阻塞
+pub(crate) async fn detect<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<Detected> {
+    let mut magic = [0; PREAMBLE_MAGIC.len()];
+    reader.read_exact(&mut magic).await?;
+    if &magic != PREAMBLE_MAGIC {
+        return Ok(Detected::Remoc(magic.to_vec()));
+    }
+
+    // 逐字节读取分帧名称，避免读取前导之后的消息
+    let mut name = Vec::new();
+    loop {
+        match reader.read_u8().await? {
+            b'\n' => break,
+            byte if name.len() < MAX_FRAMING_NAME_LEN => name.push(byte),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Raw JSON-RPC preamble is too long",
+                ))
+            }
+        }
+    }
+    let name = String::from_utf8_lossy(&name);
+    match Framing::from_name(name.trim_end_matches('\r')) {
+        Some(framing) => Ok(Detected::Raw(framing)),
+        None => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Unknown raw JSON-RPC framing '{name}'"),
+        )),
+    }
+}
+
+/// 读取到的一条消息
+enum Frame {
+    Message(Vec<u8>),
+    /// 超过长度限制的消息已被丢弃，参数为已知的长度
+    TooLarge(usize),
+}
+
+/// 丢弃当前行的剩余部分，返回丢弃的字节数
+async fn skip_line<R: AsyncRead + Unpin>(reader: &mut BufReader<R>) -> io::Result<usize> {
+    let mut skipped = 0;
+    loop {
+        let buf = reader.fill_buf().await?;
+        if buf.is_empty() {
+            return Ok(skipped);
+        }
+        let (len, done) = match buf.iter().position(|b| *b == b'\n') {
+            Some(pos) => (pos + 1, true),
+            None => (buf.len(), false),
+        };
+        reader.consume(len);
+        skipped += len;
+        if done {
+            return Ok(skipped);
+        }
+    }
+}
+
+/// 客户端发送前导并建立原始链路
+pub(crate) async fn connect<R, W>(
+    framing: Framing,
+    reader: R,
+    mut writer: W,
+) -> io::Result<(Peer, Inbound)>
+where
+    R: AsyncRead + Send + Unpin + 'static,
+    W: AsyncWrite + Send + Unpin + 'static,
+{
+    writer.write_all(&framing.preamble()).await?;
+    writer.flush().await?;
+    Ok(spawn_link(framing, MAX_FRAME_LEN, reader, writer))
+}
+
+/// 在字节流上建立原始链路
+///
+/// 返回的 `Peer` 与 `Inbound` 与 remoc 链路的用法相同：发送的请求登记回复通道，
+/// 收到的请求携带本地回复通道，处理结果写回对端。超过 `max_frame_len` 的消息
+/// 不会被读入内存，对端收到 `MESSAGE_TOO_LARGE` 错误
+pub(crate) fn spawn_link<R, W>(
+    framing: Framing,
+    max_frame_len: usize,
+    reader: R,
+    mut writer: W,
+) -> (Peer, Inbound)
+where
+    R: AsyncRead + Send + Unpin + 'static,
+    W: AsyncWrite + Send + Unpin + 'static,
+{
+    // 等待对端回复的请求
+    let pending: Arc<Mutex<HashMap<String, rch::mpsc::Sender<JsonRpc>>>> = Default::default();
+    let closed = CancellationToken::new();
+
+    // 所有消息由同一个任务写入，避免分帧交错
+    let (frames_tx, mut frames_rx) = mpsc::unbounded_channel::<Outgoing>();
+    tokio::spawn(async move {
+        while let Some(msg) = frames_rx.recv().await {
+            if let Err(err) = framing.write_frame(&mut writer, &msg).await {
+                log::warn!("PipeIo: cannot write raw JSON-RPC frame: {err}");
+                break;
+            }
+        }
+    });
+
+    // 发送队列，请求的回复通道在写入前登记
+    let (outbound, mut outbound_rx) = mpsc::unbounded_channel::<IpcMsg>();
+    {
+        let pending = pending.clone();
+        let frames_tx = frames_tx.clone();
+        let closed = closed.clone();
+        tokio::spawn(async move {
+            loop {
+                let msg = tokio::select! {
+                    msg = outbound_rx.recv() => match msg {
+                        Some(msg) => msg,
+                        None => break,
+                    },
+                    _ = closed.cancelled() => break,
+                };
+                if let (Some(id), Some(reply_tx)) = (msg.payload.get_id(), msg.reply_tx) {
+                    pending.lock().insert(deadline::id_key(&id), reply_tx);
+                }
+                if frames_tx.send(msg.payload.into()).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    // 读取对端消息，请求与通知交给会话处理，回复交给等待的调用方
+    let (inbound_tx, inbound_rx) = mpsc::unbounded_channel::<IpcMsg>();
+    tokio::spawn(async move {
+        let mut reader = BufReader::new(reader);
+        loop {
+            let frame = match framing.read_frame(&mut reader, max_frame_len).await {
+                Ok(Some(Frame::Message(frame))) => frame,
+                Ok(Some(Frame::TooLarge(len))) => {
+                    log::warn!("PipeIo: raw JSON-RPC frame too large: {len} > {max_frame_len}");
+                    let reply = limits::too_large_reply(Id::None(()), len, max_frame_len);
+                    let _ = frames_tx.send(reply.into());
+                    continue;
+                }
+                Ok(None) => break,
+                Err(err) => {
+                    log::warn!("PipeIo: cannot read raw JSON-RPC frame: {err}");
+                    break;
+                }
+            };
+            let value = match serde_json::from_slice::<Value>(&frame) {
+                Ok(value) => value,
+                Err(err) => {
+                    log::warn!("PipeIo: invalid raw JSON-RPC message: {err}");
+                    let reply = JsonRpc::error(Id::None(()), jsonrpc_lite::Error::parse_error());
+                    let _ = frames_tx.send(reply.into());
+                    continue;
+                }
+            };
+            let payload = match value {
+                Value::Array(items) => match batch_request(items, &frames_tx) {
+                    Some(payload) => payload,
+                    None => continue,
+                },
+                value => match serde_json::from_value::<JsonRpc>(value) {
+                    Ok(payload) => payload,
+                    Err(err) => {
+                        log::warn!("PipeIo: invalid raw JSON-RPC message: {err}");
+                        let error = jsonrpc_lite::Error::invalid_request();
+                        let _ = frames_tx.send(JsonRpc::error(Id::None(()), error).into());
+                        continue;
+                    }
+                },
+            };
+
+            match &payload {
+                JsonRpc::Request(_) => {
+                    let is_batch = payload.get_method() == Some(METHOD_BATCH)
+                        && matches!(payload.get_params(), Some(Params::Array(_)));
+                    let (reply_tx, mut reply_rx) = rch::mpsc::channel(1);
+                    let frames_tx = frames_tx.clone();
+                    tokio::spawn(async move {
+                        if let Ok(Some(reply)) = reply_rx.recv().await {
+                            let _ = match is_batch {
+                                true => send_batch_reply(&frames_tx, reply),
+                                false => frames_tx.send(reply.into()).is_ok(),
+                            };
+                        }
+                    });
+                    let _ = inbound_tx.send(IpcMsg {
+                        payload,
+                        reply_tx: Some(reply_tx),
+                        cancel_rx: None,
+                        progress_tx: None,
+                        body_rx: None,
+                        reply_body_tx: None,
+                    });
+                }
+                JsonRpc::Notification(_) => {
+                    let _ = inbound_tx.send(IpcMsg {
+                        payload,
+                        reply_tx: None,
+                        cancel_rx: None,
+                        progress_tx: None,
+                        body_rx: None,
+                        reply_body_tx: None,
+                    });
+                }
+                JsonRpc::Success(_) | JsonRpc::Error(_) => {
+                    let key = payload.get_id().map(|id| deadline::id_key(&id));
+                    let reply_tx = key.and_then(|key| pending.lock().remove(&key));
+                    match reply_tx {
+                        Some(reply_tx) => {
+                            let _ = reply_tx.send(payload).await;
+                        }
+                        None => log::warn!("PipeIo: unexpected raw JSON-RPC reply: {payload:?}"),
+                    }
+                }
+            }
+        }
+
+        // 链路关闭，等待回复的调用方收到空回复
+        closed.cancel();
+        pending.lock().clear();
+    });
+
+    (Peer::new(outbound), Inbound::Raw(inbound_rx))
+}
+
+/// 将批量数组转换为非原子的 `rpc.batch` 请求，不需要交给会话处理时返回 None
+///
+/// 空数组回复 `invalid_request`；无法解析的元素替换为错误消息，会话按位置回复
+/// `invalid_request`
+fn batch_request(
+    items: Vec<Value>,
+    frames_tx: &mpsc::UnboundedSender<Outgoing>,
+) -> Option<JsonRpc> {
+    if items.is_empty() {
+        let reply = JsonRpc::error(Id::None(()), jsonrpc_lite::Error::invalid_request());
+        let _ = frames_tx.send(reply.into());
+        return None;
+    }
+
+    let items: Vec<Value> = items
+        .into_iter()
+        .map(
+            |item| match serde_json::from_value::<JsonRpc>(item.clone()) {
+                Ok(JsonRpc::Request(_) | JsonRpc::Notification(_)) => item,
+                _ => {
+                    let error = jsonrpc_lite::Error::invalid_request();
+                    serde_json::to_value(JsonRpc::error(Id::None(()), error)).unwrap_or_default()
+                }
+            },
+        )
+        .collect();
+
+    let id = format!("{METHOD_BATCH}-{}", uuid::Uuid::new_v4());
+    Some(JsonRpc::request_with_params(id, METHOD_BATCH, items))
+}
+
+/// 写入批量请求的回复数组，只包含通知时不回复
+fn send_batch_reply(frames_tx: &mpsc::UnboundedSender<Outgoing>, reply: JsonRpc) -> bool {
+    let replies = match reply.get_result() {
+        Some(Value::Array(replies)) => replies
+            .iter()
+            .cloned()
+            .filter_map(|reply| serde_json::from_value::<JsonRpc>(reply).ok())
+            .collect::<Vec<_>>(),
+        _ => {
+            let error = match reply.get_error() {
+                Some(error) => error.clone(),
+                None => jsonrpc_lite::Error::internal_error(),
+            };
+            return frames_tx
+                .send(JsonRpc::error(Id::None(()), error).into())
+                .is_ok();
+        }
+    };
+    if replies.is_empty() {
+        return true;
+    }
+    frames_tx.send(Outgoing::Batch(replies)).is_ok()
+}
+
+#[cfg(test)]
+mod test {
+    use jsonrpc_lite::JsonRpc;
+    use serde_json::{json, Value};
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+
+    use super::Framing;
+    use crate::client::Client;
+    use crate::limits::{Limits, MESSAGE_TOO_LARGE};
+    use crate::server::Server;
+    use crate::transport::{memory, Connector};
+
+    #[tokio::test]
+    async fn test_raw_and_remoc_clients() {
+        let (listener, connector) = memory::channel();
+
+        let mut server = Server::with_listener("memory", listener);
+        server.on_connection(|socket, _| {
+            socket.lock().on_request_async(|ctx, req| async move {
+                let method = req.get_method().unwrap_or_default().to_owned();
+                if method == "callback" {
+                    // 反向调用客户端
+                    let reply = ctx
+                        .peer
+                        .call_with_params("whoami", json!({}))
+                        .await
+                        .unwrap();
+                    return JsonRpc::success(ctx.id, reply.get_result().unwrap());
+                }
+                JsonRpc::success(ctx.id, &json!({ "method": method }))
+            });
+            0
+        });
+        tokio::spawn(async move { server.listen().await });
+
+        // remoc 客户端与原始客户端使用同一个端点
+        let mut client = Client::with_connector(connector.clone());
+        client.connect().await.unwrap();
+        let reply = client.call_with_params("ping", json!({})).await.unwrap();
+        assert_eq!(reply.get_result(), Some(&json!({ "method": "ping" })));
+
+        let mut raw = Client::with_connector(connector.clone());
+        raw.set_raw_framing(Some(Framing::LengthPrefixed));
+        raw.on_request(|id, _| JsonRpc::success(id, &json!("raw")))
+            .await;
+        raw.connect().await.unwrap();
+        assert!(raw.server_info().is_some());
+        let reply = raw.call_with_params("callback", json!({})).await.unwrap();
+        assert_eq!(reply.get_result(), Some(&json!("raw")));
+
+        // 不使用本库的客户端按行收发
+        let stream = connector.connect().await.unwrap();
+        let (reader, mut writer) = tokio::io::split(stream);
+        let mut reader = BufReader::new(reader);
+        writer
+            .write_all(&Framing::NewlineDelimited.preamble())
+            .await
+            .unwrap();
+        writer
+            .write_all(b"{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"hello\",\"params\":{}}\n")
+            .await
+            .unwrap();
+        let mut line = String::new();
+        reader.read_line(&mut line).await.unwrap();
+        let reply: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(reply["id"], json!(1));
+        assert_eq!(reply["result"], json!({ "method": "hello" }));
+
+        // 无法解析的消息回复 parse_error，链路保持可用
+        writer.write_all(b"not json\n").await.unwrap();
+        line.clear();
+        reader.read_line(&mut line).await.unwrap();
+        let reply: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(reply["error"]["code"], json!(-32700));
+
+        // 批量数组按顺序回复，无效的元素回复 invalid_request
+        writer
+            .write_all(b"[{\"jsonrpc\":\"2.0\",\"id\":2,\"method\":\"a\"},{\"jsonrpc\":\"2.0\",\"method\":\"n\"},1]\n")
+            .await
+            .unwrap();
+        line.clear();
+        reader.read_line(&mut line).await.unwrap();
+        let replies: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(replies.as_array().unwrap().len(), 2);
+        assert_eq!(replies[0]["result"], json!({ "method": "a" }));
+        assert_eq!(replies[1]["error"]["code"], json!(-32600));
+
+        // 空数组是无效请求，只包含通知的批量请求没有回复
+        for batch in [&b"[]\n"[..], b"[{\"jsonrpc\":\"2.0\",\"method\":\"n\"}]\n"] {
+            writer.write_all(batch).await.unwrap();
+        }
+        writer
+            .write_all(b"{\"jsonrpc\":\"2.0\",\"id\":3,\"method\":\"b\"}\n")
+            .await
+            .unwrap();
+        line.clear();
+        reader.read_line(&mut line).await.unwrap();
+        let reply: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(reply["error"]["code"], json!(-32600));
+        line.clear();
+        reader.read_line(&mut line).await.unwrap();
+        let reply: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(reply["id"], json!(3));
+    }
+
+    #[tokio::test]
+    async fn test_raw_frame_size_limit() {
+        let (listener, connector) = memory::channel();
+
+        let mut server = Server::with_listener("memory", listener);
+        server.set_limits(Limits::new().max_message_size(256));
+        server.on_connection(|socket, _| {
+            socket
+                .lock()
+                .on_request_async(|ctx, _| async move { JsonRpc::success(ctx.id, &json!("ok")) });
+            0
+        });
+        tokio::spawn(async move { server.listen().await });
+
+        let hello = b"{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"hello\",\"params\":{}}";
+        let oversized = vec![b'x'; 4096];
+
+        // 超过限制的消息被丢弃并回复错误，之后的消息正常处理
+        let stream = connector.connect().await.unwrap();
+        let (reader, mut writer) = tokio::io::split(stream);
+        let mut reader = BufReader::new(reader);
+        writer
+            .write_all(&Framing::NewlineDelimited.preamble())
+            .await
+            .unwrap();
+        writer.write_all(&oversized).await.unwrap();
+        writer.write_all(b"\n").await.unwrap();
+        writer.write_all(hello).await.unwrap();
+        writer.write_all(b"\n").await.unwrap();
+
+        let mut line = String::new();
+        reader.read_line(&mut line).await.unwrap();
+        let reply: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(reply["error"]["code"], json!(MESSAGE_TOO_LARGE));
+        line.clear();
+        reader.read_line(&mut line).await.unwrap();
+        let reply: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(reply["result"], json!("ok"));
+
+        // 长度前缀分帧在读取内容前检查长度
+        let stream = connector.connect().await.unwrap();
+        let (mut reader, mut writer) = tokio::io::split(stream);
+        writer
+            .write_all(&Framing::LengthPrefixed.preamble())
+            .await
+            .unwrap();
+        writer.write_u32_le(oversized.len() as u32).await.unwrap();
+        writer.write_all(&oversized).await.unwrap();
+        writer.write_u32_le(hello.len() as u32).await.unwrap();
+        writer.write_all(hello).await.unwrap();
+
+        for expected in ["error", "result"] {
+            let len = reader.read_u32_le().await.unwrap();
+            let mut frame = vec![0; len as usize];
+            reader.read_exact(&mut frame).await.unwrap();
+            let reply: Value = serde_json::from_slice(&frame).unwrap();
+            assert!(reply.get(expected).is_some(), "{reply}");
+        }
+    }
+}
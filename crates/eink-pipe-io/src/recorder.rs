@@ -0,0 +1,108 @@
+//
+// Copyright (C) Lenovo ThinkBook Gen4 Project.
+//
+// This program is protected under international and China copyright laws as
+// an unpublished work. This program is confidential and proprietary to the
+// copyright owners. Reproduction or disclosure, in whole or in part, or the
+// production of derivative works therefrom without the express permission of
+// the copyright owners is prohibited.
+//
+// All rights reserved.
+//
+
+//! 通信记录
+//!
+//! 服务器设置记录器后，每个请求、通知与回复按接收或发送顺序写入 JSON-lines 文件，
+//! 每行包含时间戳、连接 id 与完整的 JSON-RPC 消息。记录文件可以由 `replay` 回放。
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use jsonrpc_lite::JsonRpc;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::registry::ConnectionId;
+
+/// 记录的消息类型
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordKind {
+    /// 客户端发送的请求
+    Request,
+    /// 客户端发送的通知
+    Notification,
+    /// 服务端发送的回复
+    Reply,
+}
+
+/// 一条通信记录，对应记录文件中的一行
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    /// Unix 时间戳，单位毫秒
+    pub timestamp: u64,
+    /// 连接 id，使用 uuid 格式
+    pub conn_id: String,
+    pub kind: RecordKind,
+    pub message: JsonRpc,
+}
+
+/// 通信记录器，可以克隆到任意任务或线程中使用
+#[derive(Clone)]
+pub struct Recorder {
+    writer: Arc<Mutex<BufWriter<File>>>,
+}
+
+impl Recorder {
+    /// 打开记录文件，文件已存在时追加写入
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            writer: Arc::new(Mutex::new(BufWriter::new(file))),
+        })
+    }
+
+    /// 写入一条记录，写入失败时只记录日志，不影响请求处理
+    pub(crate) fn record(&self, conn_id: ConnectionId, kind: RecordKind, message: &JsonRpc) {
+        let record = Record {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+            conn_id: uuid::Uuid::from_u128(conn_id).to_string(),
+            kind,
+            message: message.clone(),
+        };
+
+        let mut writer = self.writer.lock();
+        let result = serde_json::to_writer(&mut *writer, &record)
+            .map_err(io::Error::from)
+            .and_then(|_| writer.write_all(b"\n"))
+            .and_then(|_| writer.flush());
+        if let Err(err) = result {
+            log::warn!("PipeIo::Recorder: cannot write record: {err}");
+        }
+    }
+}
+
+/// 读取记录文件，忽略空行
+pub fn read_records<P: AsRef<Path>>(path: P) -> anyhow::Result<Vec<Record>> {
+    let path = path.as_ref();
+    let file = File::open(path).with_context(|| format!("Cannot open {}", path.display()))?;
+
+    let mut records = Vec::new();
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record = serde_json::from_str(&line)
+            .with_context(|| format!("Invalid record at {}:{}", path.display(), index + 1))?;
+        records.push(record);
+    }
+    Ok(records)
+}
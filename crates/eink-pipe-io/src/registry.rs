@@ -0,0 +1,262 @@
+//
+// Copyright (C) Lenovo ThinkBook Gen4 Project.
+//
+// This program is protected under international and China copyright laws as
+// an unpublished work. This program is confidential and proprietary to the
+// copyright owners. Reproduction or disclosure, in whole or in part, or the
+// production of derivative works therefrom without the express permission of
+// the copyright owners is prohibited.
+//
+// All rights reserved.
+//
+
+//! 连接注册表
+//!
+//! 服务器为每个连接分配稳定的连接 id，并记录对端进程凭据、连接时间与最后活动时间。
+//! 注册表可以枚举当前存活的连接，并向全部或筛选后的对端广播通知与调用。
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use jsonrpc_lite::{JsonRpc, Params};
+use parking_lot::Mutex;
+use tokio::task::JoinSet;
+
+use crate::access::PeerIdentity;
+use crate::peer::Peer;
+use crate::transport::PeerCredentials;
+
+/// 连接 id，在服务器生命周期内唯一
+pub type ConnectionId = u128;
+
+/// 连接信息
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConnectionInfo {
+    pub id: ConnectionId,
+    /// 对端进程凭据
+    pub credentials: PeerCredentials,
+    /// 根据凭据解析的对端身份
+    pub identity: PeerIdentity,
+    pub connected_at: SystemTime,
+    /// 最后一次收到对端消息的时间
+    pub last_activity: SystemTime,
+}
+
+impl ConnectionInfo {
+    pub(crate) fn new(credentials: PeerCredentials) -> Self {
+        let now = SystemTime::now();
+        Self {
+            id: uuid::Uuid::new_v4().as_u128(),
+            identity: PeerIdentity::resolve(&credentials),
+            credentials,
+            connected_at: now,
+            last_activity: now,
+        }
+    }
+
+    /// 对端进程 id
+    pub fn pid(&self) -> Option<u32> {
+        self.credentials.pid
+    }
+
+    /// 距离最后一次活动的时间
+    pub fn idle(&self) -> Duration {
+        SystemTime::now()
+            .duration_since(self.last_activity)
+            .unwrap_or_default()
+    }
+}
+
+struct Entry {
+    info: ConnectionInfo,
+    peer: Peer,
+}
+
+/// 连接注册表，可以克隆到任意任务或线程中使用
+#[derive(Clone, Default)]
+pub struct Registry {
+    entries: Arc<Mutex<HashMap<ConnectionId, Entry>>>,
+}
+
+impl Registry {
+    pub(crate) fn insert(&self, info: ConnectionInfo, peer: Peer) {
+        self.entries.lock().insert(info.id, Entry { info, peer });
+    }
+
+    pub(crate) fn remove(&self, id: ConnectionId) {
+        self.entries.lock().remove(&id);
+    }
+
+    /// 更新最后活动时间
+    pub(crate) fn touch(&self, id: ConnectionId) {
+        if let Some(entry) = self.entries.lock().get_mut(&id) {
+            entry.info.last_activity = SystemTime::now();
+        }
+    }
+
+    /// 当前存活的连接，按连接时间排序
+    pub fn connections(&self) -> Vec<ConnectionInfo> {
+        let mut connections: Vec<_> = self
+            .entries
+            .lock()
+            .values()
+            .map(|entry| entry.info.clone())
+            .collect();
+        connections.sort_by_key(|info| info.connected_at);
+        connections
+    }
+
+    /// 查找连接信息
+    pub fn get(&self, id: ConnectionId) -> Option<ConnectionInfo> {
+        self.entries.lock().get(&id).map(|entry| entry.info.clone())
+    }
+
+    /// 连接的对端句柄
+    pub fn peer(&self, id: ConnectionId) -> Option<Peer> {
+        self.entries.lock().get(&id).map(|entry| entry.peer.clone())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.lock().is_empty()
+    }
+
+    /// 筛选连接，返回连接 id 与对端句柄
+    fn select<F>(&self, filter: F) -> Vec<(ConnectionId, Peer)>
+    where
+        F: Fn(&ConnectionInfo) -> bool,
+    {
+        self.entries
+            .lock()
+            .values()
+            .filter(|entry| filter(&entry.info))
+            .map(|entry| (entry.info.id, entry.peer.clone()))
+            .collect()
+    }
+
+    /// 向所有连接发送通知，返回发送成功的连接数量
+    pub fn broadcast<P: Into<Params>>(&self, method: &str, params: P) -> usize {
+        self.broadcast_to(|_| true, method, params)
+    }
+
+    /// 向满足条件的连接发送通知，返回发送成功的连接数量
+    pub fn broadcast_to<F, P>(&self, filter: F, method: &str, params: P) -> usize
+    where
+        F: Fn(&ConnectionInfo) -> bool,
+        P: Into<Params>,
+    {
+        let params = params.into();
+        self.select(filter)
+            .into_iter()
+            .filter(|(_, peer)| peer.notify(method, params.clone()).is_ok())
+            .count()
+    }
+
+    /// 并发调用所有连接的方法，返回每个连接的调用结果
+    pub async fn broadcast_call<P: Into<Params>>(
+        &self,
+        method: &str,
+        params: P,
+    ) -> Vec<(ConnectionId, anyhow::Result<JsonRpc>)> {
+        self.broadcast_call_to(|_| true, method, params).await
+    }
+
+    /// 并发调用满足条件的连接的方法，返回每个连接的调用结果
+    ///
+    /// 每个调用使用默认超时时间，单个连接失败不影响其它连接
+    pub async fn broadcast_call_to<F, P>(
+        &self,
+        filter: F,
+        method: &str,
+        params: P,
+    ) -> Vec<(ConnectionId, anyhow::Result<JsonRpc>)>
+    where
+        F: Fn(&ConnectionInfo) -> bool,
+        P: Into<Params>,
+    {
+        let params = params.into();
+        let mut calls = JoinSet::new();
+        for (id, peer) in self.select(filter) {
+            let method = method.to_owned();
+            let params = params.clone();
+            calls.spawn(async move { (id, peer.call_with_params(&method, params).await) });
+        }
+
+        let mut results = Vec::with_capacity(calls.len());
+        while let Some(result) = calls.join_next().await {
+            if let Ok(result) = result {
+                results.push(result);
+            }
+        }
+        results
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use jsonrpc_lite::JsonRpc;
+    use serde_json::json;
+
+    use crate::client::Client;
+    use crate::server::Server;
+    use crate::transport::{memory, PeerCredentials};
+
+    #[tokio::test]
+    async fn test_registry_identity_and_broadcast() {
+        let (listener, connector) = memory::channel();
+
+        let mut server = Server::with_listener("memory", listener);
+        server.on_connection(|socket, _| {
+            socket.lock().on_request_async(|ctx, _| async move {
+                JsonRpc::success(ctx.id, &json!(ctx.connection.pid()))
+            });
+            0
+        });
+        let registry = server.registry();
+        tokio::spawn(async move { server.listen().await });
+
+        let credentials = PeerCredentials {
+            pid: Some(42),
+            ..Default::default()
+        };
+        let mut first = Client::with_connector(connector.clone());
+        first.connect().await.unwrap();
+        let mut second = Client::with_connector(connector.with_credentials(credentials));
+        second
+            .on_request(|id, req| JsonRpc::success(id, &json!(req.get_method())))
+            .await;
+        second.connect().await.unwrap();
+
+        // 请求上下文携带对端身份
+        let reply = second.call_with_params("whoami", json!({})).await.unwrap();
+        assert_eq!(reply.get_result(), Some(&json!(42)));
+
+        let connections = registry.connections();
+        assert_eq!(connections.len(), 2);
+        assert_eq!(connections[0].pid(), Some(std::process::id()));
+        assert_eq!(connections[1].pid(), Some(42));
+        assert!(connections[1].last_activity >= connections[1].connected_at);
+
+        // 只调用筛选后的对端
+        let results = registry
+            .broadcast_call_to(|info| info.pid() == Some(42), "ping", json!({}))
+            .await;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0, connections[1].id);
+        let reply = results[0].1.as_ref().unwrap();
+        assert_eq!(reply.get_result(), Some(&json!("ping")));
+
+        assert_eq!(registry.broadcast("event", json!({})), 2);
+
+        // 连接断开后从注册表移除
+        first.disconnect();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(registry.len(), 1);
+    }
+}
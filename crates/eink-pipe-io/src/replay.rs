@@ -0,0 +1,264 @@
+//
+// Copyright (C) Lenovo ThinkBook Gen4 Project.
+//
+// This program is protected under international and China copyright laws as
+// an unpublished work. This program is confidential and proprietary to the
+// copyright owners. Reproduction or disclosure, in whole or in part, or the
+// production of derivative works therefrom without the express permission of
+// the copyright owners is prohibited.
+//
+// All rights reserved.
+//
+
+//! 通信回放
+//!
+//! 按记录顺序重新发送 `Recorder` 记录的请求与通知，每个记录的连接使用独立的客户端，
+//! 并将服务端的回复与记录的回复比较。回复的 id 不参与比较。
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use jsonrpc_lite::{JsonRpc, Params};
+use serde_json::{json, Value};
+
+use crate::batch::METHOD_BATCH;
+use crate::client::Client;
+use crate::deadline;
+use crate::recorder::{Record, RecordKind};
+
+/// 回复与记录不一致的请求
+#[derive(Clone, Debug)]
+pub struct Mismatch {
+    /// 请求在记录中的序号
+    pub index: usize,
+    pub conn_id: String,
+    pub method: String,
+    pub expected: Value,
+    pub actual: Value,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "#{} {} (conn {})", self.index, self.method, self.conn_id)?;
+        writeln!(f, "  expected: {}", self.expected)?;
+        write!(f, "  actual:   {}", self.actual)
+    }
+}
+
+/// 回放结果
+#[derive(Clone, Debug, Default)]
+pub struct ReplayReport {
+    /// 重新发送的请求数量
+    pub replayed: usize,
+    /// 记录中没有回复、无法比较的请求数量
+    pub unanswered: usize,
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl ReplayReport {
+    /// 所有回复都与记录一致
+    pub fn is_clean(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+/// 回放器
+///
+/// ```ignore
+/// let records = recorder::read_records("trace.jsonl")?;
+/// let report = Replayer::new(|| Client::new(PIPE_NAME)).run(&records).await?;
+/// ```
+pub struct Replayer<F> {
+    new_client: F,
+    ignored: HashSet<String>,
+}
+
+impl<F> Replayer<F>
+where
+    F: Fn() -> Client,
+{
+    /// `new_client` 为每个记录的连接创建一个客户端
+    pub fn new(new_client: F) -> Self {
+        Self {
+            new_client,
+            ignored: HashSet::new(),
+        }
+    }
+
+    /// 重新发送该方法，但不比较回复，用于结果与时间或设备状态相关的方法
+    pub fn ignore(mut self, method: &str) -> Self {
+        self.ignored.insert(method.to_owned());
+        self
+    }
+
+    /// 按记录顺序回放，连接失败时返回错误
+    pub async fn run(&self, records: &[Record]) -> anyhow::Result<ReplayReport> {
+        // 记录的回复，按连接与请求 id 索引
+        let replies: HashMap<(&str, String), &JsonRpc> = records
+            .iter()
+            .filter(|record| record.kind == RecordKind::Reply)
+            .filter_map(|record| {
+                let id = record.message.get_id()?;
+                Some((
+                    (record.conn_id.as_str(), deadline::id_key(&id)),
+                    &record.message,
+                ))
+            })
+            .collect();
+
+        let mut clients: HashMap<&str, Client> = HashMap::new();
+        let mut report = ReplayReport::default();
+
+        for (index, record) in records.iter().enumerate() {
+            if record.kind == RecordKind::Reply {
+                continue;
+            }
+            let method = match record.message.get_method() {
+                Some(method) => method,
+                None => continue,
+            };
+            // 握手、订阅等保留方法由客户端自行处理
+            if method.starts_with("rpc.") && method != METHOD_BATCH {
+                continue;
+            }
+
+            if !clients.contains_key(record.conn_id.as_str()) {
+                let mut client = (self.new_client)();
+                client.connect().await?;
+                clients.insert(&record.conn_id, client);
+            }
+            let client = clients.get_mut(record.conn_id.as_str()).unwrap();
+            let params = record.message.get_params().unwrap_or(Params::None(()));
+
+            if record.kind == RecordKind::Notification {
+                if let Some(peer) = client.peer() {
+                    let _ = peer.notify(method, params);
+                }
+                continue;
+            }
+
+            report.replayed += 1;
+            let actual = match client.call_with_params(method, params).await {
+                Ok(reply) => outcome(&reply),
+                Err(err) => json!({ "failed": err.to_string() }),
+            };
+
+            let expected = match record
+                .message
+                .get_id()
+                .and_then(|id| replies.get(&(record.conn_id.as_str(), deadline::id_key(&id))))
+            {
+                Some(expected) => outcome(expected),
+                None => {
+                    report.unanswered += 1;
+                    continue;
+                }
+            };
+
+            if expected != actual && !self.ignored.contains(method) {
+                report.mismatches.push(Mismatch {
+                    index,
+                    conn_id: record.conn_id.clone(),
+                    method: method.to_owned(),
+                    expected,
+                    actual,
+                });
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+/// 回复中参与比较的部分
+fn outcome(reply: &JsonRpc) -> Value {
+    match reply.get_error() {
+        Some(error) => json!({ "error": error }),
+        None => json!({ "result": reply.get_result() }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use jsonrpc_lite::JsonRpc;
+    use serde_json::json;
+
+    use super::Replayer;
+    use crate::client::Client;
+    use crate::recorder::{self, RecordKind, Recorder};
+    use crate::server::Server;
+    use crate::transport::memory;
+
+    /// `add` 返回两数之和，`bias` 用于模拟行为变化后的服务端
+    fn adder(listener: memory::MemoryListener, bias: i64) -> Server {
+        let mut server = Server::with_listener("memory", listener);
+        server.on_connection(move |socket, _| {
+            socket.lock().on_request(move |_, id, req| {
+                let params = serde_json::to_value(req.get_params()).unwrap();
+                let sum = params["a"].as_i64().unwrap() + params["b"].as_i64().unwrap();
+                JsonRpc::success(id, &json!(sum + bias))
+            });
+            0
+        });
+        server
+    }
+
+    #[tokio::test]
+    async fn test_record_and_replay() {
+        let path = std::env::temp_dir().join(format!("pipe-io-{}.jsonl", uuid::Uuid::new_v4()));
+
+        // 记录
+        let (listener, connector) = memory::channel();
+        let mut server = adder(listener, 0);
+        server.set_recorder(Recorder::create(&path).unwrap());
+        tokio::spawn(async move { server.listen().await });
+
+        let mut client = Client::with_connector(connector);
+        client.connect().await.unwrap();
+        for (a, b) in [(1, 2), (3, 4)] {
+            client
+                .call_with_params("add", json!({ "a": a, "b": b }))
+                .await
+                .unwrap();
+        }
+
+        let records = recorder::read_records(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let calls: Vec<_> = records
+            .iter()
+            .filter(|record| record.message.get_method() == Some("add"))
+            .collect();
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].kind, RecordKind::Request);
+        let replies = records
+            .iter()
+            .filter(|record| record.kind == RecordKind::Reply)
+            .filter(|record| record.message.get_result() == Some(&json!(7)))
+            .count();
+        assert_eq!(replies, 1);
+
+        // 相同行为的服务端
+        let (listener, connector) = memory::channel();
+        let mut server = adder(listener, 0);
+        tokio::spawn(async move { server.listen().await });
+        let report = Replayer::new(|| Client::with_connector(connector.clone()))
+            .run(&records)
+            .await
+            .unwrap();
+        assert_eq!(report.replayed, 2);
+        assert!(report.is_clean());
+
+        // 行为变化后的服务端
+        let (listener, connector) = memory::channel();
+        let mut server = adder(listener, 1);
+        tokio::spawn(async move { server.listen().await });
+        let report = Replayer::new(|| Client::with_connector(connector.clone()))
+            .run(&records)
+            .await
+            .unwrap();
+        assert_eq!(report.mismatches.len(), 2);
+        assert_eq!(report.mismatches[0].method, "add");
+        assert_eq!(report.mismatches[0].expected, json!({ "result": 3 }));
+        assert_eq!(report.mismatches[0].actual, json!({ "result": 4 }));
+    }
+}
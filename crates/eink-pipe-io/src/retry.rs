@@ -0,0 +1,125 @@
+//
+// Copyright (C) Lenovo ThinkBook Gen4 Project.
+//
+// This program is protected under international and China copyright laws as
+// an unpublished work. This program is confidential and proprietary to the
+// copyright owners. Reproduction or disclosure, in whole or in part, or the
+// production of derivative works therefrom without the express permission of
+// the copyright owners is prohibited.
+//
+// All rights reserved.
+//
+
+//! 客户端断线重连与请求重试策略
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+/// 客户端连接状态
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// 未连接或连接已断开
+    Disconnected,
+    /// 正在建立连接
+    Connecting,
+    /// 连接断开后正在重连
+    Reconnecting,
+    /// 已连接
+    Connected,
+}
+
+/// 断线重连策略，重连间隔按指数退避增长
+#[derive(Clone, Debug)]
+pub struct ReconnectPolicy {
+    /// 第一次重连前的等待时间
+    pub initial_backoff: Duration,
+    /// 重连等待时间上限
+    pub max_backoff: Duration,
+    /// 每次失败后等待时间的增长倍数
+    pub multiplier: f64,
+    /// 最大重连次数，None 表示一直重连
+    pub max_attempts: Option<u32>,
+}
+
+impl ReconnectPolicy {
+    /// 第 `attempt` 次（从 0 开始）重连失败后的等待时间
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = self
+            .multiplier
+            .max(1.0)
+            .powi(attempt.min(i32::MAX as u32) as i32);
+        let backoff = self.initial_backoff.as_secs_f64() * factor;
+        Duration::from_secs_f64(backoff.min(self.max_backoff.as_secs_f64()))
+    }
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            multiplier: 2.0,
+            max_attempts: Some(5),
+        }
+    }
+}
+
+/// 请求重试策略，只有声明为幂等的方法会在链路断开后重试
+#[derive(Clone, Debug, Default)]
+pub struct RetryPolicy {
+    /// 最大重试次数
+    pub max_retries: u32,
+    idempotent_methods: HashSet<String>,
+}
+
+impl RetryPolicy {
+    pub fn new(max_retries: u32) -> Self {
+        Self {
+            max_retries,
+            idempotent_methods: HashSet::new(),
+        }
+    }
+
+    /// 声明幂等方法，可以安全地重复调用
+    pub fn idempotent(mut self, method: &str) -> Self {
+        self.idempotent_methods.insert(method.to_owned());
+        self
+    }
+
+    /// 方法是否允许重试
+    pub fn is_retryable(&self, method: &str) -> bool {
+        self.max_retries > 0 && self.idempotent_methods.contains(method)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{ReconnectPolicy, RetryPolicy};
+
+    #[test]
+    fn test_exponential_backoff() {
+        let policy = ReconnectPolicy {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(500),
+            multiplier: 2.0,
+            max_attempts: None,
+        };
+        assert_eq!(policy.backoff(0), Duration::from_millis(100));
+        assert_eq!(policy.backoff(1), Duration::from_millis(200));
+        assert_eq!(policy.backoff(2), Duration::from_millis(400));
+        assert_eq!(policy.backoff(3), Duration::from_millis(500));
+        assert_eq!(policy.backoff(100), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_only_idempotent_methods_are_retryable() {
+        let policy = RetryPolicy::new(2).idempotent("get_mipi_mode");
+        assert!(policy.is_retryable("get_mipi_mode"));
+        assert!(!policy.is_retryable("software_reset_api"));
+        assert!(!RetryPolicy::new(0)
+            .idempotent("get_mipi_mode")
+            .is_retryable("get_mipi_mode"));
+    }
+}
@@ -1,63 +1,267 @@
-use std::{
-    marker,
-    os::windows::prelude::AsRawHandle,
-    pin::Pin,
-    sync::{Arc, Weak},
-    time::Duration,
-};
-
-use anyhow::bail;
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
 use jsonrpc_lite::{Id, JsonRpc, Params};
 use parking_lot::Mutex;
 use remoc::rch;
-use signals2::{connect::ConnectionImpl, Connect2, Connect3, Connection, Emit2, Emit3, Signal};
-use tokio::{
-    net::windows::named_pipe::{self, ClientOptions, ServerOptions},
-    time,
-};
-use windows::{
-    core::PWSTR,
-    Win32::{
-        Foundation::{BOOL, ERROR_PIPE_BUSY, ERROR_SUCCESS, HANDLE, PSID},
-        Security::{
-            AllocateAndInitializeSid,
-            Authorization::{
-                SetEntriesInAclW, SetSecurityInfo, ACCESS_MODE, EXPLICIT_ACCESS_W, SET_ACCESS,
-                SE_KERNEL_OBJECT, TRUSTEE_IS_SID, TRUSTEE_IS_WELL_KNOWN_GROUP, TRUSTEE_TYPE,
-            },
-            FreeSid, InitializeSecurityDescriptor, SetSecurityDescriptorDacl, ACE_FLAGS, ACL,
-            DACL_SECURITY_INFORMATION, PSECURITY_DESCRIPTOR, SECURITY_ATTRIBUTES, SID,
-        },
-        Storage::FileSystem::FILE_WRITE_DATA,
-        System::{
-            Memory::{LocalAlloc, LocalFree, LPTR},
-            SystemServices::{GENERIC_READ, GENERIC_WRITE, SECURITY_DESCRIPTOR_REVISION},
-        },
-    },
-};
-
-use crate::msg::IpcMsg;
+use serde_json::json;
+use signals2::{Connect2, Connect3, Connection, Emit2, Emit3, Signal};
+use tokio::io::AsyncReadExt;
+use tokio::sync::{mpsc, RwLock, Semaphore, TryAcquireError};
+use tokio::task::JoinSet;
+use tokio::time;
+
+use crate::access::{self, AccessPolicy};
+use crate::batch;
+use crate::codec::{Body, Codec};
+use crate::deadline::{self, CallOptions, CancellationToken};
+use crate::handshake::{self, BuildInfo, MethodInfo, ServerInfo};
+use crate::limits::{self, Limiter, Limits, Overflow};
+use crate::metrics::{Metrics, Outcome};
+use crate::middleware::{self, Endpoint, Middleware, Next};
+use crate::msg::{Inbound, IpcMsg};
+use crate::peer::Peer;
+use crate::pubsub::Publisher;
+use crate::raw::{self, Detected};
+use crate::recorder::{RecordKind, Recorder};
+use crate::registry::{ConnectionId, ConnectionInfo, Registry};
+use crate::stream::Progress;
+#[cfg(windows)]
+pub use crate::transport::named_pipe::SecurityAttributes;
+use crate::transport::{self, Listener};
+
+/// 每个连接默认同时处理的请求数量
+pub const DEFAULT_MAX_CONCURRENT_REQUESTS: usize = 16;
+
+/// 关闭时等待正在处理的请求完成的默认时间
+pub const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 服务器即将关闭，发送给已连接客户端的通知
+pub const METHOD_SHUTDOWN: &str = "rpc.shutdown";
+
+/// 异步请求处理函数返回的 future
+pub type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send + 'static>>;
+
+type AsyncHandler = Arc<dyn Fn(RequestContext, JsonRpc) -> BoxFuture<JsonRpc> + Send + Sync>;
+
+/// 请求上下文，传递给异步请求处理函数
+#[derive(Clone)]
+pub struct RequestContext {
+    /// 请求 id
+    pub id: Id,
+    /// 发起请求的对端，可以在处理过程中回调对端方法
+    pub peer: Peer,
+    /// 对端放弃该请求时被触发
+    pub cancel: CancellationToken,
+    /// 连接 id 与对端进程凭据
+    pub connection: ConnectionInfo,
+    /// 进度发送端，调用方没有请求流式回复时发送被忽略
+    pub progress: Progress,
+    /// 打包的参数与返回值，调用方没有打包参数时为空
+    pub body: Body,
+}
+
+/// 正在处理的请求
+#[derive(Clone)]
+struct InFlight {
+    cancel: CancellationToken,
+    progress: Progress,
+    body: Body,
+}
+
+/// 服务器关闭句柄，可以克隆到其它线程中使用
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    token: CancellationToken,
+}
+
+impl ShutdownHandle {
+    /// 停止接受新连接，通知已连接的客户端，等待正在处理的请求完成后 `listen` 返回
+    pub fn shutdown(&self) {
+        self.token.cancel();
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        self.token.is_cancelled()
+    }
+}
 
 pub struct ServerHandlers {
     pub on_request: Signal<(i32, JsonRpc), JsonRpc>,
 }
 
 pub struct Server {
-    pipe_name: String,
+    name: String,
     // handlers: Arc<Mutex<ServerHandlers>>,
     on_connection: Signal<(Arc<Mutex<Socket>>, i32), i32>,
-    security_attributes: SecurityAttributes,
+    listener: Box<dyn Listener>,
+    publisher: Publisher,
+    /// 存活的连接
+    registry: Registry,
+    /// 请求执行闸门，普通请求共享持有，原子批量请求独占持有
+    gate: Arc<RwLock<()>>,
+    /// 握手与 `rpc.discover` 返回的服务端信息
+    info: Arc<Mutex<ServerInfo>>,
+    /// 方法访问策略，None 时允许所有调用
+    access: Option<Arc<AccessPolicy>>,
+    /// 通信记录器，None 时不记录
+    recorder: Option<Recorder>,
+    /// 消息大小与调用频率限制
+    limiter: Arc<Limiter>,
+    /// 调用统计
+    metrics: Metrics,
+    /// 定期将调用统计写入日志的间隔，None 时不写入
+    stats_log_interval: Option<Duration>,
+    /// 请求中间件，先添加的位于最外层
+    middlewares: Vec<Arc<dyn Middleware>>,
+    max_concurrent_requests: usize,
+    /// 停止接受连接与请求
+    shutdown: CancellationToken,
+    /// 等待超时后取消正在处理的请求
+    abort: CancellationToken,
+    drain_timeout: Duration,
 }
 
 impl Server {
+    /// 使用当前平台默认传输创建服务器（Windows 命名管道 / Unix domain socket）
     pub fn new(pipe_name: &str) -> Self {
         Self {
-            pipe_name: pipe_name.to_string(),
+            name: pipe_name.to_string(),
+            on_connection: Signal::new(),
+            listener: transport::default_listener(pipe_name),
+            publisher: Publisher::default(),
+            registry: Registry::default(),
+            gate: Default::default(),
+            info: Default::default(),
+            access: None,
+            recorder: None,
+            limiter: Default::default(),
+            metrics: Metrics::new(pipe_name),
+            stats_log_interval: None,
+            middlewares: Vec::new(),
+            max_concurrent_requests: DEFAULT_MAX_CONCURRENT_REQUESTS,
+            shutdown: CancellationToken::new(),
+            abort: CancellationToken::new(),
+            drain_timeout: DEFAULT_DRAIN_TIMEOUT,
+        }
+    }
+
+    /// 使用自定义传输创建服务器
+    pub fn with_listener<L: Listener>(name: &str, listener: L) -> Self {
+        Self {
+            name: name.to_string(),
             on_connection: Signal::new(),
-            security_attributes: SecurityAttributes::allow_everyone_create().unwrap(),
+            listener: Box::new(listener),
+            publisher: Publisher::default(),
+            registry: Registry::default(),
+            gate: Default::default(),
+            info: Default::default(),
+            access: None,
+            recorder: None,
+            limiter: Default::default(),
+            metrics: Metrics::new(name),
+            stats_log_interval: None,
+            middlewares: Vec::new(),
+            max_concurrent_requests: DEFAULT_MAX_CONCURRENT_REQUESTS,
+            shutdown: CancellationToken::new(),
+            abort: CancellationToken::new(),
+            drain_timeout: DEFAULT_DRAIN_TIMEOUT,
+        }
+    }
+
+    /// 服务器端点名称
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 主题发布器，用于向订阅的客户端推送通知
+    pub fn publisher(&self) -> Publisher {
+        self.publisher.clone()
+    }
+
+    /// 连接注册表，用于枚举连接与广播
+    pub fn registry(&self) -> Registry {
+        self.registry.clone()
+    }
+
+    /// 当前存活的连接
+    pub fn connections(&self) -> Vec<ConnectionInfo> {
+        self.registry.connections()
+    }
+
+    /// 设置方法访问策略，在 `listen` 之前设置
+    pub fn set_access_policy(&mut self, policy: AccessPolicy) {
+        self.access = Some(Arc::new(policy));
+    }
+
+    /// 记录所有连接的请求、通知与回复，在 `listen` 之前设置
+    pub fn set_recorder(&mut self, recorder: Recorder) {
+        self.recorder = Some(recorder);
+    }
+
+    /// 设置消息大小、并发溢出处理与方法调用频率限制，在 `listen` 之前设置
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limiter = Arc::new(Limiter::new(limits));
+    }
+
+    /// 调用统计，与 `rpc.stats` 返回的内容一致
+    pub fn metrics(&self) -> Metrics {
+        self.metrics.clone()
+    }
+
+    /// 每隔 `interval` 将调用统计写入日志，在 `listen` 之前设置
+    pub fn set_stats_log_interval(&mut self, interval: Duration) {
+        self.stats_log_interval = Some(interval);
+    }
+
+    /// 添加请求中间件，先添加的中间件先处理请求，在 `listen` 之前添加
+    pub fn add_middleware<M: Middleware>(&mut self, middleware: M) {
+        self.middlewares.push(Arc::new(middleware));
+    }
+
+    /// 设置每个连接同时处理的请求数量，达到上限后按 `Limits::overflow` 排队或拒绝
+    ///
+    /// 设置为 1 时按接收顺序逐个处理
+    pub fn set_max_concurrent_requests(&mut self, limit: usize) {
+        self.max_concurrent_requests = limit.max(1);
+    }
+
+    /// 设置关闭时等待正在处理的请求完成的时间，超时后取消这些请求
+    pub fn set_drain_timeout(&mut self, timeout: Duration) {
+        self.drain_timeout = timeout;
+    }
+
+    /// 关闭句柄，在 `listen` 之前获取
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle {
+            token: self.shutdown.clone(),
         }
     }
 
+    /// 设置服务端构建信息，在握手时发送给客户端
+    pub fn set_build_info(&mut self, build: BuildInfo) {
+        self.info.lock().build = Some(build);
+    }
+
+    /// 登记服务方法，用于握手与 `rpc.discover`
+    pub fn register_methods<I: IntoIterator<Item = MethodInfo>>(&mut self, methods: I) {
+        self.info.lock().methods.extend(methods);
+    }
+
+    /// 设置支持的参数与返回值编码，用于握手协商，默认支持 `Codec::ALL`
+    pub fn set_codecs(&mut self, codecs: Vec<Codec>) {
+        self.info.lock().codecs = codecs;
+    }
+
+    /// 服务端信息
+    pub fn server_info(&self) -> ServerInfo {
+        self.info.lock().clone()
+    }
+
     /// 设置请求回调，使用 signals 接口
     pub fn on_connection<Callback>(&mut self, cb: Callback) -> Connection
     where
@@ -66,69 +270,159 @@ impl Server {
         self.on_connection.connect(cb)
     }
 
-    pub async fn listen(&mut self) {
-        // 创建底层 pipe 连接
-
-        let pipe_name = self.pipe_name.clone();
+    /// 接受连接并处理请求，直到通过 `ShutdownHandle` 关闭或接受连接失败
+    ///
+    /// 关闭时不再接受新的连接与请求，通知已连接的客户端，并在 drain 超时时间内
+    /// 等待正在处理的请求完成
+    pub async fn listen(&mut self) -> anyhow::Result<()> {
         let on_connection_cloned = self.on_connection.clone();
+        let publisher = self.publisher.clone();
+        let registry = self.registry.clone();
+        let gate = self.gate.clone();
+        let info = self.info.clone();
+        let access = self.access.clone();
+        let recorder = self.recorder.clone();
+        let limiter = self.limiter.clone();
+        let metrics = self.metrics.clone();
+        let middlewares: Arc<[Arc<dyn Middleware>]> = self.middlewares.clone().into();
+        let max_concurrent_requests = self.max_concurrent_requests;
+        let shutdown = self.shutdown.clone();
+        let abort = self.abort.clone();
+        let name = self.name.clone();
+
+        // 定期将调用统计写入日志，服务器关闭时写入最后一次
+        if let Some(interval) = self.stats_log_interval {
+            let metrics = metrics.clone();
+            let shutdown = shutdown.clone();
+            tokio::spawn(async move {
+                loop {
+                    tokio::select! {
+                        _ = time::sleep(interval) => metrics.log_summary(),
+                        _ = shutdown.cancelled() => break,
+                    }
+                }
+                metrics.log_summary();
+            });
+        }
 
-        // The first server needs to be constructed early so that clients can
-        // be correctly connected. Otherwise calling .wait will cause the client to
-        // error.
-        //
-        // Here we also make use of `first_pipe_instance`, which will ensure that
-        // there are no other servers up and running already.
-        let mut pipe_server = unsafe {
-            ServerOptions::new()
-                .first_pipe_instance(true)
-                .reject_remote_clients(true)
-                // .access_inbound(true)
-                // .access_outbound(true)
-                // .in_buffer_size(65536 * 100)
-                // .out_buffer_size(65536 * 100)
-                // .pipe_mode(named_pipe::PipeMode::Message)
-                .create_with_security_attributes_raw(
-                    &self.pipe_name,
-                    std::mem::transmute(self.security_attributes.as_ptr()),
-                )
-                .unwrap()
-        };
+        let mut connections = JoinSet::new();
 
         // Spawn the server loop.
-        loop {
+        let result = loop {
             // Wait for a client to connect.
-            let _connected = match pipe_server.connect().await {
-                Ok(res) => res,
-                Err(err) => panic!("err: {err}"),
+            let (stream, credentials) = tokio::select! {
+                _ = shutdown.cancelled() => break Ok(()),
+                // 回收已经结束的连接，Listener::accept 是取消安全的
+                Some(_) = connections.join_next(), if !connections.is_empty() => continue,
+                accepted = self.listener.accept() => match accepted {
+                    Ok(accepted) => accepted,
+                    // 关闭过程中 Listener 被关闭不是错误
+                    Err(_) if shutdown.is_cancelled() => break Ok(()),
+                    Err(err) => {
+                        log::error!("PipeIo: server '{name}' cannot accept connection: {err}");
+                        break Err(err.into());
+                    }
+                },
             };
 
             /* use the connected client */
-            let (pipe_rx, pipe_tx) = tokio::io::split(pipe_server);
-
-            // Construct the next server to be connected before sending the one
-            // we already have of onto a task. This ensures that the server
-            // isn't closed (after it's done in the task) before a new one is
-            // available. Otherwise the client might error with
-            // `io::ErrorKind::NotFound`.
-            pipe_server = ServerOptions::new().create(&pipe_name).unwrap();
+            let (mut pipe_rx, pipe_tx) = tokio::io::split(stream);
 
             let on_connection_cloned2 = on_connection_cloned.clone();
+            let publisher = publisher.clone();
+            let registry = registry.clone();
+            let gate = gate.clone();
+            let info = info.clone();
+            let access = access.clone();
+            let recorder = recorder.clone();
+            let limiter = limiter.clone();
+            let metrics = metrics.clone();
+            let middlewares = middlewares.clone();
+            let server_name = name.clone();
+            let shutdown = shutdown.clone();
+            let abort = abort.clone();
+
+            connections.spawn(async move {
+                // remoc 链路任务，连接任务被强制中止时随之中止并断开链路
+                let mut link = JoinSet::new();
+
+                // 原始 JSON-RPC 客户端先发送前导，其它连接使用 remoc 链路
+                let detected =
+                    match time::timeout(raw::DETECT_TIMEOUT, raw::detect(&mut pipe_rx)).await {
+                        Ok(detected) => detected,
+                        Err(_) => {
+                            log::warn!(
+                                "PipeIo: connection sent nothing in {:?}, drop it",
+                                raw::DETECT_TIMEOUT
+                            );
+                            return;
+                        }
+                    };
+                let (peer, rx) = match detected {
+                    Ok(Detected::Raw(framing)) => {
+                        log::info!("PipeIo: accept raw JSON-RPC connection, framing: {framing}");
+                        raw::spawn_link(framing, limiter.max_frame_len(), pipe_rx, pipe_tx)
+                    }
+                    Ok(Detected::Remoc(read)) => {
+                        // 已经读取的字节重新送入 remoc 链路
+                        let pipe_rx = io::Cursor::new(read).chain(pipe_rx);
+
+                        // Establish Remoc connection over pipe connection.
+                        // The connection is always bidirectional, but we can just drop
+                        // the unneeded sender.
+                        let (conn, tx, mut rx): (
+                            _,
+                            rch::base::Sender<IpcMsg>,
+                            rch::base::Receiver<IpcMsg>,
+                        ) = match remoc::Connect::io(remoc::Cfg::default(), pipe_rx, pipe_tx).await
+                        {
+                            Ok(link) => link,
+                            Err(err) => {
+                                log::error!("PipeIo: cannot establish remoc link: {err}");
+                                return;
+                            }
+                        };
+                        // 超过大小限制的消息在完整读入前丢弃
+                        if let Some(max) = limiter.max_message_size() {
+                            rx.set_max_item_size(max);
+                        }
 
-            let _client = tokio::spawn(async move {
-                // Establish Remoc connection over pipe connection.
-                // The connection is always bidirectional, but we can just drop
-                // the unneeded sender.
-                let (conn, tx, rx): (_, rch::base::Sender<IpcMsg>, rch::base::Receiver<IpcMsg>) =
-                    remoc::Connect::io(remoc::Cfg::default(), pipe_rx, pipe_tx)
-                        .await
-                        .unwrap();
+                        link.spawn(conn);
+                        (Peer::spawn(tx), Inbound::Remoc(Box::new(rx)))
+                    }
+                    Err(err) => {
+                        log::error!("PipeIo: cannot read connection preamble: {err}");
+                        return;
+                    }
+                };
 
-                tokio::spawn(conn);
+                let connection = ConnectionInfo::new(credentials);
+                registry.insert(connection.clone(), peer.clone());
+                metrics.connection_opened();
 
                 let socket = Arc::new(Mutex::new(Socket {
-                    tx,
+                    conn_id: connection.id,
+                    connection,
+                    peer,
                     rx: Some(rx),
                     on_request: Signal::new(),
+                    on_request_async: None,
+                    on_notification: Signal::new(),
+                    publisher,
+                    registry,
+                    in_flight: Default::default(),
+                    gate,
+                    info,
+                    access,
+                    server_name,
+                    recorder,
+                    limiter,
+                    metrics,
+                    middlewares,
+                    requests: Arc::new(Semaphore::new(max_concurrent_requests)),
+                    max_concurrent_requests,
+                    shutdown,
+                    abort,
                 }));
 
                 on_connection_cloned2.emit(socket.clone(), 0);
@@ -136,16 +430,60 @@ impl Server {
                 // Run server.
                 Socket::process_incoming(socket).await;
 
-                ()
+                // 正常结束时链路继续运行，直到已经发送的回复送达对端
+                link.detach_all();
             });
+        };
+
+        // 停止接收新的请求，通知已连接的客户端并等待正在处理的请求完成
+        shutdown.cancel();
+        let drained = time::timeout(self.drain_timeout, async {
+            while connections.join_next().await.is_some() {}
+        })
+        .await;
+        if drained.is_err() {
+            log::warn!(
+                "PipeIo: server '{name}' drain timed out, cancel {} connections",
+                connections.len()
+            );
+            abort.cancel();
+            connections.shutdown().await;
         }
+
+        log::info!("PipeIo: server '{name}' stopped");
+        result
     }
 }
 
 pub struct Socket {
-    pub tx: rch::base::Sender<IpcMsg>,
-    pub rx: Option<rch::base::Receiver<IpcMsg>>,
+    conn_id: ConnectionId,
+    /// 建立连接时的连接信息
+    connection: ConnectionInfo,
+    /// 对端句柄，允许在任意线程中发送
+    peer: Peer,
+    rx: Option<Inbound>,
     pub on_request: Signal<(Arc<Mutex<Socket>>, Id, JsonRpc), JsonRpc>,
+    on_request_async: Option<AsyncHandler>,
+    pub on_notification: Signal<(Arc<Mutex<Socket>>, JsonRpc)>,
+    publisher: Publisher,
+    registry: Registry,
+    /// 正在处理的请求，对端取消时触发对应的令牌
+    in_flight: Arc<Mutex<HashMap<String, InFlight>>>,
+    /// 服务器共享的请求执行闸门
+    gate: Arc<RwLock<()>>,
+    info: Arc<Mutex<ServerInfo>>,
+    access: Option<Arc<AccessPolicy>>,
+    /// 服务器端点名称，用于审计日志
+    server_name: String,
+    recorder: Option<Recorder>,
+    limiter: Arc<Limiter>,
+    metrics: Metrics,
+    middlewares: Arc<[Arc<dyn Middleware>]>,
+    /// 同时处理的请求数量限制
+    requests: Arc<Semaphore>,
+    max_concurrent_requests: usize,
+    shutdown: CancellationToken,
+    abort: CancellationToken,
 }
 
 impl Socket {
@@ -157,85 +495,549 @@ impl Socket {
         self.on_request.connect(cb)
     }
 
+    /// 设置通知回调，使用 signals 接口
+    pub fn on_notification<Callback>(&mut self, cb: Callback) -> Connection
+    where
+        Callback: Fn(Arc<Mutex<Socket>>, JsonRpc) + Send + Sync + 'static,
+    {
+        self.on_notification.connect(cb)
+    }
+
+    /// 设置异步请求回调，设置后替代同步的 `on_request` 回调
+    ///
+    /// 处理函数在 tokio 任务中执行，可以直接 await 对端调用，不应执行阻塞操作
+    pub fn on_request_async<Handler, Fut>(&mut self, handler: Handler)
+    where
+        Handler: Fn(RequestContext, JsonRpc) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = JsonRpc> + Send + 'static,
+    {
+        self.on_request_async = Some(Arc::new(move |ctx, req| Box::pin(handler(ctx, req))));
+    }
+
+    /// 连接 id
+    pub fn conn_id(&self) -> ConnectionId {
+        self.conn_id
+    }
+
+    /// 连接信息，包含对端进程凭据与最后活动时间
+    pub fn connection_info(&self) -> ConnectionInfo {
+        self.registry
+            .get(self.conn_id)
+            .unwrap_or_else(|| self.connection.clone())
+    }
+
+    /// 对端句柄
+    pub fn peer(&self) -> Peer {
+        self.peer.clone()
+    }
+
+    /// 主题发布器
+    pub fn publisher(&self) -> Publisher {
+        self.publisher.clone()
+    }
+
+    /// 向对端发送通知，不等待回复
+    pub fn notify<P: Into<Params>>(&self, method: &str, params: P) -> anyhow::Result<()> {
+        self.peer.notify(method, params)
+    }
+
+    /// 正在处理的请求的取消令牌，对端放弃该请求时令牌被触发
+    ///
+    /// 请求处理结束后令牌被移除，耗时的处理函数应在开始时获取
+    pub fn cancellation_token(&self, id: &Id) -> Option<CancellationToken> {
+        self.in_flight
+            .lock()
+            .get(&deadline::id_key(id))
+            .map(|in_flight| in_flight.cancel.clone())
+    }
+
+    /// 正在处理的请求的进度发送端，同步回调中也可以使用 `Progress::current()`
+    pub fn progress(&self, id: &Id) -> Option<Progress> {
+        self.in_flight
+            .lock()
+            .get(&deadline::id_key(id))
+            .map(|in_flight| in_flight.progress.clone())
+    }
+
+    /// 正在处理的请求的打包数据，同步回调中也可以使用 `Body::current()`
+    pub fn body(&self, id: &Id) -> Option<Body> {
+        self.in_flight
+            .lock()
+            .get(&deadline::id_key(id))
+            .map(|in_flight| in_flight.body.clone())
+    }
+
+    /// 调用对端方法，使用默认超时时间
+    ///
+    /// 在异步代码中应使用 `peer()` 获取句柄后调用，避免跨越 await 持有锁
     pub async fn call_with_params<P: Into<Params>>(
-        &mut self,
+        &self,
         method: &str,
         params: P,
     ) -> anyhow::Result<JsonRpc> {
-        let id = uuid::Uuid::new_v4().to_string();
-        let (reply_tx, mut reply_rx) = rch::mpsc::channel(1);
-        self.tx
-            .send(IpcMsg {
-                payload: JsonRpc::request_with_params(id, method, params),
-                reply_tx: Some(reply_tx),
+        self.peer.call_with_params(method, params).await
+    }
+
+    /// 调用对端方法，超时后返回 `CallError::Timeout`
+    pub async fn call_with_timeout<P: Into<Params>>(
+        &self,
+        method: &str,
+        params: P,
+        timeout: Duration,
+    ) -> anyhow::Result<JsonRpc> {
+        self.peer.call_with_timeout(method, params, timeout).await
+    }
+
+    /// 使用指定的超时与取消选项调用对端方法
+    pub async fn call_with_options<P: Into<Params>>(
+        &self,
+        method: &str,
+        params: P,
+        options: CallOptions,
+    ) -> anyhow::Result<JsonRpc> {
+        self.peer.call_with_options(method, params, options).await
+    }
+
+    /// 处理订阅、握手、方法发现与调用统计等保留方法，其它方法返回 None
+    fn handle_reserved(
+        conn_id: ConnectionId,
+        outbound: &mpsc::UnboundedSender<IpcMsg>,
+        publisher: &Publisher,
+        info: &Mutex<ServerInfo>,
+        metrics: &Metrics,
+        req: &JsonRpc,
+    ) -> Option<JsonRpc> {
+        publisher
+            .handle_request(conn_id, outbound, req)
+            .or_else(|| handshake::handle_request(&info.lock(), req))
+            .or_else(|| metrics.handle_request(req))
+    }
+
+    /// 检查访问策略，拒绝时记录审计日志
+    fn is_allowed(&self, method: &str, connection: &ConnectionInfo) -> bool {
+        match &self.access {
+            Some(policy) if !policy.check(method, connection) => {
+                access::audit_denied(&self.server_name, method, connection);
+                false
+            }
+            _ => true,
+        }
+    }
+
+    /// 经过中间件后执行请求回调，没有回调处理或回调 panic 时回复 internal_error
+    ///
+    /// 异步回调在独立任务中执行，同步回调在 blocking 线程中执行
+    async fn emit_request(this: Arc<Mutex<Self>>, id: Id, req: JsonRpc) -> JsonRpc {
+        // Signal 的 clone 是轻量级操作
+        let (on_request_async, on_request, middlewares, ctx) = {
+            let socket = this.lock();
+            let connection = socket.connection_info();
+            let method = req.get_method().unwrap_or_default();
+            if !socket.is_allowed(method, &connection) {
+                return access::denied_reply(id, method);
+            }
+            if !socket.limiter.check_rate(socket.conn_id, method) {
+                log::warn!(
+                    "PipeIo: client[{}] '{method}' was rate limited",
+                    socket.conn_id
+                );
+                return limits::rate_limited_reply(id, method);
+            }
+
+            let ctx = RequestContext {
+                id: id.clone(),
+                peer: socket.peer.clone(),
+                cancel: socket.cancellation_token(&id).unwrap_or_default(),
+                connection,
+                progress: socket.progress(&id).unwrap_or_default(),
+                body: socket.body(&id).unwrap_or_default(),
+            };
+            (
+                socket.on_request_async.clone(),
+                socket.on_request.clone(),
+                socket.middlewares.clone(),
+                ctx,
+            )
+        };
+
+        let endpoint: Endpoint = Box::new(move |ctx, req| {
+            Box::pin(async move {
+                let id = ctx.id.clone();
+                let request_id = middleware::request_id();
+                let reply = match on_request_async {
+                    Some(handler) => {
+                        // 在独立任务中执行以捕获 panic，请求被中止时任务随 JoinSet 一起中止
+                        let mut task = JoinSet::new();
+                        task.spawn(middleware::scope_async(request_id, handler(ctx, req)));
+                        task.join_next().await.unwrap().map(Some)
+                    }
+                    None => {
+                        // 事件处理可能是耗时操作，分离到 blocking 线程进行
+                        let id = id.clone();
+                        let progress = ctx.progress;
+                        let body = ctx.body;
+                        tokio::task::spawn_blocking(move || {
+                            middleware::scope_sync(request_id, || {
+                                Progress::scope(progress, || {
+                                    Body::scope(body, || on_request.emit(this, id, req))
+                                })
+                            })
+                        })
+                        .await
+                    }
+                };
+                match reply {
+                    Ok(Some(reply)) => reply,
+                    Ok(None) => JsonRpc::error(id, jsonrpc_lite::Error::internal_error()),
+                    Err(err) => {
+                        // 处理函数 panic 时回复 internal_error，错误数据为 panic 信息
+                        let mut error = jsonrpc_lite::Error::internal_error();
+                        if let Ok(panic) = err.try_into_panic() {
+                            let message = middleware::panic_message(panic);
+                            log::error!("PipeIo: request handler panicked: {message}");
+                            error.data = Some(json!(message));
+                        }
+                        JsonRpc::error(id, error)
+                    }
+                }
             })
-            .await
-            .unwrap();
-        match reply_rx.recv().await {
-            Ok(reply) => return Ok(reply.unwrap()),
-            Err(err) => bail!(err),
+        });
+        Next::new(middlewares, endpoint).run(ctx, req).await
+    }
+
+    /// 在 blocking 线程中执行通知回调
+    async fn emit_notification(this: Arc<Mutex<Self>>, req: JsonRpc) {
+        let on_notification = {
+            let socket = this.lock();
+            let method = req.get_method().unwrap_or_default();
+            if !socket.is_allowed(method, &socket.connection_info()) {
+                return;
+            }
+            if !socket.limiter.check_rate(socket.conn_id, method) {
+                log::warn!(
+                    "PipeIo: client[{}] '{method}' was rate limited",
+                    socket.conn_id
+                );
+                return;
+            }
+            socket.on_notification.clone()
+        };
+        let _ = tokio::task::spawn_blocking(move || on_notification.emit(this, req)).await;
+    }
+
+    /// 依次处理批量请求中的每一项，回复顺序与请求顺序一致
+    ///
+    /// 原子执行期间独占请求闸门，其它连接的请求等待批量请求完成后再处理
+    async fn process_batch(
+        this: Arc<Mutex<Self>>,
+        items: Vec<JsonRpc>,
+        atomic: bool,
+    ) -> Vec<JsonRpc> {
+        let (conn_id, peer, publisher, gate, info, metrics) = {
+            let this = this.lock();
+            (
+                this.conn_id,
+                this.peer.clone(),
+                this.publisher.clone(),
+                this.gate.clone(),
+                this.info.clone(),
+                this.metrics.clone(),
+            )
+        };
+
+        let _exclusive = match atomic {
+            true => Some(gate.write().await),
+            false => None,
+        };
+
+        let mut replies = Vec::with_capacity(items.len());
+        for item in items {
+            match &item {
+                JsonRpc::Request(_) => {
+                    let id = item.get_id().unwrap_or(Id::None(()));
+                    if let Some(reply) = Self::handle_reserved(
+                        conn_id,
+                        peer.outbound(),
+                        &publisher,
+                        &info,
+                        &metrics,
+                        &item,
+                    ) {
+                        replies.push(reply);
+                    } else if batch::parse_batch(&item).is_some() {
+                        // 不支持嵌套的批量请求
+                        replies.push(JsonRpc::error(id, jsonrpc_lite::Error::invalid_request()));
+                    } else {
+                        let _shared = match atomic {
+                            true => None,
+                            false => Some(gate.read().await),
+                        };
+                        replies.push(Self::emit_request(this.clone(), id, item).await);
+                    }
+                }
+                JsonRpc::Notification(_) => {
+                    let _shared = match atomic {
+                        true => None,
+                        false => Some(gate.read().await),
+                    };
+                    Self::emit_notification(this.clone(), item).await;
+                }
+                JsonRpc::Success(_) | JsonRpc::Error(_) => {
+                    replies.push(JsonRpc::error(
+                        Id::None(()),
+                        jsonrpc_lite::Error::invalid_request(),
+                    ));
+                }
+            }
         }
+        replies
     }
 
     /// 处理输入的请求
     pub async fn process_incoming(this: Arc<Mutex<Self>>) {
-        let conn_id = uuid::Uuid::new_v4().as_u128();
+        let (conn_id, peer, publisher, in_flight, gate, info, requests) = {
+            let this = this.lock();
+            (
+                this.conn_id,
+                this.peer.clone(),
+                this.publisher.clone(),
+                this.in_flight.clone(),
+                this.gate.clone(),
+                this.info.clone(),
+                this.requests.clone(),
+            )
+        };
+        let (registry, recorder, limiter, metrics, max_concurrent_requests, shutdown, abort) = {
+            let this = this.lock();
+            (
+                this.registry.clone(),
+                this.recorder.clone(),
+                this.limiter.clone(),
+                this.metrics.clone(),
+                this.max_concurrent_requests,
+                this.shutdown.clone(),
+                this.abort.clone(),
+            )
+        };
         eprintln!("client[{conn_id}] was connected:");
 
-        let on_request = this.lock().on_request.clone();
-        // let shared_self = this.clone();
-
         let mut rx = this.lock().rx.take().unwrap();
+        // 请求与通知的处理任务，连接任务被强制中止时随之中止
+        let mut tasks = JoinSet::new();
 
         loop {
-            match rx.recv().await {
+            // 回收已经完成的处理任务
+            while tasks.try_join_next().is_some() {}
+
+            let received = tokio::select! {
+                received = rx.recv() => received,
+                _ = shutdown.cancelled() => {
+                    // 通知对端服务器即将关闭，不再接收新的请求，等待正在处理的请求完成
+                    let _ = peer.notify(METHOD_SHUTDOWN, json!({}));
+                    let _ = requests.acquire_many(max_concurrent_requests as u32).await;
+                    break;
+                }
+            };
+            // 统计的耗时包含排队等待的时间
+            let received_at = Instant::now();
+
+            if let Ok(Some(rpc_msg)) = &received {
+                registry.touch(conn_id);
+                if let Some(recorder) = &recorder {
+                    let kind = match &rpc_msg.payload {
+                        JsonRpc::Notification(_) => RecordKind::Notification,
+                        _ => RecordKind::Request,
+                    };
+                    recorder.record(conn_id, kind, &rpc_msg.payload);
+                }
+            }
+
+            let overflow = limiter.limits().get_overflow();
+            match received {
                 Ok(received) => match received {
                     Some(rpc_msg) => match &rpc_msg.payload {
                         JsonRpc::Request(_) => {
                             let id = rpc_msg.payload.get_id().unwrap();
-                            let id2 = id.clone();
-
-                            // Signal 的 clone 是轻量级操作
+                            let method =
+                                rpc_msg.payload.get_method().unwrap_or_default().to_owned();
+                            // 订阅、握手等保留方法
+                            if let Some(reply) = Self::handle_reserved(
+                                conn_id,
+                                peer.outbound(),
+                                &publisher,
+                                &info,
+                                &metrics,
+                                &rpc_msg.payload,
+                            ) {
+                                Self::send_reply(conn_id, &recorder, rpc_msg.reply_tx, reply).await;
+                                continue;
+                            }
 
-                            // 事件处理可能是耗时操作，分离到 blocking 线程进行
+                            // 达到并发上限时暂停接收直到有请求处理完成，或者直接拒绝
+                            let permit = match overflow {
+                                Overflow::Queue => match requests.clone().acquire_owned().await {
+                                    Ok(permit) => permit,
+                                    Err(_) => break,
+                                },
+                                Overflow::Reject => match requests.clone().try_acquire_owned() {
+                                    Ok(permit) => permit,
+                                    Err(TryAcquireError::NoPermits) => {
+                                        metrics.record(
+                                            &method,
+                                            received_at.elapsed(),
+                                            Outcome::Error,
+                                        );
+                                        let reply = limits::too_many_requests_reply(id);
+                                        Self::send_reply(
+                                            conn_id,
+                                            &recorder,
+                                            rpc_msg.reply_tx,
+                                            reply,
+                                        )
+                                        .await;
+                                        continue;
+                                    }
+                                    Err(TryAcquireError::Closed) => break,
+                                },
+                            };
+
+                            let IpcMsg {
+                                payload,
+                                reply_tx,
+                                cancel_rx,
+                                progress_tx,
+                                body_rx,
+                                reply_body_tx,
+                            } = rpc_msg;
+                            let key = deadline::id_key(&id);
+                            let token = abort.child_token();
+                            // 调用方请求流式回复时启动进度转发
+                            let (progress, progress_task) = match progress_tx {
+                                Some(progress_tx) => {
+                                    let (progress, task) = Progress::spawn(progress_tx);
+                                    (progress, Some(task))
+                                }
+                                None => (Progress::default(), None),
+                            };
+                            in_flight.lock().insert(
+                                key.clone(),
+                                InFlight {
+                                    cancel: token.clone(),
+                                    progress,
+                                    body: Body::default(),
+                                },
+                            );
 
                             let self_cloned = this.clone();
-                            let on_request_cloned = on_request.clone();
-
-                            let blocking_res = tokio::task::spawn_blocking(move || {
-                                Box::new(on_request_cloned.emit(self_cloned, id2, rpc_msg.payload))
-                            })
-                            .await
-                            .unwrap();
+                            let gate = gate.clone();
+                            let info = info.clone();
+                            let in_flight = in_flight.clone();
+                            let recorder = recorder.clone();
+                            let limiter = limiter.clone();
+                            let metrics = metrics.clone();
+                            tasks.spawn(async move {
+                                let id2 = id.clone();
+                                let in_flight2 = in_flight.clone();
+                                let key2 = key.clone();
+                                let handler = async move {
+                                    // 打包的参数与请求同时发送，编码不受支持时拒绝
+                                    if let Some(body_rx) = body_rx {
+                                        let packed = match body_rx.await {
+                                            Ok(packed) => packed,
+                                            Err(_) => {
+                                                let error = jsonrpc_lite::Error::invalid_request();
+                                                return JsonRpc::error(id2, error);
+                                            }
+                                        };
+                                        if let Err((size, max)) =
+                                            limiter.check_len(packed.data.len())
+                                        {
+                                            return limits::too_large_reply(id2, size, max);
+                                        }
+                                        if !info.lock().codecs.contains(&packed.codec) {
+                                            log::warn!(
+                                                "PipeIo: client[{conn_id}] uses unsupported codec {}",
+                                                packed.codec
+                                            );
+                                            let error = jsonrpc_lite::Error::invalid_request();
+                                            return JsonRpc::error(id2, error);
+                                        }
+                                        if let Some(in_flight) = in_flight2.lock().get_mut(&key2) {
+                                            in_flight.body = Body::new(packed);
+                                        }
+                                    }
 
-                            match *blocking_res {
-                                Some(reply) => {
-                                    if let Some(tx) = rpc_msg.reply_tx {
-                                        tx.send(reply).await.unwrap();
+                                    match batch::parse_batch(&payload) {
+                                        Some(Ok((items, atomic))) => {
+                                            let replies =
+                                                Self::process_batch(self_cloned, items, atomic)
+                                                    .await;
+                                            JsonRpc::success(id2, &json!(replies))
+                                        }
+                                        Some(Err(error)) => JsonRpc::error(id2, error),
+                                        None => {
+                                            let _shared = gate.read().await;
+                                            Self::emit_request(self_cloned, id2, payload).await
+                                        }
                                     }
+                                };
+
+                                // 对端取消请求时触发令牌，不再等待处理结果
+                                let reply =
+                                    deadline::run_cancellable(&id, cancel_rx, token, handler).await;
+                                let reply_body = in_flight
+                                    .lock()
+                                    .remove(&key)
+                                    .and_then(|in_flight| in_flight.body.take_reply());
+                                drop(permit);
+                                metrics.record(
+                                    &method,
+                                    received_at.elapsed(),
+                                    Outcome::of(reply.as_ref()),
+                                );
+
+                                // 所有进度在最终回复之前送达
+                                if let Some(task) = progress_task {
+                                    task.finish().await;
                                 }
-                                None => {
-                                    // internal_error
-                                    if let Some(tx) = rpc_msg.reply_tx {
-                                        tx.send(jsonrpc_lite::JsonRpc::error(
-                                            id,
-                                            jsonrpc_lite::Error::internal_error(),
-                                        ))
-                                        .await
-                                        .unwrap();
-                                    }
+                                // 打包的返回值同样在最终回复之前送达
+                                if let (Some(tx), Some(reply_body)) = (reply_body_tx, reply_body) {
+                                    let _ = tx.send(reply_body);
                                 }
-                            }
+                                if let (Some(reply), Some(recorder)) = (&reply, &recorder) {
+                                    recorder.record(conn_id, RecordKind::Reply, reply);
+                                }
+                                if let (Some(reply), Some(tx)) = (reply, reply_tx) {
+                                    let _ = tx.send(reply).await;
+                                }
+                            });
                         }
                         JsonRpc::Notification(_) => {
-                            if let Some(tx) = rpc_msg.reply_tx {
-                                tx.send(jsonrpc_lite::JsonRpc::error(
-                                    0,
-                                    jsonrpc_lite::Error::invalid_request(),
-                                ))
-                                .await
-                                .unwrap();
-                            }
+                            // 通知与请求共享并发上限，被拒绝的通知直接丢弃
+                            let permit = match overflow {
+                                Overflow::Queue => match requests.clone().acquire_owned().await {
+                                    Ok(permit) => permit,
+                                    Err(_) => break,
+                                },
+                                Overflow::Reject => match requests.clone().try_acquire_owned() {
+                                    Ok(permit) => permit,
+                                    Err(TryAcquireError::NoPermits) => continue,
+                                    Err(TryAcquireError::Closed) => break,
+                                },
+                            };
+
+                            // 通知不需要回复，不阻塞后续消息的接收
+                            let self_cloned = this.clone();
+                            let gate = gate.clone();
+                            let metrics = metrics.clone();
+                            tasks.spawn(async move {
+                                let _shared = gate.read().await;
+                                let method =
+                                    rpc_msg.payload.get_method().unwrap_or_default().to_owned();
+                                Self::emit_notification(self_cloned, rpc_msg.payload).await;
+                                drop(permit);
+                                metrics.record(&method, received_at.elapsed(), Outcome::Ok);
+                            });
                         }
                         JsonRpc::Success(_) | JsonRpc::Error(_) => {
                             panic!("单向链路只应该收到 Reuquest 和 Notification");
@@ -258,311 +1060,152 @@ impl Socket {
                 }
             }
         }
-    }
-}
-
-/// Security attributes.
-pub struct SecurityAttributes {
-    attributes: Option<InnerAttributes>,
-}
-
-pub const DEFAULT_SECURITY_ATTRIBUTES: SecurityAttributes = SecurityAttributes {
-    attributes: Some(InnerAttributes {
-        descriptor: SecurityDescriptor {
-            descriptor_ptr: PSECURITY_DESCRIPTOR(std::ptr::null_mut()),
-        },
-        acl: Acl {
-            acl_ptr: std::ptr::null_mut(),
-        },
-        attrs: SECURITY_ATTRIBUTES {
-            nLength: std::mem::size_of::<SECURITY_ATTRIBUTES>() as u32,
-            lpSecurityDescriptor: std::ptr::null_mut(),
-            bInheritHandle: BOOL(0),
-        },
-    }),
-};
-
-impl SecurityAttributes {
-    /// New default security attributes.
-    pub fn empty() -> SecurityAttributes {
-        DEFAULT_SECURITY_ATTRIBUTES
-    }
-
-    /// New default security attributes that allow everyone to connect.
-    pub fn allow_everyone_connect(&self) -> std::io::Result<SecurityAttributes> {
-        let attributes = Some(InnerAttributes::allow_everyone(
-            GENERIC_READ | FILE_WRITE_DATA.0,
-        )?);
-        Ok(SecurityAttributes { attributes })
-    }
-
-    /// Set a custom permission on the socket
-    pub fn set_mode(self, _mode: u32) -> std::io::Result<Self> {
-        // for now, does nothing.
-        Ok(self)
-    }
-
-    /// New default security attributes that allow everyone to create.
-    pub fn allow_everyone_create() -> std::io::Result<SecurityAttributes> {
-        let attributes = Some(InnerAttributes::allow_everyone(
-            GENERIC_READ | GENERIC_WRITE,
-        )?);
-        Ok(SecurityAttributes { attributes })
-    }
-
-    /// Return raw handle of security attributes.
-    pub(crate) unsafe fn as_ptr(&mut self) -> *mut SECURITY_ATTRIBUTES {
-        match self.attributes.as_mut() {
-            Some(attributes) => attributes.as_ptr(),
-            None => std::ptr::null_mut(),
-        }
-    }
-}
-
-unsafe impl Send for SecurityAttributes {}
-
-struct Sid {
-    sid_ptr: PSID,
-}
-
-impl Sid {
-    fn everyone_sid() -> std::io::Result<Sid> {
-        pub const SECURITY_WORLD_SID_AUTHORITY: [u8; 6] = [0, 0, 0, 0, 0, 1];
-        pub const SECURITY_WORLD_RID: u32 = 0x00000000;
-
-        let mut sid_ptr = PSID(std::ptr::null_mut());
-        let result = unsafe {
-            #[allow(const_item_mutation)]
-            AllocateAndInitializeSid(
-                SECURITY_WORLD_SID_AUTHORITY.as_mut_ptr() as *mut _,
-                1,
-                SECURITY_WORLD_RID,
-                0,
-                0,
-                0,
-                0,
-                0,
-                0,
-                0,
-                &mut sid_ptr as *mut PSID,
-            )
-        };
-
-        if !result.as_bool() {
-            Err(std::io::Error::last_os_error())
-        } else {
-            Ok(Sid { sid_ptr })
-        }
-    }
-
-    // Unsafe - the returned pointer is only valid for the lifetime of self.
-    unsafe fn as_ptr(&self) -> PSID {
-        self.sid_ptr
-    }
-}
-
-impl Drop for Sid {
-    fn drop(&mut self) {
-        if !self.sid_ptr.is_invalid() {
-            unsafe {
-                FreeSid(self.sid_ptr);
-            }
-        }
-    }
-}
-
-struct AceWithSid<'a> {
-    explicit_access: EXPLICIT_ACCESS_W,
-    _marker: marker::PhantomData<&'a Sid>,
-}
-
-impl<'a> AceWithSid<'a> {
-    fn new(sid: &'a Sid, trustee_type: i32) -> AceWithSid<'a> {
-        let mut explicit_access = unsafe { std::mem::zeroed::<EXPLICIT_ACCESS_W>() };
-        explicit_access.Trustee.TrusteeForm = TRUSTEE_IS_SID;
-        explicit_access.Trustee.TrusteeType = TRUSTEE_TYPE(trustee_type as i32);
-        explicit_access.Trustee.ptstrName = unsafe { PWSTR(std::mem::transmute(sid.as_ptr())) };
-
-        AceWithSid {
-            explicit_access,
-            _marker: marker::PhantomData,
-        }
-    }
-
-    fn set_access_mode(&mut self, access_mode: i32) -> &mut Self {
-        self.explicit_access.grfAccessMode = ACCESS_MODE(access_mode);
-        self
-    }
-
-    fn set_access_permissions(&mut self, access_permissions: u32) -> &mut Self {
-        self.explicit_access.grfAccessPermissions = access_permissions;
-        self
-    }
-
-    fn allow_inheritance(&mut self, inheritance_flags: u32) -> &mut Self {
-        self.explicit_access.grfInheritance = ACE_FLAGS(inheritance_flags);
-        self
-    }
-}
 
-struct Acl {
-    acl_ptr: *mut ACL,
-}
+        // 正常退出时剩余的任务继续运行，完成回复的发送
+        tasks.detach_all();
 
-impl Acl {
-    fn empty() -> std::io::Result<Acl> {
-        Self::new(&mut [])
+        publisher.remove_connection(conn_id);
+        registry.remove(conn_id);
+        limiter.remove_connection(conn_id);
+        metrics.connection_closed();
     }
 
-    fn new(entries: &mut [AceWithSid<'_>]) -> std::io::Result<Acl> {
-        let mut acl_ptr = std::ptr::null_mut();
-        let result = unsafe {
-            SetEntriesInAclW(
-                Some(unsafe {
-                    &*(entries as *mut _ as *mut [EXPLICIT_ACCESS_W]) as &[EXPLICIT_ACCESS_W]
-                }),
-                None,
-                &mut acl_ptr,
-            )
-        };
-
-        if result != ERROR_SUCCESS.0 {
-            return Err(std::io::Error::from_raw_os_error(result as i32));
+    /// 不经过请求回调直接回复，用于保留方法与被限制的请求
+    async fn send_reply(
+        conn_id: ConnectionId,
+        recorder: &Option<Recorder>,
+        reply_tx: Option<rch::mpsc::Sender<JsonRpc>>,
+        reply: JsonRpc,
+    ) {
+        if let Some(recorder) = recorder {
+            recorder.record(conn_id, RecordKind::Reply, &reply);
         }
-
-        Ok(Acl { acl_ptr })
-    }
-
-    unsafe fn as_ptr(&self) -> *mut ACL {
-        self.acl_ptr
-    }
-}
-
-impl Drop for Acl {
-    fn drop(&mut self) {
-        if !self.acl_ptr.is_null() {
-            unsafe { LocalFree(std::mem::transmute(self.acl_ptr)) };
+        if let Some(tx) = reply_tx {
+            let _ = tx.send(reply).await;
         }
     }
 }
 
-struct SecurityDescriptor {
-    descriptor_ptr: PSECURITY_DESCRIPTOR,
-}
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use jsonrpc_lite::JsonRpc;
+    use serde_json::json;
+
+    use super::Server;
+    use crate::client::Client;
+    use crate::transport::memory;
+
+    #[tokio::test]
+    async fn test_async_handler_concurrency() {
+        let (listener, connector) = memory::channel();
+
+        let active = Arc::new(AtomicUsize::new(0));
+        let max_active = Arc::new(AtomicUsize::new(0));
+
+        let mut server = Server::with_listener("memory", listener);
+        server.set_max_concurrent_requests(2);
+        {
+            let active = active.clone();
+            let max_active = max_active.clone();
+            server.on_connection(move |socket, _| {
+                let active = active.clone();
+                let max_active = max_active.clone();
+                socket.lock().on_request_async(move |ctx, req| {
+                    let active = active.clone();
+                    let max_active = max_active.clone();
+                    async move {
+                        if req.get_method() == Some("callback") {
+                            // 在处理过程中回调对端方法
+                            return match ctx.peer.call_with_params("client-method", json!({})).await
+                            {
+                                Ok(reply) => reply,
+                                Err(_) => {
+                                    JsonRpc::error(ctx.id, jsonrpc_lite::Error::internal_error())
+                                }
+                            };
+                        }
 
-#[cfg(target_pointer_width = "64")]
-pub const SECURITY_DESCRIPTOR_MIN_LENGTH: usize = 40;
-#[cfg(target_pointer_width = "32")]
-pub const SECURITY_DESCRIPTOR_MIN_LENGTH: usize = 20;
-
-impl SecurityDescriptor {
-    fn new() -> std::io::Result<Self> {
-        let descriptor_ptr = unsafe { LocalAlloc(LPTR, SECURITY_DESCRIPTOR_MIN_LENGTH) };
-        let descriptor_ptr = PSECURITY_DESCRIPTOR(unsafe { std::mem::transmute(descriptor_ptr) });
-        if descriptor_ptr.is_invalid() {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "Failed to allocate security descriptor",
-            ));
+                        let current = active.fetch_add(1, Ordering::SeqCst) + 1;
+                        max_active.fetch_max(current, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                        active.fetch_sub(1, Ordering::SeqCst);
+                        JsonRpc::success(ctx.id, &json!(true))
+                    }
+                });
+                0
+            });
         }
+        tokio::spawn(async move { server.listen().await });
 
-        if unsafe {
-            !InitializeSecurityDescriptor(descriptor_ptr, SECURITY_DESCRIPTOR_REVISION).as_bool()
-        } {
-            return Err(std::io::Error::last_os_error());
-        };
-
-        Ok(SecurityDescriptor { descriptor_ptr })
-    }
-
-    fn set_dacl(&mut self, acl: &Acl) -> std::io::Result<()> {
-        if unsafe {
-            !SetSecurityDescriptorDacl(
-                self.descriptor_ptr, //
-                BOOL(1),
-                Some(acl.as_ptr()),
-                BOOL(0),
-            )
-            .as_bool()
-        } {
-            return Err(std::io::Error::last_os_error());
-        }
-        Ok(())
-    }
+        let mut client = Client::with_connector(connector);
+        client
+            .on_request(|_, req| JsonRpc::success(req.get_id().unwrap(), &json!("from client")))
+            .await;
+        client.connect().await.unwrap();
 
-    unsafe fn as_ptr(&self) -> PSECURITY_DESCRIPTOR {
-        self.descriptor_ptr
-    }
-}
+        let reply = client
+            .call_with_params("callback", json!({}))
+            .await
+            .unwrap();
+        assert_eq!(reply.get_result(), Some(&json!("from client")));
 
-impl Drop for SecurityDescriptor {
-    fn drop(&mut self) {
-        if !self.descriptor_ptr.is_invalid() {
-            unsafe { LocalFree(std::mem::transmute(self.descriptor_ptr)) };
-            self.descriptor_ptr = PSECURITY_DESCRIPTOR(std::ptr::null_mut());
+        let peer = client.peer().unwrap();
+        let calls: Vec<_> = (0..6)
+            .map(|_| {
+                let peer = peer.clone();
+                tokio::spawn(async move { peer.call_with_params("slow", json!({})).await })
+            })
+            .collect();
+        for call in calls {
+            assert!(call.await.unwrap().is_ok());
         }
+        assert_eq!(max_active.load(Ordering::SeqCst), 2);
     }
-}
-
-struct InnerAttributes {
-    descriptor: SecurityDescriptor,
-    acl: Acl,
-    attrs: SECURITY_ATTRIBUTES,
-}
-
-impl InnerAttributes {
-    fn empty() -> std::io::Result<InnerAttributes> {
-        let descriptor = SecurityDescriptor::new()?;
-        let mut attrs = unsafe { std::mem::zeroed::<SECURITY_ATTRIBUTES>() };
-        attrs.nLength = std::mem::size_of::<SECURITY_ATTRIBUTES>() as u32;
-        attrs.lpSecurityDescriptor = unsafe { std::mem::transmute(descriptor.as_ptr()) };
-        attrs.bInheritHandle = BOOL(1); // false as i32;
-
-        let acl = Acl::empty().expect("this should never fail");
-
-        Ok(InnerAttributes {
-            acl,
-            descriptor,
-            attrs,
-        })
-    }
-
-    fn allow_everyone(permissions: u32) -> std::io::Result<InnerAttributes> {
-        let mut attributes = Self::empty()?;
-        let sid = Sid::everyone_sid()?;
-
-        let mut everyone_ace = AceWithSid::new(&sid, TRUSTEE_IS_WELL_KNOWN_GROUP.0);
-        everyone_ace
-            .set_access_mode(SET_ACCESS.0)
-            .set_access_permissions(permissions)
-            .allow_inheritance(false as u32);
 
-        let mut entries = vec![everyone_ace];
-        attributes.acl = Acl::new(&mut entries)?;
-        attributes.descriptor.set_dacl(&attributes.acl)?;
+    #[tokio::test]
+    async fn test_shutdown_drains_in_flight_requests() {
+        let (listener, connector) = memory::channel();
+
+        let started = Arc::new(tokio::sync::Notify::new());
+        let started_cloned = started.clone();
+
+        let mut server = Server::with_listener("memory", listener);
+        server.on_connection(move |socket, _| {
+            let started = started_cloned.clone();
+            socket.lock().on_request_async(move |ctx, _| {
+                let started = started.clone();
+                async move {
+                    started.notify_one();
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    JsonRpc::success(ctx.id, &json!("done"))
+                }
+            });
+            0
+        });
+        let shutdown = server.shutdown_handle();
+        let listen = tokio::spawn(async move { server.listen().await });
 
-        Ok(attributes)
-    }
+        let mut client = Client::with_connector(connector);
+        client.set_reconnect_policy(None);
+        client.connect().await.unwrap();
 
-    unsafe fn as_ptr(&mut self) -> *mut SECURITY_ATTRIBUTES {
-        &mut self.attrs as *mut _
-    }
-}
+        let peer = client.peer().unwrap();
+        let call = tokio::spawn(async move { peer.call_with_params("slow", json!({})).await });
 
-#[cfg(test)]
-mod test {
-    use super::SecurityAttributes;
+        // 请求开始处理后关闭服务器
+        started.notified().await;
+        shutdown.shutdown();
 
-    #[test]
-    fn test_allow_everyone_everything() {
-        SecurityAttributes::allow_everyone_create()
-            .expect("failed to create security attributes that allow everyone to create a pipe");
-    }
+        // 正在处理的请求完成后 listen 返回
+        let reply = call.await.unwrap().unwrap();
+        assert_eq!(reply.get_result(), Some(&json!("done")));
+        assert!(listen.await.unwrap().is_ok());
 
-    #[test]
-    fn test_allow_eveyone_read_write() {
-        SecurityAttributes::empty()
-            .allow_everyone_connect()
-            .expect("failed to create security attributes that allow everyone to read and write to/from a pipe");
+        // 服务器关闭后不再处理新的请求
+        assert!(client.call_with_params("slow", json!({})).await.is_err());
     }
 }
@@ -0,0 +1,432 @@
+//
+// Copyright (C) Lenovo ThinkBook Gen4 Project.
+//
+// This program is protected under international and China copyright laws as
+// an unpublished work. This program is confidential and proprietary to the
+// copyright owners. Reproduction or disclosure, in whole or in part, or the
+// production of derivative works therefrom without the express permission of
+// the copyright owners is prohibited.
+//
+// All rights reserved.
+//
+
+//! 类型化的 RPC 服务定义
+//!
+//! 使用 `rpc_service!` 声明一次服务接口，同时生成服务端分发函数与客户端存根，
+//! 方法名称取自函数名，参数与返回值通过 serde 序列化，参数解析失败时自动返回
+//! `invalid_params` 错误。客户端存根使用握手协商的编码，见 `codec`。
+//!
+//! ```ignore
+//! eink_pipe_io::rpc_service! {
+//!     /// TCON 服务
+//!     pub mod tcon {
+//!         /// 设置 MIPI 模式
+//!         fn set_mipi_mode(SetMipiModeParams) -> Ack;
+//!         /// 获得当前 MIPI 模式
+//!         fn get_mipi_mode(Empty) -> u32;
+//!     }
+//! }
+//!
+//! // 服务端
+//! tcon::serve(&mut server, Arc::new(TconRpc { .. }));
+//!
+//! // 客户端
+//! let mode = tcon::BlockingClient::new(&mut client).get_mipi_mode(Empty {})?;
+//! ```
+
+use std::fmt;
+use std::sync::Arc;
+
+use jsonrpc_lite::{Id, JsonRpc, Params};
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+
+use crate::codec::Body;
+use crate::server::Server;
+
+/// 服务端方法的返回值
+pub type RpcResult<T> = Result<T, jsonrpc_lite::Error>;
+
+/// 客户端存根的返回值
+pub type CallResult<T> = anyhow::Result<T>;
+
+/// 无参数方法的参数，序列化为 `{}`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct Empty {}
+
+/// 无返回值方法的回复，序列化为历史兼容的字符串 `"true"`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Ack;
+
+impl Serialize for Ack {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str("true")
+    }
+}
+
+impl<'de> Deserialize<'de> for Ack {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // bincode 不支持忽略任意类型的值
+        if deserializer.is_human_readable() {
+            serde::de::IgnoredAny::deserialize(deserializer)?;
+        } else {
+            String::deserialize(deserializer)?;
+        }
+        Ok(Ack)
+    }
+}
+
+impl JsonSchema for Ack {
+    fn schema_name() -> String {
+        "Ack".to_owned()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        String::json_schema(gen)
+    }
+}
+
+/// 对端返回的 JSON-RPC 错误
+#[derive(Clone, Debug, PartialEq)]
+pub struct RemoteError(pub jsonrpc_lite::Error);
+
+impl fmt::Display for RemoteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Remote error {}: {}", self.0.code, self.0.message)
+    }
+}
+
+impl std::error::Error for RemoteError {}
+
+/// 解析请求参数，失败时返回带有错误描述的 `invalid_params`
+///
+/// 调用方打包了参数时从当前请求的 `Body` 中解析
+pub fn decode_params<T: DeserializeOwned>(req: &JsonRpc) -> RpcResult<T> {
+    let body = Body::current();
+    if body.is_packed() {
+        return body.decode().map_err(|err| {
+            let mut error = jsonrpc_lite::Error::invalid_params();
+            error.data = Some(Value::String(err.to_string()));
+            error
+        });
+    }
+
+    let value = match req.get_params() {
+        Some(Params::Map(map)) => Value::Object(map),
+        Some(Params::Array(array)) => Value::Array(array),
+        Some(Params::None(_)) | None => Value::Object(Default::default()),
+    };
+
+    // 标量参数被 `encode_params` 包装为单个元素的数组
+    let unwrapped = match &value {
+        Value::Array(array) if array.len() == 1 => Some(array[0].clone()),
+        _ => None,
+    };
+    serde_json::from_value(value).or_else(|err| {
+        unwrapped
+            .and_then(|value| serde_json::from_value(value).ok())
+            .ok_or_else(|| {
+                let mut error = jsonrpc_lite::Error::invalid_params();
+                error.data = Some(Value::String(err.to_string()));
+                error
+            })
+    })
+}
+
+/// 将服务端方法的返回值编码为回复
+///
+/// 调用方打包了参数时返回值也按相同的编码打包，回复的结果为 null
+pub fn encode_reply<T: Serialize>(id: Id, reply: RpcResult<T>) -> JsonRpc {
+    let body = Body::current();
+    if body.is_packed() {
+        return match reply.and_then(|reply| {
+            body.set_reply(&reply)
+                .map_err(|_| jsonrpc_lite::Error::internal_error())
+        }) {
+            Ok(()) => JsonRpc::success(id, &Value::Null),
+            Err(error) => JsonRpc::error(id, error),
+        };
+    }
+
+    match reply.and_then(|reply| {
+        serde_json::to_value(reply).map_err(|_| jsonrpc_lite::Error::internal_error())
+    }) {
+        Ok(result) => JsonRpc::success(id, &result),
+        Err(error) => JsonRpc::error(id, error),
+    }
+}
+
+/// 将客户端参数编码为请求参数
+///
+/// JSON-RPC 的参数只能是对象或数组，标量参数包装为单个元素的数组，
+/// 由 `decode_params` 解开
+pub fn encode_params<T: Serialize>(params: &T) -> CallResult<Params> {
+    Ok(match serde_json::to_value(params)? {
+        Value::Object(map) => Params::Map(map),
+        Value::Array(array) => Params::Array(array),
+        value => Params::Array(vec![value]),
+    })
+}
+
+/// 解析对端回复，错误回复转换为 `RemoteError`
+pub fn decode_reply<T: DeserializeOwned>(reply: JsonRpc) -> CallResult<T> {
+    match &reply {
+        JsonRpc::Success(_) => {
+            let result = reply.get_result().cloned().unwrap_or(Value::Null);
+            Ok(serde_json::from_value(result)?)
+        }
+        JsonRpc::Error(_) => match reply.get_error() {
+            Some(error) => Err(RemoteError(error.clone()).into()),
+            None => anyhow::bail!("Invalid error reply"),
+        },
+        _ => anyhow::bail!("Unexpected reply: {reply:?}"),
+    }
+}
+
+/// 在服务器的每个连接上挂载请求处理函数
+pub fn serve<F>(server: &mut Server, handler: F)
+where
+    F: Fn(Id, JsonRpc) -> JsonRpc + Send + Sync + 'static,
+{
+    let handler = Arc::new(handler);
+    let _ = server.on_connection(move |socket, _| {
+        let handler = handler.clone();
+        socket
+            .lock()
+            .on_request(move |_socket, id, req| handler(id, req));
+        0
+    });
+}
+
+/// 声明 RPC 服务，生成同名模块，包含：
+///
+/// * `METHODS`：方法名称列表
+/// * `method`：方法名称常量
+/// * `describe`：方法描述，包含参数与返回值的 JSON Schema，用于握手与 `rpc.discover`
+/// * `Service`：服务端需要实现的接口
+/// * `dispatch` / `serve`：服务端分发函数
+/// * `Client` / `BlockingClient`：客户端存根
+#[macro_export]
+macro_rules! rpc_service {
+    (
+        $(#[$meta:meta])*
+        $vis:vis mod $module:ident {
+            $(
+                $(#[$method_meta:meta])*
+                fn $method:ident($params:ty) -> $reply:ty;
+            )*
+        }
+    ) => {
+        $(#[$meta])*
+        #[allow(dead_code)]
+        $vis mod $module {
+            #[allow(unused_imports)]
+            use super::*;
+
+            /// 服务的方法名称
+            pub const METHODS: &[&str] = &[$(stringify!($method)),*];
+
+            /// 方法名称常量，用于构造批量请求
+            pub mod method {
+                $(
+                    #[allow(non_upper_case_globals)]
+                    pub const $method: &str = stringify!($method);
+                )*
+            }
+
+            /// 方法描述，参数与返回值类型需要实现 `schemars::JsonSchema`
+            pub fn describe() -> ::std::vec::Vec<$crate::handshake::MethodInfo> {
+                ::std::vec![
+                    $($crate::handshake::MethodInfo::new::<$params, $reply>(stringify!($method))),*
+                ]
+            }
+
+            /// 服务端接口
+            pub trait Service: Send + Sync + 'static {
+                $(
+                    $(#[$method_meta])*
+                    fn $method(&self, params: $params) -> $crate::service::RpcResult<$reply>;
+                )*
+            }
+
+            /// 将请求分发到服务实现
+            pub fn dispatch<S: Service + ?Sized>(
+                service: &S,
+                id: $crate::jsonrpc::Id,
+                req: $crate::jsonrpc::JsonRpc,
+            ) -> $crate::jsonrpc::JsonRpc {
+                let method = match req.get_method() {
+                    Some(method) => method,
+                    None => {
+                        return $crate::jsonrpc::JsonRpc::error(
+                            id,
+                            $crate::jsonrpc::Error::invalid_request(),
+                        )
+                    }
+                };
+                $(
+                    if method == stringify!($method) {
+                        let reply = $crate::service::decode_params::<$params>(&req)
+                            .and_then(|params| service.$method(params));
+                        return $crate::service::encode_reply(id, reply);
+                    }
+                )*
+                $crate::jsonrpc::JsonRpc::error(id, $crate::jsonrpc::Error::method_not_found())
+            }
+
+            /// 在服务器的每个连接上挂载服务实现
+            pub fn serve<S: Service>(
+                server: &mut $crate::server::Server,
+                service: ::std::sync::Arc<S>,
+            ) {
+                server.register_methods(describe());
+                $crate::service::serve(server, move |id, req| dispatch(&*service, id, req))
+            }
+
+            /// 异步客户端存根
+            pub struct Client<'a> {
+                inner: &'a mut $crate::client::Client,
+            }
+
+            impl<'a> Client<'a> {
+                pub fn new(inner: &'a mut $crate::client::Client) -> Self {
+                    Self { inner }
+                }
+
+                $(
+                    $(#[$method_meta])*
+                    pub async fn $method(
+                        &mut self,
+                        params: $params,
+                    ) -> $crate::service::CallResult<$reply> {
+                        self.inner.call_encoded(stringify!($method), &params).await
+                    }
+                )*
+            }
+
+            /// 同步客户端存根
+            pub struct BlockingClient<'a> {
+                inner: &'a mut $crate::blocking::BlockingClient,
+            }
+
+            impl<'a> BlockingClient<'a> {
+                pub fn new(inner: &'a mut $crate::blocking::BlockingClient) -> Self {
+                    Self { inner }
+                }
+
+                $(
+                    $(#[$method_meta])*
+                    pub fn $method(&mut self, params: $params) -> $crate::service::CallResult<$reply> {
+                        self.inner.call_encoded(stringify!($method), &params)
+                    }
+                )*
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use jsonrpc_lite::{Id, JsonRpc};
+    use schemars::JsonSchema;
+    use serde::{Deserialize, Serialize};
+    use serde_json::json;
+
+    use super::{Ack, Empty, RemoteError, RpcResult};
+    use crate::client::Client;
+    use crate::server::Server;
+    use crate::transport::memory;
+
+    #[derive(Serialize, Deserialize, JsonSchema)]
+    struct AddParams {
+        a: u32,
+        b: u32,
+    }
+
+    crate::rpc_service! {
+        mod calc {
+            fn add(AddParams) -> u32;
+            fn double(u32) -> u32;
+            fn reset(Empty) -> Ack;
+        }
+    }
+
+    struct Calc;
+
+    impl calc::Service for Calc {
+        fn add(&self, params: AddParams) -> RpcResult<u32> {
+            Ok(params.a + params.b)
+        }
+
+        fn double(&self, params: u32) -> RpcResult<u32> {
+            Ok(params * 2)
+        }
+
+        fn reset(&self, _params: Empty) -> RpcResult<Ack> {
+            Ok(Ack)
+        }
+    }
+
+    #[test]
+    fn test_dispatch_validates_params() {
+        let id = Id::Num(1);
+
+        let req = JsonRpc::request_with_params(1, "add", json!({ "a": 1, "b": 2 }));
+        let reply = calc::dispatch(&Calc, id.clone(), req);
+        assert_eq!(reply.get_result(), Some(&json!(3)));
+
+        let req = JsonRpc::request_with_params(1, "add", json!({ "a": "1" }));
+        let reply = calc::dispatch(&Calc, id.clone(), req);
+        assert_eq!(reply.get_error().unwrap().code, -32602);
+
+        let req = JsonRpc::request_with_params(1, "double", json!([21]));
+        let reply = calc::dispatch(&Calc, id.clone(), req);
+        assert_eq!(reply.get_result(), Some(&json!(42)));
+
+        let req = JsonRpc::request(1, "reset");
+        let reply = calc::dispatch(&Calc, id.clone(), req);
+        assert_eq!(reply.get_result(), Some(&json!("true")));
+
+        let req = JsonRpc::request(1, "ad");
+        let reply = calc::dispatch(&Calc, id, req);
+        assert_eq!(reply.get_error().unwrap().code, -32601);
+    }
+
+    #[tokio::test]
+    async fn test_typed_client() {
+        let (listener, connector) = memory::channel();
+
+        let mut server = Server::with_listener("memory", listener);
+        calc::serve(&mut server, Arc::new(Calc));
+        tokio::spawn(async move { server.listen().await });
+
+        let mut client = Client::with_connector(connector);
+        client.connect().await.unwrap();
+
+        let info = client.server_info().unwrap();
+        assert!(info.supports(calc::method::add));
+        assert_eq!(
+            info.method("add").unwrap().params["required"],
+            json!(["a", "b"])
+        );
+
+        let mut calc = calc::Client::new(&mut client);
+        assert_eq!(calc.add(AddParams { a: 40, b: 2 }).await.unwrap(), 42);
+        assert_eq!(calc.double(21).await.unwrap(), 42);
+        assert_eq!(calc.reset(Empty {}).await.unwrap(), Ack);
+
+        let err = client
+            .call_with_params("add", json!({}))
+            .await
+            .map(super::decode_reply::<u32>)
+            .unwrap()
+            .unwrap_err();
+        assert!(err.downcast_ref::<RemoteError>().is_some());
+    }
+}
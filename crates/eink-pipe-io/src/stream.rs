@@ -0,0 +1,265 @@
+//
+// Copyright (C) Lenovo ThinkBook Gen4 Project.
+//
+// This program is protected under international and China copyright laws as
+// an unpublished work. This program is confidential and proprietary to the
+// copyright owners. Reproduction or disclosure, in whole or in part, or the
+// production of derivative works therefrom without the express permission of
+// the copyright owners is prohibited.
+//
+// All rights reserved.
+//
+
+//! 流式回复与进度报告
+//!
+//! 调用方通过 `IpcMsg::progress_tx` 携带一个 remoc 通道，服务端处理请求期间可以
+//! 通过 `Progress` 发送任意数量的进度或部分结果，最后仍然通过 `reply_tx` 回复。
+//! 服务端保证所有进度在最终回复之前送达。调用方丢弃或取消 `CallStream` 时，
+//! 对端的取消令牌被触发，后续的 `Progress::send` 返回错误。
+
+use std::cell::RefCell;
+
+use anyhow::{anyhow, bail};
+use jsonrpc_lite::JsonRpc;
+use remoc::rch;
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+use crate::deadline::{self, CallOptions, CancellationToken};
+
+/// 每个流缓存的进度数量
+pub(crate) const PROGRESS_BUFFER: usize = 16;
+
+thread_local! {
+    /// 当前线程正在处理的请求的进度发送端，用于同步处理函数
+    static CURRENT: RefCell<Option<Progress>> = const { RefCell::new(None) };
+}
+
+/// 进度发送端，可以克隆到任意任务或线程中
+///
+/// 调用方没有请求流式回复时，发送操作被忽略
+#[derive(Clone, Debug, Default)]
+pub struct Progress {
+    tx: Option<mpsc::UnboundedSender<Value>>,
+}
+
+impl Progress {
+    /// 启动转发任务，返回发送端与用于结束转发的句柄
+    pub(crate) fn spawn(remote: rch::mpsc::Sender<Value>) -> (Self, ProgressTask) {
+        let (tx, mut rx) = mpsc::unbounded_channel::<Value>();
+        let done = CancellationToken::new();
+        let done_cloned = done.clone();
+        let handle = tokio::spawn(async move {
+            loop {
+                let item = tokio::select! {
+                    item = rx.recv() => match item {
+                        Some(item) => item,
+                        None => break,
+                    },
+                    // 处理结束后发送剩余的进度
+                    _ = done_cloned.cancelled() => match rx.try_recv() {
+                        Ok(item) => item,
+                        Err(_) => break,
+                    },
+                };
+                if remote.send(item).await.is_err() {
+                    break;
+                }
+            }
+        });
+        (Self { tx: Some(tx) }, ProgressTask { done, handle })
+    }
+
+    /// 当前线程正在处理的请求的进度发送端，在同步请求回调中使用
+    pub fn current() -> Self {
+        CURRENT.with(|current| current.borrow().clone().unwrap_or_default())
+    }
+
+    /// 在 `f` 执行期间设置当前线程的进度发送端
+    pub(crate) fn scope<R>(progress: Progress, f: impl FnOnce() -> R) -> R {
+        let previous = CURRENT.with(|current| current.replace(Some(progress)));
+        let result = f();
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+        result
+    }
+
+    /// 调用方是否请求了流式回复
+    pub fn is_streaming(&self) -> bool {
+        self.tx.is_some()
+    }
+
+    /// 发送进度，调用方取消或链路断开时返回错误
+    pub fn send<T: Serialize>(&self, item: T) -> anyhow::Result<()> {
+        let tx = match &self.tx {
+            Some(tx) => tx,
+            None => return Ok(()),
+        };
+        tx.send(serde_json::to_value(item)?)
+            .map_err(|_| anyhow!("Stream was closed"))
+    }
+}
+
+/// 进度转发任务
+pub(crate) struct ProgressTask {
+    done: CancellationToken,
+    handle: JoinHandle<()>,
+}
+
+impl ProgressTask {
+    /// 发送剩余的进度并等待转发结束，之后才能发送最终回复
+    pub(crate) async fn finish(self) {
+        self.done.cancel();
+        let _ = self.handle.await;
+    }
+}
+
+/// 流式调用，先读取进度，再获取最终回复
+///
+/// ```ignore
+/// let mut stream = client.call_stream("set_shutdown_cover", params).await?;
+/// while let Some(progress) = stream.next().await {
+///     println!("progress: {progress}");
+/// }
+/// let reply = stream.finish().await?;
+/// ```
+pub struct CallStream {
+    method: String,
+    progress_rx: Option<rch::mpsc::Receiver<Value>>,
+    reply_rx: rch::mpsc::Receiver<JsonRpc>,
+    cancel_tx: rch::oneshot::Sender<()>,
+    deadline: Option<Instant>,
+    options: CallOptions,
+}
+
+impl CallStream {
+    pub(crate) fn new(
+        method: &str,
+        progress_rx: rch::mpsc::Receiver<Value>,
+        reply_rx: rch::mpsc::Receiver<JsonRpc>,
+        cancel_tx: rch::oneshot::Sender<()>,
+        deadline: Option<Instant>,
+        options: CallOptions,
+    ) -> Self {
+        Self {
+            method: method.to_owned(),
+            progress_rx: Some(progress_rx),
+            reply_rx,
+            cancel_tx,
+            deadline,
+            options,
+        }
+    }
+
+    /// 下一条进度，所有进度接收完成、超时或取消后返回 None
+    pub async fn next(&mut self) -> Option<Value> {
+        let progress_rx = self.progress_rx.as_mut()?;
+        let item = tokio::select! {
+            item = progress_rx.recv() => item.ok().flatten(),
+            _ = deadline::sleep_until(self.deadline) => None,
+            _ = deadline::cancelled(self.options.get_cancel()) => None,
+        };
+        if item.is_none() {
+            self.progress_rx = None;
+        }
+        item
+    }
+
+    /// 丢弃剩余的进度，等待最终回复
+    ///
+    /// 超时或取消时返回 `CallError`，并通知对端取消请求
+    pub async fn finish(mut self) -> anyhow::Result<JsonRpc> {
+        while self.next().await.is_some() {}
+
+        match deadline::wait_reply(
+            &self.method,
+            &mut self.reply_rx,
+            self.cancel_tx,
+            self.deadline,
+            &self.options,
+        )
+        .await?
+        {
+            Some(reply) => Ok(reply),
+            None => bail!("Reply is empty"),
+        }
+    }
+
+    /// 取消调用，对端的取消令牌被触发
+    pub fn cancel(self) {
+        let _ = self.cancel_tx.send(());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use jsonrpc_lite::JsonRpc;
+    use serde_json::json;
+
+    use super::Progress;
+    use crate::client::Client;
+    use crate::server::Server;
+    use crate::transport::memory;
+
+    #[tokio::test]
+    async fn test_progress_stream_and_cancel() {
+        let (listener, connector) = memory::channel();
+
+        let cancelled = Arc::new(AtomicBool::new(false));
+
+        let mut server = Server::with_listener("memory", listener);
+        {
+            let cancelled = cancelled.clone();
+            server.on_connection(move |socket, _| {
+                let cancelled = cancelled.clone();
+                socket.lock().on_request(move |_, id, req| {
+                    let progress = Progress::current();
+                    if req.get_method() == Some("upload") {
+                        for percent in [25, 50, 75, 100] {
+                            progress.send(json!({ "percent": percent })).unwrap();
+                        }
+                        return JsonRpc::success(id, &json!("done"));
+                    }
+
+                    // 持续发送直到调用方取消
+                    while progress.send(json!({})).is_ok() {
+                        std::thread::sleep(Duration::from_millis(10));
+                    }
+                    cancelled.store(true, Ordering::SeqCst);
+                    JsonRpc::success(id, &json!("cancelled"))
+                });
+                0
+            });
+        }
+        tokio::spawn(async move { server.listen().await });
+
+        let mut client = Client::with_connector(connector);
+        client.connect().await.unwrap();
+
+        let mut stream = client.call_stream("upload", json!({})).await.unwrap();
+        let mut received = Vec::new();
+        while let Some(progress) = stream.next().await {
+            received.push(progress["percent"].clone());
+        }
+        assert_eq!(received, vec![json!(25), json!(50), json!(75), json!(100)]);
+        let reply = stream.finish().await.unwrap();
+        assert_eq!(reply.get_result(), Some(&json!("done")));
+
+        // 普通调用中进度被忽略
+        let reply = client.call_with_params("upload", json!({})).await.unwrap();
+        assert_eq!(reply.get_result(), Some(&json!("done")));
+
+        let mut stream = client.call_stream("endless", json!({})).await.unwrap();
+        assert!(stream.next().await.is_some());
+        stream.cancel();
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(cancelled.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,90 @@
+//
+// Copyright (C) Lenovo ThinkBook Gen4 Project.
+//
+// This program is protected under international and China copyright laws as
+// an unpublished work. This program is confidential and proprietary to the
+// copyright owners. Reproduction or disclosure, in whole or in part, or the
+// production of derivative works therefrom without the express permission of
+// the copyright owners is prohibited.
+//
+// All rights reserved.
+//
+
+//! 进程内传输，基于 `tokio::io::duplex`，主要用于测试
+
+use std::io;
+
+use async_trait::async_trait;
+use tokio::io::DuplexStream;
+use tokio::sync::mpsc;
+
+use super::{BoxStream, Connector, Listener, PeerCredentials};
+
+/// duplex 缓冲区大小
+const DEFAULT_BUFFER_SIZE: usize = 64 * 1024;
+
+/// 创建一对互相连通的内存监听器与连接器
+pub fn channel() -> (MemoryListener, MemoryConnector) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    (
+        MemoryListener { rx },
+        MemoryConnector {
+            tx,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            credentials: PeerCredentials {
+                pid: Some(std::process::id()),
+                uid: None,
+                gid: None,
+            },
+        },
+    )
+}
+
+pub struct MemoryListener {
+    rx: mpsc::UnboundedReceiver<(DuplexStream, PeerCredentials)>,
+}
+
+#[async_trait]
+impl Listener for MemoryListener {
+    async fn accept(&mut self) -> io::Result<(BoxStream, PeerCredentials)> {
+        match self.rx.recv().await {
+            Some((stream, credentials)) => Ok((Box::new(stream), credentials)),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "All memory connectors were dropped",
+            )),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct MemoryConnector {
+    tx: mpsc::UnboundedSender<(DuplexStream, PeerCredentials)>,
+    buffer_size: usize,
+    /// 服务端看到的对端凭据，默认为当前进程
+    credentials: PeerCredentials,
+}
+
+impl MemoryConnector {
+    /// 使用指定的对端凭据建立连接，用于模拟其它进程或用户
+    pub fn with_credentials(mut self, credentials: PeerCredentials) -> Self {
+        self.credentials = credentials;
+        self
+    }
+}
+
+#[async_trait]
+impl Connector for MemoryConnector {
+    async fn connect(&self) -> io::Result<BoxStream> {
+        let (local, remote) = tokio::io::duplex(self.buffer_size);
+        self.tx
+            .send((remote, self.credentials.clone()))
+            .map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::ConnectionRefused,
+                    "Memory listener was dropped",
+                )
+            })?;
+        Ok(Box::new(local))
+    }
+}
@@ -0,0 +1,135 @@
+//
+// Copyright (C) Lenovo ThinkBook Gen4 Project.
+//
+// This program is protected under international and China copyright laws as
+// an unpublished work. This program is confidential and proprietary to the
+// copyright owners. Reproduction or disclosure, in whole or in part, or the
+// production of derivative works therefrom without the express permission of
+// the copyright owners is prohibited.
+//
+// All rights reserved.
+//
+
+//! 底层传输抽象
+//!
+//! `Server` / `Client` 只依赖 `Listener` / `Connector` 产生的字节流，
+//! remoc 与 JSON-RPC 会话逻辑与具体传输方式无关。
+
+use std::io;
+
+use async_trait::async_trait;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+pub mod memory;
+#[cfg(windows)]
+pub mod named_pipe;
+#[cfg(unix)]
+pub mod unix;
+
+/// 双向字节流
+pub trait Stream: AsyncRead + AsyncWrite + Send + Sync + Unpin + 'static {}
+
+impl<T> Stream for T where T: AsyncRead + AsyncWrite + Send + Sync + Unpin + 'static {}
+
+/// 类型擦除后的字节流
+pub type BoxStream = Box<dyn Stream>;
+
+/// 对端进程凭据，由操作系统凭据接口获取，平台不支持的字段为 None
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PeerCredentials {
+    pub pid: Option<u32>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+}
+
+/// 服务端监听器，每次 accept 返回一个已建立的连接及对端凭据
+///
+/// accept 必须是取消安全的：服务器在 `select!` 中等待 accept，
+/// 被取消时不能丢失正在等待或已经建立的连接
+#[async_trait]
+pub trait Listener: Send + 'static {
+    async fn accept(&mut self) -> io::Result<(BoxStream, PeerCredentials)>;
+}
+
+/// 客户端连接器，可多次调用以建立新连接
+#[async_trait]
+pub trait Connector: Send + Sync + 'static {
+    async fn connect(&self) -> io::Result<BoxStream>;
+}
+
+/// 根据端点名称创建当前平台的默认监听器
+///
+/// Windows 下为命名管道，Unix 下为 Unix domain socket 路径
+pub fn default_listener(name: &str) -> Box<dyn Listener> {
+    #[cfg(windows)]
+    {
+        Box::new(named_pipe::NamedPipeListener::new(name))
+    }
+    #[cfg(unix)]
+    {
+        Box::new(unix::UnixSocketListener::new(name))
+    }
+}
+
+/// 根据端点名称创建当前平台的默认连接器
+pub fn default_connector(name: &str) -> Box<dyn Connector> {
+    #[cfg(windows)]
+    {
+        Box::new(named_pipe::NamedPipeConnector::new(name))
+    }
+    #[cfg(unix)]
+    {
+        Box::new(unix::UnixSocketConnector::new(name))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use jsonrpc_lite::JsonRpc;
+    use serde_json::json;
+
+    use crate::client::Client;
+    use crate::server::Server;
+
+    fn echo_server(server: &mut Server) {
+        let _ = server.on_connection(|socket, _| {
+            socket.lock().on_request(|_socket, id, req| {
+                JsonRpc::success(id, &json!({ "method": req.get_method().unwrap() }))
+            });
+            0
+        });
+    }
+
+    #[tokio::test]
+    async fn test_memory_round_trip() {
+        let (listener, connector) = super::memory::channel();
+
+        let mut server = Server::with_listener("memory", listener);
+        echo_server(&mut server);
+        tokio::spawn(async move { server.listen().await });
+
+        let mut client = Client::with_connector(connector);
+        client.connect().await.unwrap();
+
+        let reply = client.call_with_params("ping", json!({})).await.unwrap();
+        assert_eq!(reply.get_result(), Some(&json!({ "method": "ping" })));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_unix_socket_round_trip() {
+        let path = std::env::temp_dir().join(format!("eink-pipe-io-{}.sock", uuid::Uuid::new_v4()));
+        let path = path.to_str().unwrap().to_owned();
+
+        let listener = super::unix::UnixSocketListener::bind(&path).unwrap();
+        let mut server = Server::with_listener(&path, listener);
+        echo_server(&mut server);
+        tokio::spawn(async move { server.listen().await });
+
+        let mut client = crate::client::connect(&path).await.unwrap();
+        let reply = client.call_with_params("ping", json!({})).await.unwrap();
+        assert_eq!(reply.get_result(), Some(&json!({ "method": "ping" })));
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,474 @@
+//
+// Copyright (C) Lenovo ThinkBook Gen4 Project.
+//
+// This program is protected under international and China copyright laws as
+// an unpublished work. This program is confidential and proprietary to the
+// copyright owners. Reproduction or disclosure, in whole or in part, or the
+// production of derivative works therefrom without the express permission of
+// the copyright owners is prohibited.
+//
+// All rights reserved.
+//
+
+//! Windows 命名管道传输
+
+use std::os::windows::io::AsRawHandle;
+use std::time::Duration;
+use std::{io, marker};
+
+use async_trait::async_trait;
+use tokio::net::windows::named_pipe::{ClientOptions, NamedPipeServer, ServerOptions};
+use tokio::time;
+use windows::core::PWSTR;
+use windows::Win32::Foundation::{BOOL, ERROR_PIPE_BUSY, ERROR_SUCCESS, HANDLE, PSID};
+use windows::Win32::Security::Authorization::{
+    SetEntriesInAclW, ACCESS_MODE, EXPLICIT_ACCESS_W, SET_ACCESS, TRUSTEE_IS_SID,
+    TRUSTEE_IS_WELL_KNOWN_GROUP, TRUSTEE_TYPE,
+};
+use windows::Win32::Security::{
+    AllocateAndInitializeSid, FreeSid, InitializeSecurityDescriptor, SetSecurityDescriptorDacl,
+    ACE_FLAGS, ACL, PSECURITY_DESCRIPTOR, SECURITY_ATTRIBUTES,
+};
+use windows::Win32::Storage::FileSystem::FILE_WRITE_DATA;
+use windows::Win32::System::Memory::{LocalAlloc, LocalFree, LPTR};
+use windows::Win32::System::Pipes::GetNamedPipeClientProcessId;
+use windows::Win32::System::SystemServices::{
+    GENERIC_READ, GENERIC_WRITE, SECURITY_DESCRIPTOR_REVISION,
+};
+
+use super::{BoxStream, Connector, Listener, PeerCredentials};
+
+pub struct NamedPipeListener {
+    pipe_name: String,
+    security_attributes: SecurityAttributes,
+    /// 等待下一个客户端连接的管道实例
+    next: Option<NamedPipeServer>,
+    /// 是否已经创建过第一个管道实例
+    created: bool,
+}
+
+impl NamedPipeListener {
+    pub fn new(pipe_name: &str) -> Self {
+        Self::with_security_attributes(
+            pipe_name,
+            SecurityAttributes::allow_everyone_create().unwrap(),
+        )
+    }
+
+    pub fn with_security_attributes(
+        pipe_name: &str,
+        security_attributes: SecurityAttributes,
+    ) -> Self {
+        Self {
+            pipe_name: pipe_name.to_string(),
+            security_attributes,
+            next: None,
+            created: false,
+        }
+    }
+
+    fn create_instance(&mut self) -> io::Result<NamedPipeServer> {
+        if self.created {
+            return ServerOptions::new().create(&self.pipe_name);
+        }
+
+        // The first server needs to be constructed early so that clients can
+        // be correctly connected. Otherwise calling .wait will cause the client to
+        // error.
+        //
+        // Here we also make use of `first_pipe_instance`, which will ensure that
+        // there are no other servers up and running already.
+        let server = unsafe {
+            ServerOptions::new()
+                .first_pipe_instance(true)
+                .reject_remote_clients(true)
+                .create_with_security_attributes_raw(
+                    &self.pipe_name,
+                    std::mem::transmute(self.security_attributes.as_ptr()),
+                )?
+        };
+        self.created = true;
+        Ok(server)
+    }
+}
+
+#[async_trait]
+impl Listener for NamedPipeListener {
+    async fn accept(&mut self) -> io::Result<(BoxStream, PeerCredentials)> {
+        if self.next.is_none() {
+            self.next = Some(self.create_instance()?);
+        }
+
+        // Wait for a client to connect. The instance stays in `self.next` while
+        // waiting, so a cancelled accept neither closes the pipe nor drops a
+        // client which connected meanwhile, the next accept picks it up.
+        self.next.as_ref().unwrap().connect().await?;
+        let server = self.next.take().unwrap();
+
+        // Construct the next server to be connected before sending the one
+        // we already have of onto a task. This ensures that the server
+        // isn't closed (after it's done in the task) before a new one is
+        // available. Otherwise the client might error with
+        // `io::ErrorKind::NotFound`.
+        self.next = Some(self.create_instance()?);
+
+        let credentials = PeerCredentials {
+            pid: client_process_id(&server),
+            uid: None,
+            gid: None,
+        };
+        Ok((Box::new(server), credentials))
+    }
+}
+
+/// 获取已连接客户端的进程 id
+fn client_process_id(server: &NamedPipeServer) -> Option<u32> {
+    let mut pid = 0u32;
+    let ok =
+        unsafe { GetNamedPipeClientProcessId(HANDLE(server.as_raw_handle() as isize), &mut pid) };
+    if ok.as_bool() {
+        Some(pid)
+    } else {
+        log::warn!(
+            "PipeIo: cannot get client process id: {}",
+            io::Error::last_os_error()
+        );
+        None
+    }
+}
+
+#[derive(Clone)]
+pub struct NamedPipeConnector {
+    pipe_name: String,
+}
+
+impl NamedPipeConnector {
+    pub fn new(pipe_name: &str) -> Self {
+        Self {
+            pipe_name: pipe_name.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl Connector for NamedPipeConnector {
+    async fn connect(&self) -> io::Result<BoxStream> {
+        let client = loop {
+            match ClientOptions::new().open(&self.pipe_name) {
+                Ok(client) => break client,
+                Err(e) if e.raw_os_error() == Some(ERROR_PIPE_BUSY.0 as i32) => (),
+                Err(e) => return Err(e),
+            }
+
+            time::sleep(Duration::from_millis(50)).await;
+        };
+
+        Ok(Box::new(client))
+    }
+}
+
+/// Security attributes.
+pub struct SecurityAttributes {
+    attributes: Option<InnerAttributes>,
+}
+
+pub const DEFAULT_SECURITY_ATTRIBUTES: SecurityAttributes = SecurityAttributes {
+    attributes: Some(InnerAttributes {
+        descriptor: SecurityDescriptor {
+            descriptor_ptr: PSECURITY_DESCRIPTOR(std::ptr::null_mut()),
+        },
+        acl: Acl {
+            acl_ptr: std::ptr::null_mut(),
+        },
+        attrs: SECURITY_ATTRIBUTES {
+            nLength: std::mem::size_of::<SECURITY_ATTRIBUTES>() as u32,
+            lpSecurityDescriptor: std::ptr::null_mut(),
+            bInheritHandle: BOOL(0),
+        },
+    }),
+};
+
+impl SecurityAttributes {
+    /// New default security attributes.
+    pub fn empty() -> SecurityAttributes {
+        DEFAULT_SECURITY_ATTRIBUTES
+    }
+
+    /// New default security attributes that allow everyone to connect.
+    pub fn allow_everyone_connect(&self) -> std::io::Result<SecurityAttributes> {
+        let attributes = Some(InnerAttributes::allow_everyone(
+            GENERIC_READ | FILE_WRITE_DATA.0,
+        )?);
+        Ok(SecurityAttributes { attributes })
+    }
+
+    /// Set a custom permission on the socket
+    pub fn set_mode(self, _mode: u32) -> std::io::Result<Self> {
+        // for now, does nothing.
+        Ok(self)
+    }
+
+    /// New default security attributes that allow everyone to create.
+    pub fn allow_everyone_create() -> std::io::Result<SecurityAttributes> {
+        let attributes = Some(InnerAttributes::allow_everyone(
+            GENERIC_READ | GENERIC_WRITE,
+        )?);
+        Ok(SecurityAttributes { attributes })
+    }
+
+    /// Return raw handle of security attributes.
+    pub(crate) unsafe fn as_ptr(&mut self) -> *mut SECURITY_ATTRIBUTES {
+        match self.attributes.as_mut() {
+            Some(attributes) => attributes.as_ptr(),
+            None => std::ptr::null_mut(),
+        }
+    }
+}
+
+unsafe impl Send for SecurityAttributes {}
+
+struct Sid {
+    sid_ptr: PSID,
+}
+
+impl Sid {
+    fn everyone_sid() -> std::io::Result<Sid> {
+        pub const SECURITY_WORLD_SID_AUTHORITY: [u8; 6] = [0, 0, 0, 0, 0, 1];
+        pub const SECURITY_WORLD_RID: u32 = 0x00000000;
+
+        let mut sid_ptr = PSID(std::ptr::null_mut());
+        let result = unsafe {
+            #[allow(const_item_mutation)]
+            AllocateAndInitializeSid(
+                SECURITY_WORLD_SID_AUTHORITY.as_mut_ptr() as *mut _,
+                1,
+                SECURITY_WORLD_RID,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                &mut sid_ptr as *mut PSID,
+            )
+        };
+
+        if !result.as_bool() {
+            Err(std::io::Error::last_os_error())
+        } else {
+            Ok(Sid { sid_ptr })
+        }
+    }
+
+    // Unsafe - the returned pointer is only valid for the lifetime of self.
+    unsafe fn as_ptr(&self) -> PSID {
+        self.sid_ptr
+    }
+}
+
+impl Drop for Sid {
+    fn drop(&mut self) {
+        if !self.sid_ptr.is_invalid() {
+            unsafe {
+                FreeSid(self.sid_ptr);
+            }
+        }
+    }
+}
+
+struct AceWithSid<'a> {
+    explicit_access: EXPLICIT_ACCESS_W,
+    _marker: marker::PhantomData<&'a Sid>,
+}
+
+impl<'a> AceWithSid<'a> {
+    fn new(sid: &'a Sid, trustee_type: i32) -> AceWithSid<'a> {
+        let mut explicit_access = unsafe { std::mem::zeroed::<EXPLICIT_ACCESS_W>() };
+        explicit_access.Trustee.TrusteeForm = TRUSTEE_IS_SID;
+        explicit_access.Trustee.TrusteeType = TRUSTEE_TYPE(trustee_type as i32);
+        explicit_access.Trustee.ptstrName = unsafe { PWSTR(std::mem::transmute(sid.as_ptr())) };
+
+        AceWithSid {
+            explicit_access,
+            _marker: marker::PhantomData,
+        }
+    }
+
+    fn set_access_mode(&mut self, access_mode: i32) -> &mut Self {
+        self.explicit_access.grfAccessMode = ACCESS_MODE(access_mode);
+        self
+    }
+
+    fn set_access_permissions(&mut self, access_permissions: u32) -> &mut Self {
+        self.explicit_access.grfAccessPermissions = access_permissions;
+        self
+    }
+
+    fn allow_inheritance(&mut self, inheritance_flags: u32) -> &mut Self {
+        self.explicit_access.grfInheritance = ACE_FLAGS(inheritance_flags);
+        self
+    }
+}
+
+struct Acl {
+    acl_ptr: *mut ACL,
+}
+
+impl Acl {
+    fn empty() -> std::io::Result<Acl> {
+        Self::new(&mut [])
+    }
+
+    fn new(entries: &mut [AceWithSid<'_>]) -> std::io::Result<Acl> {
+        let mut acl_ptr = std::ptr::null_mut();
+        let result = unsafe {
+            SetEntriesInAclW(
+                Some(unsafe {
+                    &*(entries as *mut _ as *mut [EXPLICIT_ACCESS_W]) as &[EXPLICIT_ACCESS_W]
+                }),
+                None,
+                &mut acl_ptr,
+            )
+        };
+
+        if result != ERROR_SUCCESS.0 {
+            return Err(std::io::Error::from_raw_os_error(result as i32));
+        }
+
+        Ok(Acl { acl_ptr })
+    }
+
+    unsafe fn as_ptr(&self) -> *mut ACL {
+        self.acl_ptr
+    }
+}
+
+impl Drop for Acl {
+    fn drop(&mut self) {
+        if !self.acl_ptr.is_null() {
+            unsafe { LocalFree(std::mem::transmute(self.acl_ptr)) };
+        }
+    }
+}
+
+struct SecurityDescriptor {
+    descriptor_ptr: PSECURITY_DESCRIPTOR,
+}
+
+#[cfg(target_pointer_width = "64")]
+pub const SECURITY_DESCRIPTOR_MIN_LENGTH: usize = 40;
+#[cfg(target_pointer_width = "32")]
+pub const SECURITY_DESCRIPTOR_MIN_LENGTH: usize = 20;
+
+impl SecurityDescriptor {
+    fn new() -> std::io::Result<Self> {
+        let descriptor_ptr = unsafe { LocalAlloc(LPTR, SECURITY_DESCRIPTOR_MIN_LENGTH) };
+        let descriptor_ptr = PSECURITY_DESCRIPTOR(unsafe { std::mem::transmute(descriptor_ptr) });
+        if descriptor_ptr.is_invalid() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "Failed to allocate security descriptor",
+            ));
+        }
+
+        if unsafe {
+            !InitializeSecurityDescriptor(descriptor_ptr, SECURITY_DESCRIPTOR_REVISION).as_bool()
+        } {
+            return Err(std::io::Error::last_os_error());
+        };
+
+        Ok(SecurityDescriptor { descriptor_ptr })
+    }
+
+    fn set_dacl(&mut self, acl: &Acl) -> std::io::Result<()> {
+        if unsafe {
+            !SetSecurityDescriptorDacl(
+                self.descriptor_ptr, //
+                BOOL(1),
+                Some(acl.as_ptr()),
+                BOOL(0),
+            )
+            .as_bool()
+        } {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    unsafe fn as_ptr(&self) -> PSECURITY_DESCRIPTOR {
+        self.descriptor_ptr
+    }
+}
+
+impl Drop for SecurityDescriptor {
+    fn drop(&mut self) {
+        if !self.descriptor_ptr.is_invalid() {
+            unsafe { LocalFree(std::mem::transmute(self.descriptor_ptr)) };
+            self.descriptor_ptr = PSECURITY_DESCRIPTOR(std::ptr::null_mut());
+        }
+    }
+}
+
+struct InnerAttributes {
+    descriptor: SecurityDescriptor,
+    acl: Acl,
+    attrs: SECURITY_ATTRIBUTES,
+}
+
+impl InnerAttributes {
+    fn empty() -> std::io::Result<InnerAttributes> {
+        let descriptor = SecurityDescriptor::new()?;
+        let mut attrs = unsafe { std::mem::zeroed::<SECURITY_ATTRIBUTES>() };
+        attrs.nLength = std::mem::size_of::<SECURITY_ATTRIBUTES>() as u32;
+        attrs.lpSecurityDescriptor = unsafe { std::mem::transmute(descriptor.as_ptr()) };
+        attrs.bInheritHandle = BOOL(1); // false as i32;
+
+        let acl = Acl::empty().expect("this should never fail");
+
+        Ok(InnerAttributes {
+            acl,
+            descriptor,
+            attrs,
+        })
+    }
+
+    fn allow_everyone(permissions: u32) -> std::io::Result<InnerAttributes> {
+        let mut attributes = Self::empty()?;
+        let sid = Sid::everyone_sid()?;
+
+        let mut everyone_ace = AceWithSid::new(&sid, TRUSTEE_IS_WELL_KNOWN_GROUP.0);
+        everyone_ace
+            .set_access_mode(SET_ACCESS.0)
+            .set_access_permissions(permissions)
+            .allow_inheritance(false as u32);
+
+        let mut entries = vec![everyone_ace];
+        attributes.acl = Acl::new(&mut entries)?;
+        attributes.descriptor.set_dacl(&attributes.acl)?;
+
+        Ok(attributes)
+    }
+
+    unsafe fn as_ptr(&mut self) -> *mut SECURITY_ATTRIBUTES {
+        &mut self.attrs as *mut _
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SecurityAttributes;
+
+    #[test]
+    fn test_allow_everyone_everything() {
+        SecurityAttributes::allow_everyone_create()
+            .expect("failed to create security attributes that allow everyone to create a pipe");
+    }
+
+    #[test]
+    fn test_allow_eveyone_read_write() {
+        SecurityAttributes::empty()
+            .allow_everyone_connect()
+            .expect("failed to create security attributes that allow everyone to read and write to/from a pipe");
+    }
+}
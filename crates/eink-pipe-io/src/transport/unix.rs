@@ -0,0 +1,104 @@
+//
+// Copyright (C) Lenovo ThinkBook Gen4 Project.
+//
+// This program is protected under international and China copyright laws as
+// an unpublished work. This program is confidential and proprietary to the
+// copyright owners. Reproduction or disclosure, in whole or in part, or the
+// production of derivative works therefrom without the express permission of
+// the copyright owners is prohibited.
+//
+// All rights reserved.
+//
+
+//! Unix domain socket 传输
+
+use std::io;
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use tokio::net::{UnixListener, UnixStream};
+
+use super::{BoxStream, Connector, Listener, PeerCredentials};
+
+pub struct UnixSocketListener {
+    path: PathBuf,
+    inner: Option<UnixListener>,
+}
+
+impl UnixSocketListener {
+    /// 创建监听器，在第一次 accept 时才绑定地址
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: PathBuf::from(path),
+            inner: None,
+        }
+    }
+
+    /// 立即绑定地址，需要在 tokio 运行时中调用
+    pub fn bind(path: &str) -> io::Result<Self> {
+        let mut listener = Self::new(path);
+        listener.inner = Some(listener.bind_inner()?);
+        Ok(listener)
+    }
+
+    fn bind_inner(&self) -> io::Result<UnixListener> {
+        match UnixListener::bind(&self.path) {
+            Ok(listener) => Ok(listener),
+            Err(err) if err.kind() == io::ErrorKind::AddrInUse => {
+                // 地址被占用时，检查是否为上次退出残留的 socket 文件
+                if std::os::unix::net::UnixStream::connect(&self.path).is_ok() {
+                    return Err(err);
+                }
+                std::fs::remove_file(&self.path)?;
+                UnixListener::bind(&self.path)
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+#[async_trait]
+impl Listener for UnixSocketListener {
+    async fn accept(&mut self) -> io::Result<(BoxStream, PeerCredentials)> {
+        if self.inner.is_none() {
+            self.inner = Some(self.bind_inner()?);
+        }
+
+        let (stream, _addr) = self.inner.as_ref().unwrap().accept().await?;
+
+        // SO_PEERCRED，获取失败时不拒绝连接
+        let credentials = match stream.peer_cred() {
+            Ok(cred) => PeerCredentials {
+                pid: cred.pid().map(|pid| pid as u32),
+                uid: Some(cred.uid()),
+                gid: Some(cred.gid()),
+            },
+            Err(err) => {
+                log::warn!("PipeIo: cannot get peer credentials: {err}");
+                PeerCredentials::default()
+            }
+        };
+        Ok((Box::new(stream), credentials))
+    }
+}
+
+#[derive(Clone)]
+pub struct UnixSocketConnector {
+    path: PathBuf,
+}
+
+impl UnixSocketConnector {
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: PathBuf::from(path),
+        }
+    }
+}
+
+#[async_trait]
+impl Connector for UnixSocketConnector {
+    async fn connect(&self) -> io::Result<BoxStream> {
+        let stream = UnixStream::connect(&self.path).await?;
+        Ok(Box::new(stream))
+    }
+}
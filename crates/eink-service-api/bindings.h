@@ -16,8 +16,16 @@ using Box = T*;
 #include <ostream>
 #include <new>
 
+/// 关机壁纸设置进度回调，参数为进度百分比与调用方数据
+using ShutdownCoverProgressCallback = void(*)(uint32_t percent, void *user_data);
+
 extern "C" {
 
+/// 获得当前线程最后一次调用的错误码
+///
+/// 0 表示成功，-1 表示无法连接服务，其它值为服务返回的错误码（参见 `ServiceError`）
+int64_t eink_get_last_error();
+
 /// 设置窗口为置顶
 uint32_t disable_win_key();
 
@@ -40,6 +48,12 @@ uint32_t eink_show_shutdown_cover();
 /// 设置 Eink 关机壁纸
 uint32_t eink_set_shutdown_cover(const uint16_t *path, uint32_t disp_type);
 
+/// 设置 Eink 关机壁纸，上传期间通过回调报告进度
+uint32_t eink_set_shutdown_cover_with_progress(const uint16_t *path,
+                                               uint32_t disp_type,
+                                               ShutdownCoverProgressCallback on_progress,
+                                               void *user_data);
+
 /// 设置窗口为置顶
 uint32_t set_window_topmost(uint32_t hwnd);
 
@@ -13,9 +13,10 @@
 use std::ffi::c_void;
 
 use eink_pipe_io::blocking::BlockingClient;
+use eink_pipe_io::retry::RetryPolicy;
+use eink_service_proto::{keyboard, Empty};
 use log::{error, info};
 use parking_lot::Mutex;
-use serde_json::json;
 use windows::Win32::{
     Foundation::{GetLastError, HINSTANCE},
     System::SystemServices::DLL_PROCESS_ATTACH,
@@ -28,16 +29,28 @@ const KEYBOARD_PIPE_NAME: &str = r"\\.\pipe\lenovo\eink-service\keyboard";
 //
 pub static KEYBOARD_CLIENT: Mutex<Option<BlockingClient>> = Mutex::new(None);
 
+/// 链路断开后可以安全重试的方法
+fn keyboard_retry_policy() -> RetryPolicy {
+    RetryPolicy::new(2)
+        .idempotent("disable_win_key")
+        .idempotent("enable_win_key")
+}
+
 /// 检查链接状态
+///
+/// 客户端在链路断开后会自动重连，这里只负责首次建立连接
 fn ensure_keyboard_client() {
     let mut guard = KEYBOARD_CLIENT.lock();
+    crate::clear_last_error();
 
     if guard.is_none() {
         let client = eink_pipe_io::blocking::connect(KEYBOARD_PIPE_NAME);
 
-        if let Ok(client) = client {
+        if let Ok(mut client) = client {
+            client.set_retry_policy(keyboard_retry_policy());
             guard.replace(client);
         } else {
+            crate::set_connect_error();
             error!(
                 "Cannot connect to keyboard service: last error: {:?}",
                 unsafe { GetLastError() }
@@ -52,10 +65,14 @@ pub extern "C" fn disable_win_key() -> u32 {
     ensure_keyboard_client();
     let mut guard = KEYBOARD_CLIENT.lock();
     if let Some(client) = guard.as_mut() {
-        let reply = client
-            .call_with_params("disable_win_key", json!({}))
-            .expect("Cannot invoke remote method to keyboard service");
-        info!("disable_win_key: result: {:?}", reply.get_result());
+        let reply = match keyboard::BlockingClient::new(client).disable_win_key(Empty {}) {
+            Ok(reply) => reply,
+            Err(err) => {
+                crate::report_call_error("keyboard", &err);
+                return 0;
+            }
+        };
+        info!("disable_win_key: result: {:?}", reply);
     }
     0
 }
@@ -66,10 +83,14 @@ pub extern "C" fn enable_win_key() -> u32 {
     ensure_keyboard_client();
     let mut guard = KEYBOARD_CLIENT.lock();
     if let Some(client) = guard.as_mut() {
-        let reply = client
-            .call_with_params("enable_win_key", json!({}))
-            .expect("Cannot invoke remote method to keyboard service");
-        info!("enable_win_key: result: {:?}", reply.get_result());
+        let reply = match keyboard::BlockingClient::new(client).enable_win_key(Empty {}) {
+            Ok(reply) => reply,
+            Err(err) => {
+                crate::report_call_error("keyboard", &err);
+                return 0;
+            }
+        };
+        info!("enable_win_key: result: {:?}", reply);
     }
     0
 }
@@ -10,9 +10,12 @@
 // All rights reserved.
 //
 
+use std::cell::Cell;
 use std::ffi::c_void;
 
-use log::info;
+use eink_pipe_io::service::RemoteError;
+use eink_service_proto::ServiceError;
+use log::{error, info};
 use windows::Win32::Foundation::{GetLastError, BOOL, HINSTANCE};
 use windows::Win32::System::SystemServices::DLL_PROCESS_ATTACH;
 
@@ -21,6 +24,47 @@ mod tcon_api;
 mod topmost_api;
 mod wmi_api;
 
+/// 无法连接服务或链路错误时的错误码
+const IPC_ERROR: i64 = -1;
+
+thread_local! {
+    /// 当前线程最后一次调用的错误码，0 表示成功
+    static LAST_ERROR: Cell<i64> = Cell::new(0);
+}
+
+/// 获得当前线程最后一次调用的错误码
+///
+/// 0 表示成功，-1 表示无法连接服务，其它值为服务返回的错误码（参见 `ServiceError`）
+#[no_mangle]
+pub extern "C" fn eink_get_last_error() -> i64 {
+    LAST_ERROR.with(|last| last.get())
+}
+
+/// 开始新的调用前清除错误码
+fn clear_last_error() {
+    LAST_ERROR.with(|last| last.set(0));
+}
+
+/// 无法连接服务
+fn set_connect_error() {
+    LAST_ERROR.with(|last| last.set(IPC_ERROR));
+}
+
+/// 记录调用错误日志并保存错误码
+fn report_call_error(service: &str, err: &anyhow::Error) {
+    let code = if let Some(err) = ServiceError::from_call_error(err) {
+        error!("{service} service returns error {}: {err}", err.code());
+        err.code()
+    } else if let Some(remote) = err.downcast_ref::<RemoteError>() {
+        error!("{service} service returns error: {remote}");
+        remote.0.code
+    } else {
+        error!("Cannot invoke remote method to {service} service: err: {err:?}");
+        IPC_ERROR
+    };
+    LAST_ERROR.with(|last| last.set(code));
+}
+
 #[no_mangle]
 extern "stdcall" fn DllMain(
     _hInstDll: HINSTANCE,
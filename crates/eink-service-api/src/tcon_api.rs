@@ -13,9 +13,11 @@
 use std::ffi::c_void;
 
 use eink_pipe_io::blocking::BlockingClient;
+use eink_pipe_io::retry::RetryPolicy;
+use eink_pipe_io::service::{decode_reply, encode_params};
+use eink_service_proto::{tcon, Ack, Empty, SetMipiModeParams, SetShutdownCoverParams};
 use log::{error, info};
 use parking_lot::Mutex;
-use serde_json::json;
 use windows::Win32::{
     Foundation::{GetLastError, HINSTANCE},
     System::SystemServices::DLL_PROCESS_ATTACH,
@@ -28,16 +30,35 @@ const TCON_PIPE_NAME: &str = r"\\.\pipe\lenovo\eink-service\tcon";
 //
 pub static TCON_CLIENT: Mutex<Option<BlockingClient>> = Mutex::new(None);
 
+/// 链路断开后可以安全重试的方法
+///
+/// 设置类方法写入的是目标状态，重复执行结果相同。`refresh` 与 `show_shutdown_cover`
+/// 会重新绘制屏幕，重放只会多刷新一次当前画面，不会改变 TCON 的状态；
+/// `software_reset_api`、`start_launcher` 等会重复产生副作用的方法不能重试
+fn tcon_retry_policy() -> RetryPolicy {
+    RetryPolicy::new(2)
+        .idempotent(tcon::method::refresh)
+        .idempotent(tcon::method::set_mipi_mode)
+        .idempotent(tcon::method::get_mipi_mode)
+        .idempotent(tcon::method::show_shutdown_cover)
+        .idempotent(tcon::method::set_shutdown_cover)
+}
+
 /// 检查链接状态
+///
+/// 客户端在链路断开后会自动重连，这里只负责首次建立连接
 fn ensure_tcon_client() {
     let mut guard = TCON_CLIENT.lock();
+    crate::clear_last_error();
 
     if guard.is_none() {
         let client = eink_pipe_io::blocking::connect(TCON_PIPE_NAME);
 
-        if let Ok(client) = client {
+        if let Ok(mut client) = client {
+            client.set_retry_policy(tcon_retry_policy());
             guard.replace(client);
         } else {
+            crate::set_connect_error();
             error!("Cannot connect to tcon service: last error: {:?}", unsafe {
                 GetLastError()
             });
@@ -51,10 +72,14 @@ pub extern "C" fn eink_refresh() -> u32 {
     ensure_tcon_client();
     let mut guard = TCON_CLIENT.lock();
     if let Some(client) = guard.as_mut() {
-        let reply = client
-            .call_with_params("refresh", json!({}))
-            .expect("Cannot invoke remote method to tcon service");
-        info!("eink_refresh: result: {:?}", reply.get_result());
+        let reply = match tcon::BlockingClient::new(client).refresh(Empty {}) {
+            Ok(reply) => reply,
+            Err(err) => {
+                crate::report_call_error("tcon", &err);
+                return 0;
+            }
+        };
+        info!("eink_refresh: result: {:?}", reply);
     }
     0
 }
@@ -65,10 +90,15 @@ pub extern "C" fn eink_set_mipi_mode(mode: u32) -> u32 {
     ensure_tcon_client();
     let mut guard = TCON_CLIENT.lock();
     if let Some(client) = guard.as_mut() {
-        let reply = client
-            .call_with_params("set_mipi_mode", json!({ "mode": mode }))
-            .expect("Cannot invoke remote method to tcon service");
-        info!("eink_set_mipi_mode: result: {:?}", reply.get_result());
+        let reply =
+            match tcon::BlockingClient::new(client).set_mipi_mode(SetMipiModeParams { mode }) {
+                Ok(reply) => reply,
+                Err(err) => {
+                    crate::report_call_error("tcon", &err);
+                    return 0;
+                }
+            };
+        info!("eink_set_mipi_mode: result: {:?}", reply);
     }
     0
 }
@@ -80,16 +110,15 @@ pub extern "C" fn eink_get_mipi_mode() -> i32 {
     ensure_tcon_client();
     let mut guard = TCON_CLIENT.lock();
     if let Some(client) = guard.as_mut() {
-        let reply = client
-            .call_with_params("get_mipi_mode", json!({}))
-            .expect("Cannot invoke remote method to tcon service");
-        let result = reply.get_result();
-        info!("get_mipi_mode: result: {:?}", result);
-        if let Some(result) = result {
-            if result.is_i64() {
-                return result.as_i64().unwrap_or(-1) as i32;
+        let reply = match tcon::BlockingClient::new(client).get_mipi_mode(Empty {}) {
+            Ok(reply) => reply,
+            Err(err) => {
+                crate::report_call_error("tcon", &err);
+                return -1;
             }
-        }
+        };
+        info!("get_mipi_mode: result: {:?}", reply);
+        return reply as i32;
     }
     -1
 }
@@ -100,10 +129,14 @@ pub extern "C" fn eink_show_shutdown_cover() -> u32 {
     ensure_tcon_client();
     let mut guard = TCON_CLIENT.lock();
     if let Some(client) = guard.as_mut() {
-        let reply = client
-            .call_with_params("show_shutdown_cover", json!({}))
-            .expect("Cannot invoke remote method to tcon service");
-        info!("eink_show_shutdown_cover: result: {:?}", reply.get_result());
+        let reply = match tcon::BlockingClient::new(client).show_shutdown_cover(Empty {}) {
+            Ok(reply) => reply,
+            Err(err) => {
+                crate::report_call_error("tcon", &err);
+                return 0;
+            }
+        };
+        info!("eink_show_shutdown_cover: result: {:?}", reply);
     }
     0
 }
@@ -122,17 +155,61 @@ pub extern "C" fn eink_set_shutdown_cover(path: *const u16, disp_type: u32) -> u
     ensure_tcon_client();
     let mut guard = TCON_CLIENT.lock();
     if let Some(client) = guard.as_mut() {
-        let reply = client
-            .call_with_params(
-                "set_shutdown_cover",
-                json!({
-                    "path": path,
-                    "type": disp_type
-                }),
-            )
-            .expect("Cannot invoke remote method to tcon service");
-
-        info!("eink_set_shutdown_cover: result: {:?}", reply.get_result());
+        let reply = match tcon::BlockingClient::new(client)
+            .set_shutdown_cover(SetShutdownCoverParams { path, disp_type })
+        {
+            Ok(reply) => reply,
+            Err(err) => {
+                crate::report_call_error("tcon", &err);
+                return 0;
+            }
+        };
+
+        info!("eink_set_shutdown_cover: result: {:?}", reply);
+    }
+
+    0
+}
+
+/// 关机壁纸设置进度回调，参数为进度百分比与调用方数据
+pub type ShutdownCoverProgressCallback = extern "C" fn(percent: u32, user_data: *mut c_void);
+
+/// 设置 Eink 关机壁纸，上传期间通过回调报告进度
+#[no_mangle]
+pub extern "C" fn eink_set_shutdown_cover_with_progress(
+    path: *const u16,
+    disp_type: u32,
+    on_progress: Option<ShutdownCoverProgressCallback>,
+    user_data: *mut c_void,
+) -> u32 {
+    let path = unsafe { widestring::U16CString::from_ptr_str(path) };
+    let path = path.to_string_lossy();
+
+    info!(
+        "eink_set_shutdown_cover_with_progress: path: {}, type: {}",
+        &path, disp_type
+    );
+
+    ensure_tcon_client();
+    let mut guard = TCON_CLIENT.lock();
+    if let Some(client) = guard.as_mut() {
+        let result = encode_params(&SetShutdownCoverParams { path, disp_type })
+            .and_then(|params| client.call_stream(tcon::method::set_shutdown_cover, params))
+            .and_then(|mut stream| {
+                while let Some(progress) = stream.next() {
+                    let percent = progress["percent"].as_u64().unwrap_or_default();
+                    if let Some(on_progress) = on_progress {
+                        on_progress(percent as u32, user_data);
+                    }
+                }
+                stream.finish()
+            })
+            .and_then(decode_reply::<Ack>);
+
+        match result {
+            Ok(reply) => info!("eink_set_shutdown_cover_with_progress: result: {:?}", reply),
+            Err(err) => crate::report_call_error("tcon", &err),
+        }
     }
 
     0
@@ -13,9 +13,10 @@
 use std::ffi::c_void;
 
 use eink_pipe_io::blocking::BlockingClient;
+use eink_pipe_io::retry::RetryPolicy;
+use eink_service_proto::{topmost, Empty, HwndParams, PidParams};
 use log::{error, info};
 use parking_lot::Mutex;
-use serde_json::json;
 use windows::Win32::{
     Foundation::{GetLastError, HINSTANCE},
     System::SystemServices::DLL_PROCESS_ATTACH,
@@ -28,16 +29,29 @@ const TOPMOST_PIPE_NAME: &str = r"\\.\pipe\lenovo\eink-service\topmost";
 //
 pub static TOPMOST_CLIENT: Mutex<Option<BlockingClient>> = Mutex::new(None);
 
+/// 链路断开后可以安全重试的方法
+fn topmost_retry_policy() -> RetryPolicy {
+    RetryPolicy::new(2)
+        .idempotent("set_window_topmost")
+        .idempotent("unset_window_topmost")
+        .idempotent("clear_all_windows_topmost")
+}
+
 /// 检查链接状态
+///
+/// 客户端在链路断开后会自动重连，这里只负责首次建立连接
 fn ensure_topmost_client() {
     let mut guard = TOPMOST_CLIENT.lock();
+    crate::clear_last_error();
 
     if guard.is_none() {
         let client = eink_pipe_io::blocking::connect(TOPMOST_PIPE_NAME);
 
-        if let Ok(client) = client {
+        if let Ok(mut client) = client {
+            client.set_retry_policy(topmost_retry_policy());
             guard.replace(client);
         } else {
+            crate::set_connect_error();
             error!(
                 "Cannot connect to topmost service: last error: {:?}",
                 unsafe { GetLastError() }
@@ -52,10 +66,16 @@ pub extern "C" fn set_window_topmost(hwnd: u32) -> u32 {
     ensure_topmost_client();
     let mut guard = TOPMOST_CLIENT.lock();
     if let Some(client) = guard.as_mut() {
-        let reply = client
-            .call_with_params("set_window_topmost", json!({ "hwnd": hwnd }))
-            .expect("Cannot invoke remote method to topmost service");
-        info!("set_window_topmost: result: {:?}", reply.get_result());
+        let reply = match topmost::BlockingClient::new(client)
+            .set_window_topmost(HwndParams { hwnd: hwnd as i64 })
+        {
+            Ok(reply) => reply,
+            Err(err) => {
+                crate::report_call_error("topmost", &err);
+                return 0;
+            }
+        };
+        info!("set_window_topmost: result: {:?}", reply);
     }
     0
 }
@@ -66,10 +86,16 @@ pub extern "C" fn unset_window_topmost(hwnd: u32) -> u32 {
     ensure_topmost_client();
     let mut guard = TOPMOST_CLIENT.lock();
     if let Some(client) = guard.as_mut() {
-        let reply = client
-            .call_with_params("unset_window_topmost", json!({ "hwnd": hwnd }))
-            .expect("Cannot invoke remote method to topmost service");
-        info!("unset_window_topmost: result: {:?}", reply.get_result());
+        let reply = match topmost::BlockingClient::new(client)
+            .unset_window_topmost(HwndParams { hwnd: hwnd as i64 })
+        {
+            Ok(reply) => reply,
+            Err(err) => {
+                crate::report_call_error("topmost", &err);
+                return 0;
+            }
+        };
+        info!("unset_window_topmost: result: {:?}", reply);
     }
     0
 }
@@ -80,13 +106,14 @@ pub extern "C" fn clear_all_windows_topmost() -> u32 {
     ensure_topmost_client();
     let mut guard = TOPMOST_CLIENT.lock();
     if let Some(client) = guard.as_mut() {
-        let reply = client
-            .call_with_params("clear_all_windows_topmost", json!({}))
-            .expect("Cannot invoke remote method to topmost service");
-        info!(
-            "clear_all_windows_topmost: result: {:?}",
-            reply.get_result()
-        );
+        let reply = match topmost::BlockingClient::new(client).clear_all_windows_topmost(Empty {}) {
+            Ok(reply) => reply,
+            Err(err) => {
+                crate::report_call_error("topmost", &err);
+                return 0;
+            }
+        };
+        info!("clear_all_windows_topmost: result: {:?}", reply);
     }
     0
 }
@@ -97,13 +124,16 @@ pub extern "C" fn adjust_topmost_on_app_launched(pid: isize) -> u32 {
     ensure_topmost_client();
     let mut guard = TOPMOST_CLIENT.lock();
     if let Some(client) = guard.as_mut() {
-        let reply = client
-            .call_with_params("adjust_topmost_on_app_launched", json!({ "pid": pid }))
-            .expect("Cannot invoke remote method to topmost service");
-        info!(
-            "adjust_topmost_on_app_launched: result: {:?}",
-            reply.get_result()
-        );
+        let reply = match topmost::BlockingClient::new(client)
+            .adjust_topmost_on_app_launched(PidParams { pid: pid as i64 })
+        {
+            Ok(reply) => reply,
+            Err(err) => {
+                crate::report_call_error("topmost", &err);
+                return 0;
+            }
+        };
+        info!("adjust_topmost_on_app_launched: result: {:?}", reply);
     }
     0
 }
@@ -114,10 +144,14 @@ pub extern "C" fn switch_eink_oled_display() -> u32 {
     ensure_topmost_client();
     let mut guard = TOPMOST_CLIENT.lock();
     if let Some(client) = guard.as_mut() {
-        let reply = client
-            .call_with_params("switch_eink_oled_display", json!({}))
-            .expect("Cannot invoke remote method to topmost service");
-        info!("switch_eink_oled_display: result: {:?}", reply.get_result());
+        let reply = match topmost::BlockingClient::new(client).switch_eink_oled_display(Empty {}) {
+            Ok(reply) => reply,
+            Err(err) => {
+                crate::report_call_error("topmost", &err);
+                return 0;
+            }
+        };
+        info!("switch_eink_oled_display: result: {:?}", reply);
     }
     0
 }
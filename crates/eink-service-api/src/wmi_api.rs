@@ -13,9 +13,10 @@
 use std::ffi::c_void;
 
 use eink_pipe_io::blocking::BlockingClient;
+use eink_pipe_io::retry::RetryPolicy;
+use eink_service_proto::{wmi, Empty, ReadingLightParams};
 use log::{error, info};
 use parking_lot::Mutex;
-use serde_json::json;
 use windows::Win32::{
     Foundation::{GetLastError, HINSTANCE},
     System::SystemServices::DLL_PROCESS_ATTACH,
@@ -28,16 +29,28 @@ const WMI_PIPE_NAME: &str = r"\\.\pipe\lenovo\eink-service\wmi";
 //
 pub static WMI_CLIENT: Mutex<Option<BlockingClient>> = Mutex::new(None);
 
+/// 链路断开后可以安全重试的方法
+fn wmi_retry_policy() -> RetryPolicy {
+    RetryPolicy::new(2)
+        .idempotent("set_reading_light_status")
+        .idempotent("get_reading_light_status")
+}
+
 /// 检查链接状态
+///
+/// 客户端在链路断开后会自动重连，这里只负责首次建立连接
 fn ensure_wmi_client() {
     let mut guard = WMI_CLIENT.lock();
+    crate::clear_last_error();
 
     if guard.is_none() {
         let client = eink_pipe_io::blocking::connect(WMI_PIPE_NAME);
 
-        if let Ok(client) = client {
+        if let Ok(mut client) = client {
+            client.set_retry_policy(wmi_retry_policy());
             guard.replace(client);
         } else {
+            crate::set_connect_error();
             error!("Cannot connect to tcon service: last error: {:?}", unsafe {
                 GetLastError()
             });
@@ -51,10 +64,16 @@ pub extern "C" fn eink_set_reading_light_status(level: u32) -> u32 {
     ensure_wmi_client();
     let mut guard = WMI_CLIENT.lock();
     if let Some(client) = guard.as_mut() {
-        let reply = client
-            .call_with_params("set_reading_light_status", json!({ "level": level }))
-            .expect("Cannot invoke remote method to tcon service");
-        info!("set_reading_light_status: result: {:?}", reply.get_result());
+        let reply = match wmi::BlockingClient::new(client)
+            .set_reading_light_status(ReadingLightParams { level })
+        {
+            Ok(reply) => reply,
+            Err(err) => {
+                crate::report_call_error("wmi", &err);
+                return 0;
+            }
+        };
+        info!("set_reading_light_status: result: {:?}", reply);
     }
     0
 }
@@ -65,15 +84,15 @@ pub extern "C" fn eink_get_reading_light_status() -> u32 {
     ensure_wmi_client();
     let mut guard = WMI_CLIENT.lock();
     if let Some(client) = guard.as_mut() {
-        let reply = client
-            .call_with_params("get_reading_light_status", json!({}))
-            .expect("Cannot invoke remote method to tcon service");
-        info!("set_reading_light_status: result: {:?}", reply.get_result());
-        if let Some(level) = reply.get_result() {
-            if let Some(level) = level.as_u64() {
-                return level as u32;
+        let reply = match wmi::BlockingClient::new(client).get_reading_light_status(Empty {}) {
+            Ok(reply) => reply,
+            Err(err) => {
+                crate::report_call_error("wmi", &err);
+                return u32::max_value();
             }
-        }
+        };
+        info!("get_reading_light_status: result: {:?}", reply);
+        return reply;
     }
     u32::max_value()
 }
@@ -192,11 +192,15 @@ struct Opt {
 fn switch_to_eink_launcher_mode() {
     if let Ok(eink_monitor_id) = SETTINGS.read().get_string("eink_monitor_id") {
         if eink_monitor_id.len() > 8 {
-            set_monitor_specialized(&eink_monitor_id, false).unwrap();
+            if let Err(err) = set_monitor_specialized(&eink_monitor_id, false) {
+                log::error!("Cannot set monitor specialized: {err}");
+            }
         }
         if let Ok(oled_monitor_id) = SETTINGS.read().get_string("oled_monitor_id") {
             if oled_monitor_id.len() > 8 {
-                set_monitor_specialized(&oled_monitor_id, true).unwrap();
+                if let Err(err) = set_monitor_specialized(&oled_monitor_id, true) {
+                    log::error!("Cannot set monitor specialized: {err}");
+                }
 
                 // 置顶 Launcher
             }
@@ -207,10 +211,14 @@ fn switch_to_eink_launcher_mode() {
 // 切换搭配 OLED Windows 桌面模式
 fn switch_to_oled_windows_desktop_mode() {
     if let Ok(oled_monitor_id) = SETTINGS.read().get_string("oled_monitor_id") {
-        set_monitor_specialized(&oled_monitor_id, false).unwrap();
+        if let Err(err) = set_monitor_specialized(&oled_monitor_id, false) {
+            log::error!("Cannot set monitor specialized: {err}");
+        }
 
         if let Ok(eink_monitor_id) = SETTINGS.read().get_string("eink_monitor_id") {
-            set_monitor_specialized(&eink_monitor_id, true).unwrap();
+            if let Err(err) = set_monitor_specialized(&eink_monitor_id, true) {
+                log::error!("Cannot set monitor specialized: {err}");
+            }
 
             // 最小化 Launcher
         }
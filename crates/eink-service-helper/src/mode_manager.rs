@@ -146,12 +146,16 @@ impl ModeManager {
     fn switch_to_eink_launcher_mode(eink_monitor_id: &str, oled_monitor_id: &str) {
         log::info!("switch_to_eink_launcher_mode");
 
-        set_monitor_specialized(eink_monitor_id, false).unwrap();
+        if let Err(err) = set_monitor_specialized(eink_monitor_id, false) {
+            log::error!("Cannot set monitor specialized: {err}");
+        }
 
         // Sleep 100ms 等待 Windows Display 稳定
         std::thread::sleep(std::time::Duration::from_millis(100));
 
-        set_monitor_specialized(oled_monitor_id, true).unwrap();
+        if let Err(err) = set_monitor_specialized(oled_monitor_id, true) {
+            log::error!("Cannot set monitor specialized: {err}");
+        }
 
         let launcher_title = s!("ThinkbookEinkPlus2A7678FA-39DD-4C1D-8981-34A451919F59");
 
@@ -216,12 +220,16 @@ impl ModeManager {
     fn switch_to_oled_windows_desktop_mode(eink_monitor_id: &str, oled_monitor_id: &str) {
         log::info!("switch_to_oled_windows_desktop_mode");
 
-        set_monitor_specialized(&oled_monitor_id, false).unwrap();
+        if let Err(err) = set_monitor_specialized(&oled_monitor_id, false) {
+            log::error!("Cannot set monitor specialized: {err}");
+        }
 
         // Sleep 100ms 等待 Windows Display 稳定
         std::thread::sleep(std::time::Duration::from_millis(100));
 
-        set_monitor_specialized(&eink_monitor_id, true).unwrap();
+        if let Err(err) = set_monitor_specialized(&eink_monitor_id, true) {
+            log::error!("Cannot set monitor specialized: {err}");
+        }
 
         // 设置 EINK 触摸区域
         tcon_api::eink_set_tp_mask_area(tcon_api::TOUCH_EVENT_NO_REPORT, 1, 0, 2560, 0, 1600);
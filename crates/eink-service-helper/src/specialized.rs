@@ -14,6 +14,7 @@ use std::mem::{size_of, zeroed};
 use std::process::Command;
 
 use anyhow::{bail, Result};
+use eink_service_proto::ServiceError;
 use ntapi::winapi::shared::ntdef::{PWCH, UNICODE_STRING};
 use widestring::U16String;
 use windows::core::GUID;
@@ -54,7 +55,10 @@ pub fn set_monitor_specialized(monitor_id: &str, specialized: bool) -> Result<()
             }
         }
     }
-    Ok(())
+    Err(ServiceError::MonitorNotFound {
+        monitor_id: monitor_id.to_owned(),
+    }
+    .into())
 }
 
 // pub fn set_monitor_specialized(monitor_id: &str, on: bool) -> Result<()> {
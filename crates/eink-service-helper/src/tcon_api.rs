@@ -11,11 +11,16 @@
 //
 
 use std::ffi::c_void;
+use std::fmt::Debug;
 
 use eink_pipe_io::blocking::BlockingClient;
+use eink_pipe_io::retry::RetryPolicy;
+use eink_pipe_io::service::CallResult;
+use eink_service_proto::{
+    tcon, Empty, ServiceError, SetMipiModeParams, SetShutdownCoverParams, SetTpMaskAreaParams,
+};
 use log::{error, info};
 use parking_lot::Mutex;
-use serde_json::json;
 use windows::Win32::Foundation::{GetLastError, SetLastError, HINSTANCE, NO_ERROR};
 use windows::Win32::System::SystemServices::DLL_PROCESS_ATTACH;
 
@@ -26,7 +31,23 @@ const TCON_PIPE_NAME: &str = r"\\.\pipe\lenovo\eink-service\tcon";
 //
 pub static TCON_CLIENT: Mutex<Option<BlockingClient>> = Mutex::new(None);
 
+/// 链路断开后可以安全重试的方法
+///
+/// 设置类方法写入的是目标状态，重复执行结果相同。`refresh` 与 `show_shutdown_cover`
+/// 会重新绘制屏幕，重放只会多刷新一次当前画面，不会改变 TCON 的状态；
+/// `software_reset_api`、`start_launcher` 等会重复产生副作用的方法不能重试
+fn tcon_retry_policy() -> RetryPolicy {
+    RetryPolicy::new(2)
+        .idempotent(tcon::method::refresh)
+        .idempotent(tcon::method::set_mipi_mode)
+        .idempotent(tcon::method::show_shutdown_cover)
+        .idempotent(tcon::method::set_shutdown_cover)
+        .idempotent(tcon::method::set_tp_mask_area)
+}
+
 /// 检查链接状态
+///
+/// 客户端在链路断开后会自动重连，这里只负责首次建立连接
 fn connect_tcon_client() {
     let mut guard = TCON_CLIENT.lock();
 
@@ -35,7 +56,8 @@ fn connect_tcon_client() {
     if guard.is_none() {
         let client = eink_pipe_io::blocking::connect(TCON_PIPE_NAME);
 
-        if let Ok(client) = client {
+        if let Ok(mut client) = client {
+            client.set_retry_policy(tcon_retry_policy());
             guard.replace(client);
         } else {
             error!("Cannot connect to tcon service: last error: {:?}", unsafe {
@@ -50,100 +72,59 @@ pub fn disconnect_tcon_client() {
     let _client = guard.take();
 }
 
-/// 设置 Eink 刷新
-pub fn eink_refresh() -> u32 {
-    for _ in 0..2 {
-        connect_tcon_client();
-        let mut guard = TCON_CLIENT.lock();
-        if let Some(client) = guard.as_mut() {
-            match client.call_with_params("refresh", json!({})) {
-                Ok(reply) => {
-                    log::info!("eink_refresh: result: {:?}", reply.get_result());
-                    break;
-                }
-                Err(err) => {
-                    log::error!("Cannot invoke remote method to tcon service: err: {err:?}");
-
-                    // 发生错误，断开链接, 再次尝试
-                    disconnect_tcon_client();
-                    continue;
+/// 通过 tcon 客户端存根调用服务方法，失败时记录日志
+fn call_tcon_method<R, F>(name: &str, f: F) -> Option<R>
+where
+    R: Debug,
+    F: FnOnce(&mut tcon::BlockingClient) -> CallResult<R>,
+{
+    connect_tcon_client();
+    let mut guard = TCON_CLIENT.lock();
+    let client = guard.as_mut()?;
+    match f(&mut tcon::BlockingClient::new(client)) {
+        Ok(reply) => {
+            log::info!("{name}: result: {:?}", reply);
+            Some(reply)
+        }
+        Err(err) => {
+            match ServiceError::from_call_error(&err) {
+                Some(err) => {
+                    log::error!("{name}: tcon service returns error {}: {err}", err.code())
                 }
+                None => log::error!("Cannot invoke remote method to tcon service: err: {err:?}"),
             }
+            None
         }
     }
+}
+
+/// 设置 Eink 刷新
+pub fn eink_refresh() -> u32 {
+    call_tcon_method("eink_refresh", |tcon| tcon.refresh(Empty {}));
     0
 }
 
 /// 设置 Eink MIPI Mode
 pub fn eink_set_mipi_mode(mode: u32) -> u32 {
-    for _ in 0..2 {
-        connect_tcon_client();
-        let mut guard = TCON_CLIENT.lock();
-        if let Some(client) = guard.as_mut() {
-            match client.call_with_params("set_mipi_mode", json!({ "mode": mode })) {
-                Ok(reply) => {
-                    log::info!("eink_set_mipi_mode: result: {:?}", reply.get_result());
-                    break;
-                }
-                Err(err) => {
-                    log::error!("Cannot invoke remote method to tcon service: err: {err:?}");
-
-                    // 发生错误，断开链接, 再次尝试
-                    disconnect_tcon_client();
-                    continue;
-                }
-            }
-        }
-    }
+    call_tcon_method("eink_set_mipi_mode", |tcon| {
+        tcon.set_mipi_mode(SetMipiModeParams { mode })
+    });
     0
 }
 
 /// 软件启动 TCON
-/// 重试 2 次
 pub fn eink_software_reset_tcon() -> u32 {
-    for _ in 0..2 {
-        connect_tcon_client();
-        let mut guard = TCON_CLIENT.lock();
-        if let Some(client) = guard.as_mut() {
-            match client.call_with_params("software_reset_api", json!({})) {
-                Ok(reply) => {
-                    log::info!("eink_software_reset_tcon: result: {:?}", reply.get_result());
-                    break;
-                }
-                Err(err) => {
-                    log::error!("Cannot invoke remote method to tcon service: err: {err:?}");
-
-                    // 发生错误，断开链接, 再次尝试
-                    disconnect_tcon_client();
-                    continue;
-                }
-            }
-        }
-    }
+    call_tcon_method("eink_software_reset_tcon", |tcon| {
+        tcon.software_reset_api(Empty {})
+    });
     0
 }
 
 /// 设置 Eink 显示关机壁纸
 pub fn eink_show_shutdown_cover() -> u32 {
-    for _ in 0..2 {
-        connect_tcon_client();
-        let mut guard = TCON_CLIENT.lock();
-        if let Some(client) = guard.as_mut() {
-            match client.call_with_params("show_shutdown_cover", json!({})) {
-                Ok(reply) => {
-                    log::info!("eink_show_shutdown_cover: result: {:?}", reply.get_result());
-                    break;
-                }
-                Err(err) => {
-                    log::error!("Cannot invoke remote method to tcon service: err: {err:?}");
-
-                    // 发生错误，断开链接, 再次尝试
-                    disconnect_tcon_client();
-                    continue;
-                }
-            }
-        }
-    }
+    call_tcon_method("eink_show_shutdown_cover", |tcon| {
+        tcon.show_shutdown_cover(Empty {})
+    });
     0
 }
 
@@ -157,80 +138,24 @@ pub fn eink_set_shutdown_cover(path: *const u16, disp_type: u32) -> u32 {
         &path, disp_type
     );
 
-    for _ in 0..2 {
-        connect_tcon_client();
-        let mut guard = TCON_CLIENT.lock();
-        if let Some(client) = guard.as_mut() {
-            match client.call_with_params(
-                "set_shutdown_cover",
-                json!({
-                    "path": path,
-                    "type": disp_type
-                }),
-            ) {
-                Ok(reply) => {
-                    log::info!("eink_set_shutdown_cover: result: {:?}", reply.get_result());
-                    break;
-                }
-                Err(err) => {
-                    log::error!("Cannot invoke remote method to tcon service: err: {err:?}");
-
-                    // 发生错误，断开链接, 再次尝试
-                    disconnect_tcon_client();
-                    continue;
-                }
-            }
-        }
-    }
+    call_tcon_method("eink_set_shutdown_cover", |tcon| {
+        tcon.set_shutdown_cover(SetShutdownCoverParams { path, disp_type })
+    });
 
     0
 }
 
 /// 设置 Eink 显示关机壁纸
 pub fn eink_start_lockscreen_note() -> u32 {
-    for _ in 0..2 {
-        connect_tcon_client();
-        let mut guard = TCON_CLIENT.lock();
-        if let Some(client) = guard.as_mut() {
-            match client.call_with_params("start_lockscreen_note", json!({})) {
-                Ok(reply) => {
-                    log::info!("start_lockscreen_note: result: {:?}", reply.get_result());
-                    break;
-                }
-                Err(err) => {
-                    log::error!("Cannot invoke remote method to tcon service: err: {err:?}");
-
-                    // 发生错误，断开链接, 再次尝试
-                    disconnect_tcon_client();
-                    continue;
-                }
-            }
-        }
-    }
+    call_tcon_method("start_lockscreen_note", |tcon| {
+        tcon.start_lockscreen_note(Empty {})
+    });
     0
 }
 
 /// TODO: 临时借用宝地
 pub fn eink_start_launcher() -> u32 {
-    for _ in 0..2 {
-        connect_tcon_client();
-        let mut guard = TCON_CLIENT.lock();
-        if let Some(client) = guard.as_mut() {
-            match client.call_with_params("start_launcher", json!({})) {
-                Ok(reply) => {
-                    log::info!("start_launcher: result: {:?}", reply.get_result());
-                    break;
-                }
-                Err(err) => {
-                    log::error!("Cannot invoke remote method to tcon service: err: {err:?}");
-
-                    // 发生错误，断开链接, 再次尝试
-                    disconnect_tcon_client();
-                    continue;
-                }
-            }
-        }
-    }
+    call_tcon_method("start_launcher", |tcon| tcon.start_launcher(Empty {}));
     0
 }
 
@@ -248,34 +173,15 @@ pub fn eink_set_tp_mask_area(
     y1: u32,
     y2: u32,
 ) -> u32 {
-    for _ in 0..2 {
-        connect_tcon_client();
-        let mut guard = TCON_CLIENT.lock();
-        if let Some(client) = guard.as_mut() {
-            match client.call_with_params(
-                "set_tp_mask_area",
-                json!({
-                    "pen_style": pen_style,
-                    "area_id": area_id,
-                    "x1": x1,
-                    "x2": x2,
-                    "y1": y1,
-                    "y2": y2,
-                }),
-            ) {
-                Ok(reply) => {
-                    log::info!("eink_set_tp_mask_area: result: {:?}", reply.get_result());
-                    break;
-                }
-                Err(err) => {
-                    log::error!("Cannot invoke remote method to tcon service: err: {err:?}");
-
-                    // 发生错误，断开链接, 再次尝试
-                    disconnect_tcon_client();
-                    continue;
-                }
-            }
-        }
-    }
+    call_tcon_method("eink_set_tp_mask_area", |tcon| {
+        tcon.set_tp_mask_area(SetTpMaskAreaParams {
+            pen_style,
+            area_id,
+            x1,
+            x2,
+            y1,
+            y2,
+        })
+    });
     0
 }
@@ -12,14 +12,11 @@
 
 use std::sync::Arc;
 
-use eink_pipe_io::server::Socket;
+use eink_pipe_io::blocking::{BlockingServer, ServerHandle};
+use eink_service_proto::{topmost, Ack, Empty, HwndParams, PidParams, RpcResult};
 use eink_winkits::get_window_text;
-use if_chain::if_chain;
-use jsonrpc_lite::{Id, JsonRpc, Params};
 use log::info;
-use parking_lot::{Mutex, RwLock};
-use signals2::{Connect2, Emit2, Signal};
-use tokio::runtime::Runtime;
+use parking_lot::Mutex;
 use windows::s;
 use windows::Win32::Foundation::{HWND, LPARAM, WPARAM};
 use windows::Win32::UI::WindowsAndMessaging::{
@@ -28,10 +25,6 @@ use windows::Win32::UI::WindowsAndMessaging::{
 };
 
 use crate::mode_manager::set_window_topmost;
-use crate::utils::{
-    jsonrpc_error_internal_error, jsonrpc_error_invalid_params, jsonrpc_error_method_not_found,
-    jsonrpc_success_string,
-};
 use crate::win_utils::{find_window_by_classname, find_window_by_title};
 
 const PIPE_NAME: &str = r"\\.\pipe\lenovo\eink-service\topmost";
@@ -40,133 +33,29 @@ const PIPE_NAME: &str = r"\\.\pipe\lenovo\eink-service\topmost";
 pub struct TopmostManager {
     curr_topmost_hwnd: Arc<Mutex<Option<HWND>>>,
 
-    /// IPC 接口使用 tokio 异步运行时
-    rt: Runtime,
-
-    /// IPC 请求响应函数
-    on_request: Arc<RwLock<Signal<(Id, JsonRpc), JsonRpc>>>,
+    /// 后台运行的 IPC 服务器
+    ipc_server: Option<ServerHandle>,
 }
 
 impl TopmostManager {
     ///
     pub fn new() -> anyhow::Result<Self> {
-        let rt = tokio::runtime::Builder::new_multi_thread()
-            .worker_threads(3)
-            .on_thread_start(|| {
-                log::info!(
-                    "TopmostManager: thread [{:?}] started",
-                    std::thread::current().id()
-                );
-            })
-            .on_thread_stop(|| {
-                log::info!(
-                    "TopmostManager: thread [{:?}] stopping",
-                    std::thread::current().id()
-                );
-            })
-            .enable_all()
-            .build()
-            .expect("Cannot create tokio runtime for TopmostManager");
-
         Ok(Self {
             curr_topmost_hwnd: Default::default(),
-            rt,
-            on_request: Default::default(),
+            ipc_server: None,
         })
     }
 
     pub fn after_init(this: &mut Arc<Mutex<Self>>) -> anyhow::Result<()> {
         info!("TopmostManager: init");
 
-        let this2 = this.clone();
-
-        this.lock()
-            .on_request
-            .write()
-            .connect(move |id, req| -> JsonRpc {
-                info!("TopmostManager: On request");
-                match req.get_method() {
-                    Some("set_window_topmost") => if_chain! {
-                        if let Some(Params::Map(map)) = req.get_params();
-                        if let Some(hwnd) = map.get("hwnd");
-                        if let Some(hwnd) = hwnd.as_i64();
-                        then {
-                            std::thread::spawn(move || unsafe {
-                                set_window_topmost(HWND(hwnd as isize));
-
-                                // 调用 Windows 的置顶方法，需要在异步上下文进行，因为同步 RPC 会造成消息死锁
-                                SetWindowPos(
-                                    HWND(hwnd as isize),
-                                    HWND_TOPMOST,
-                                    0,
-                                    0,
-                                    0,
-                                    0,
-                                    SWP_NOMOVE | SWP_SHOWWINDOW | SWP_NOSIZE,
-                                );
-                            });
-                            return jsonrpc_success_string(id, "true");
-                        } else {
-                            jsonrpc_error_invalid_params(id)
-                        }
-                    },
-                    Some("unset_window_topmost") => {
-                        if_chain! {
-                            if let Some(Params::Map(map)) = req.get_params();
-                            if let Some(hwnd) = map.get("hwnd");
-                            if let Some(hwnd) = hwnd.as_u64();
-                            then {
-                                unset_window_topmost(HWND(hwnd as isize));
-                                return jsonrpc_success_string(id, "true");
-                            }
-                        }
-                        jsonrpc_success_string(id, "true")
-                    }
-                    Some("clear_all_windows_topmost") => {
-                        // let this2 = this2.clone();
-                        // // std::thread::spawn(move || {
-                        info!(
-                            "clear_all_windows_topmost 1: this2.is_locked(): {}",
-                            this2.is_locked()
-                        );
-                        this2.lock().clear_current_topmost_window();
-
-                        info!(
-                            "clear_all_windows_topmost 2: this2.is_locked(): {}",
-                            this2.is_locked()
-                        );
+        let mut server = BlockingServer::new(PIPE_NAME)?;
+        server.server().set_build_info(crate::utils::build_info());
+        crate::utils::add_middlewares(server.server());
+        topmost::serve(server.server(), Arc::new(TopmostRpc(this.clone())));
 
-                        // crate::mode_manager::clear_all_windows_topmost();
-                        // });
-                        jsonrpc_success_string(id, "true")
-                    }
-                    Some("adjust_topmost_on_app_launched") => {
-                        if_chain! {
-                            if let Some(Params::Map(map)) = req.get_params();
-                            if let Some(pid) = map.get("pid");
-                            if let Some(_pid) = pid.as_u64();
-                            then {
-                                let this2 = this2.clone();
-                                std::thread::spawn(move || {
-                                    this2.lock().adjust_topmost_on_app_launched();
-                                });
-                                jsonrpc_success_string(id, "true")
-                            } else {
-                                jsonrpc_error_invalid_params(id)
-                            }
-                        }
-                    }
-                    // 临时
-                    Some("switch_eink_oled_display") => {
-                        crate::switch_eink_oled_display();
-                        jsonrpc_success_string(id, "true")
-                    }
-                    Some(&_) => jsonrpc_error_method_not_found(id),
-                    None => jsonrpc_error_internal_error(id),
-                }
-            });
-
-        this.lock().start_ipc_server()?;
+        info!("TopmostManager: start_ipc_server");
+        this.lock().ipc_server = Some(server.spawn()?);
 
         Ok(())
     }
@@ -177,7 +66,12 @@ impl TopmostManager {
     fn adjust_topmost_on_app_launched(&mut self) {
         let curr_hwnd = self.curr_topmost_hwnd.clone();
 
-        self.rt.spawn(async move {
+        // 在 IPC 服务器的运行时中执行
+        let runtime = match &self.ipc_server {
+            Some(server) => server.runtime(),
+            None => return,
+        };
+        runtime.spawn(async move {
             if let Some(hwnd) = curr_hwnd.lock().take() {
                 log::info!(
                     "unset topmost and hide 'curr_topmost_hwnd': {:?}",
@@ -233,40 +127,15 @@ impl TopmostManager {
         Ok(())
     }
 
-    /// 启动 IPC 服务器
-    fn start_ipc_server(&mut self) -> anyhow::Result<()> {
-        info!("TopmostManager: start_ipc_server");
-        let mut server = eink_pipe_io::server::Server::new(PIPE_NAME);
-
-        let on_request = self.on_request.clone();
-
-        let _ = server.on_connection(move |socket, req| {
-            info!("TopmostManager: On connection");
-            let on_request2 = on_request.clone();
-            socket
-                .lock()
-                .on_request(move |_socket: Arc<Mutex<Socket>>, id: Id, req: JsonRpc| {
-                    info!("TopmostManager: On request: {req:?}");
-                    match on_request2.read().emit(id.clone(), req) {
-                        Some(reply) => reply,
-                        None => JsonRpc::error(id, jsonrpc_lite::Error::internal_error()),
-                    }
-                });
-            0
-        });
-
-        // 在异步运行时启动
-        self.rt.spawn(async move {
-            info!("TopmostManager: start server listen");
-            server.listen().await;
-            info!("TopmostManager: stop server listen");
-        });
-
-        Ok(())
-    }
-
     /// 停止服务
     pub fn stop(&mut self) -> anyhow::Result<()> {
+        // 停止接受新连接，等待正在处理的请求完成与后台线程退出
+        if let Some(server) = self.ipc_server.take() {
+            server.stop();
+            if let Err(err) = server.join() {
+                log::error!("TopmostManager: ipc server stopped with error: {err}");
+            }
+        }
         Ok(())
     }
 }
@@ -297,3 +166,59 @@ pub static TOPMOST_MANAGER: Arc<Mutex<TopmostManager>> = {
 
     this
 };
+
+/// 置顶 RPC 接口实现
+struct TopmostRpc(Arc<Mutex<TopmostManager>>);
+
+impl topmost::Service for TopmostRpc {
+    fn set_window_topmost(&self, params: HwndParams) -> RpcResult<Ack> {
+        let hwnd = HWND(params.hwnd as isize);
+        std::thread::spawn(move || unsafe {
+            set_window_topmost(hwnd);
+
+            // 调用 Windows 的置顶方法，需要在异步上下文进行，因为同步 RPC 会造成消息死锁
+            SetWindowPos(
+                hwnd,
+                HWND_TOPMOST,
+                0,
+                0,
+                0,
+                0,
+                SWP_NOMOVE | SWP_SHOWWINDOW | SWP_NOSIZE,
+            );
+        });
+        Ok(Ack)
+    }
+
+    fn unset_window_topmost(&self, params: HwndParams) -> RpcResult<Ack> {
+        unset_window_topmost(HWND(params.hwnd as isize));
+        Ok(Ack)
+    }
+
+    fn clear_all_windows_topmost(&self, _params: Empty) -> RpcResult<Ack> {
+        info!(
+            "clear_all_windows_topmost 1: this.is_locked(): {}",
+            self.0.is_locked()
+        );
+        self.0.lock().clear_current_topmost_window();
+        info!(
+            "clear_all_windows_topmost 2: this.is_locked(): {}",
+            self.0.is_locked()
+        );
+        Ok(Ack)
+    }
+
+    fn adjust_topmost_on_app_launched(&self, _params: PidParams) -> RpcResult<Ack> {
+        let this = self.0.clone();
+        std::thread::spawn(move || {
+            this.lock().adjust_topmost_on_app_launched();
+        });
+        Ok(Ack)
+    }
+
+    // 临时
+    fn switch_eink_oled_display(&self, _params: Empty) -> RpcResult<Ack> {
+        crate::switch_eink_oled_display();
+        Ok(Ack)
+    }
+}
@@ -12,23 +12,22 @@
 
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::bail;
-use eink_pipe_io::server::Socket;
-use jsonrpc_lite::{Id, JsonRpc, Params};
+use eink_pipe_io::blocking::{BlockingServer, ServerHandle};
+use eink_service_proto::{wmi as wmi_rpc, Empty, ReadingLightParams, RpcResult, ServiceError};
 use log::{debug, info};
 use parking_lot::Mutex;
+use serde_json::json;
 use signals2::{Connect1, Connection, Emit1, Signal};
-use tokio::runtime::Runtime;
 use tokio::select;
 use tokio_util::sync::CancellationToken;
 use windows::core::HRESULT;
 use windows::Win32::Foundation::RPC_E_TOO_LATE;
 use wmi::{COMLibrary, Variant, WMIConnection, WMIError};
 
-use crate::utils::{
-    jsonrpc_error_internal_error, jsonrpc_error_method_not_found, jsonrpc_success_u32,
-};
+use crate::settings::SETTINGS;
 
 #[derive(Clone, Debug)]
 pub enum LidEvent {
@@ -37,8 +36,8 @@ pub enum LidEvent {
 }
 
 pub struct WmiService {
-    /// IPC 接口使用 tokio 异步运行时
-    rt: Runtime,
+    /// 后台运行的 IPC 服务器
+    ipc_server: Option<ServerHandle>,
 
     token: Option<CancellationToken>,
 
@@ -51,31 +50,19 @@ pub struct WmiService {
 
 const PIPE_NAME: &str = r"\\.\pipe\lenovo\eink-service\wmi";
 
+/// 盒盖翻盖事件主题，参数 `{"lid": "open" | "close"}`
+pub const TOPIC_LID_EVENT: &str = "lid_event";
+
+/// 模式切换事件主题，参数 `{"mode": u32}`
+pub const TOPIC_MODE_SWITCH_EVENT: &str = "mode_switch_event";
+
 impl WmiService {
     pub fn new() -> anyhow::Result<Self> {
-        let rt = tokio::runtime::Builder::new_multi_thread()
-            .worker_threads(3)
-            .on_thread_start(|| {
-                log::info!(
-                    "WmiService thread [{:?}] started",
-                    std::thread::current().id()
-                );
-            })
-            .on_thread_stop(|| {
-                log::info!(
-                    "WmiService thread [{:?}] stopping",
-                    std::thread::current().id()
-                );
-            })
-            .enable_all()
-            .build()
-            .expect("Cannot create tokio runtime for TconService");
-
         let on_lid_event = Signal::default();
         let on_move_switch_event = Signal::default();
 
         Ok(Self {
-            rt,
+            ipc_server: None,
             token: None,
             on_lid_event,
             on_move_switch_event,
@@ -185,7 +172,11 @@ impl WmiService {
 
         let on_move_switch_event2 = self.on_move_switch_event.clone();
 
-        self.rt.spawn(async move {
+        let runtime = match &self.ipc_server {
+            Some(server) => server.runtime(),
+            None => return,
+        };
+        runtime.spawn(async move {
             select! {
                 _ = cloned_token.cancelled() => {
                     info!("Got mode switch event too fast, Ignore mode '{mode}'")
@@ -240,6 +231,39 @@ pub fn wmi_create_com_library() -> anyhow::Result<COMLibrary> {
 pub fn start_service(this: &Arc<Mutex<WmiService>>) -> anyhow::Result<()> {
     info!("WmiService: start_service");
 
+    // 启动 IPC 线程
+    let mut server = BlockingServer::new(PIPE_NAME)?;
+    server.server().set_build_info(crate::utils::build_info());
+    crate::utils::add_middlewares(server.server());
+
+    // 配置了 rpc_stats_interval 时定期将调用统计写入日志，单位秒，用于排查模式切换缓慢
+    if let Ok(secs) = SETTINGS.read().get_int("rpc_stats_interval") {
+        if secs > 0 {
+            let interval = Duration::from_secs(secs as u64);
+            server.server().set_stats_log_interval(interval);
+        }
+    }
+
+    // 向订阅的客户端推送盒盖翻盖及模式切换事件
+    let publisher = server.server().publisher();
+    let _ = this.lock().on_lid_event(move |event| {
+        let lid = match event {
+            LidEvent::Open => "open",
+            LidEvent::Close => "close",
+        };
+        publisher.publish(TOPIC_LID_EVENT, json!({ "lid": lid }));
+    });
+
+    let publisher = server.server().publisher();
+    let _ = this.lock().on_mode_switch_event(move |mode| {
+        publisher.publish(TOPIC_MODE_SWITCH_EVENT, json!({ "mode": mode }));
+    });
+
+    wmi_rpc::serve(server.server(), Arc::new(WmiRpc(this.clone())));
+
+    // 模式切换事件的保护间隔在 IPC 服务器的运行时中计时，需要先于 WMI 事件启动
+    this.lock().ipc_server = Some(server.spawn()?);
+
     // 服务内部初始化
     let this_cloned = this.clone();
 
@@ -350,47 +374,25 @@ pub fn start_service(this: &Arc<Mutex<WmiService>>) -> anyhow::Result<()> {
         }
     });
 
-    // 启动 IPC 线程
-    let mut server = eink_pipe_io::server::Server::new(PIPE_NAME);
+    Ok(())
+}
 
-    let this_cloned = this.clone();
-    let _ = server.on_connection(move |socket, _req| {
-        info!("WmiService: On connection");
-        let this_cloned = this_cloned.clone();
-        socket
-            .lock()
-            .on_request(move |_socket: Arc<Mutex<Socket>>, id: Id, req: JsonRpc| {
-                info!("TconService: On request: {req:?}");
-                match req.get_method() {
-                    Some("set_reading_light_status") => {
-                        if_chain::if_chain! {
-                            if let Some(Params::Map(map)) = req.get_params();
-                            if let Some(level) = map.get("level");
-                            if let Some(level) = level.as_u64();
-                            then {
-                                this_cloned.lock().set_reading_light_status(level as u32);
-                                jsonrpc_success_u32(id, 0)
-                            } else {
-                                jsonrpc_error_internal_error(id)
-                            }
-                        }
-                    }
-                    Some("get_reading_light_status") => {
-                        let level = this_cloned.lock().get_reading_light_status();
-                        jsonrpc_success_u32(id, level)
-                    }
-                    Some(&_) => jsonrpc_error_method_not_found(id),
-                    None => jsonrpc_error_method_not_found(id),
-                }
-            });
-        0
-    });
+/// WMI RPC 接口实现
+struct WmiRpc(Arc<Mutex<WmiService>>);
 
-    // 在异步运行时启动
-    this.lock().rt.spawn(async move {
-        info!("TconService: start server listen");
-        server.listen().await;
-        info!("TconService: stop server listen");
-    });
-    Ok(())
+impl wmi_rpc::Service for WmiRpc {
+    fn set_reading_light_status(&self, params: ReadingLightParams) -> RpcResult<u32> {
+        self.0.lock().set_reading_light_status(params.level);
+        Ok(0)
+    }
+
+    fn get_reading_light_status(&self, _params: Empty) -> RpcResult<u32> {
+        match self.0.lock().get_reading_light_status() {
+            u32::MAX => Err(ServiceError::WmiFailed {
+                reason: "Cannot read reading light level".to_owned(),
+            }
+            .into()),
+            level => Ok(level),
+        }
+    }
 }
@@ -0,0 +1,135 @@
+//
+// Copyright (C) Lenovo ThinkBook Gen4 Project.
+//
+// This program is protected under international and China copyright laws as
+// an unpublished work. This program is confidential and proprietary to the
+// copyright owners. Reproduction or disclosure, in whole or in part, or the
+// production of derivative works therefrom without the express permission of
+// the copyright owners is prohibited.
+//
+// All rights reserved.
+//
+
+//! 服务错误
+//!
+//! 服务端返回 `ServiceError` 转换后的 JSON-RPC 错误对象，错误码稳定不变，
+//! 错误数据为带有 `kind` 标签的结构化字段。客户端可以从调用错误中还原 `ServiceError`。
+
+use std::fmt;
+
+use eink_pipe_io::jsonrpc;
+use eink_pipe_io::service::RemoteError;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// 服务错误，已发布的错误码不能修改
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "kind")]
+pub enum ServiceError {
+    /// 未分类的服务内部错误
+    Internal { reason: String },
+    /// TCON 设备不可用
+    TconUnavailable,
+    /// 关机壁纸图片加载失败
+    CoverImageLoadFailed { path: String, reason: String },
+    /// 找不到指定的显示器
+    MonitorNotFound { monitor_id: String },
+    /// 键盘钩子安装或卸载失败
+    KeyboardHookFailed { reason: String },
+    /// WMI 调用失败
+    WmiFailed { reason: String },
+}
+
+impl ServiceError {
+    pub fn internal<E: fmt::Display>(err: E) -> Self {
+        ServiceError::Internal {
+            reason: err.to_string(),
+        }
+    }
+
+    /// JSON-RPC 错误码
+    pub fn code(&self) -> i64 {
+        match self {
+            ServiceError::Internal { .. } => 1000,
+            ServiceError::TconUnavailable => 1001,
+            ServiceError::CoverImageLoadFailed { .. } => 1002,
+            ServiceError::MonitorNotFound { .. } => 1003,
+            ServiceError::KeyboardHookFailed { .. } => 1004,
+            ServiceError::WmiFailed { .. } => 1005,
+        }
+    }
+
+    /// 从 JSON-RPC 错误对象还原，错误码与错误数据不匹配时返回 None
+    pub fn from_rpc_error(error: &jsonrpc::Error) -> Option<Self> {
+        let data = error.data.clone()?;
+        let err: ServiceError = serde_json::from_value(data).ok()?;
+        (err.code() == error.code).then_some(err)
+    }
+
+    /// 从客户端存根的调用错误中还原，非服务错误返回 None
+    pub fn from_call_error(err: &anyhow::Error) -> Option<Self> {
+        err.downcast_ref::<RemoteError>()
+            .and_then(|remote| Self::from_rpc_error(&remote.0))
+    }
+}
+
+impl fmt::Display for ServiceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServiceError::Internal { reason } => write!(f, "Internal error: {reason}"),
+            ServiceError::TconUnavailable => write!(f, "TCON device is unavailable"),
+            ServiceError::CoverImageLoadFailed { path, reason } => {
+                write!(f, "Cannot load cover image '{path}': {reason}")
+            }
+            ServiceError::MonitorNotFound { monitor_id } => {
+                write!(f, "Monitor '{monitor_id}' was not found")
+            }
+            ServiceError::KeyboardHookFailed { reason } => {
+                write!(f, "Keyboard hook failed: {reason}")
+            }
+            ServiceError::WmiFailed { reason } => write!(f, "WMI call failed: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for ServiceError {}
+
+impl From<ServiceError> for jsonrpc::Error {
+    fn from(err: ServiceError) -> Self {
+        jsonrpc::Error {
+            code: err.code(),
+            message: err.to_string(),
+            data: serde_json::to_value(&err).ok(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use eink_pipe_io::jsonrpc;
+    use eink_pipe_io::service::RemoteError;
+    use serde_json::json;
+
+    use super::ServiceError;
+
+    #[test]
+    fn test_rpc_error_round_trip() {
+        let err = ServiceError::MonitorNotFound {
+            monitor_id: "EINK".to_owned(),
+        };
+        let rpc_error = jsonrpc::Error::from(err.clone());
+        assert_eq!(rpc_error.code, 1003);
+        assert_eq!(
+            rpc_error.data,
+            Some(json!({ "kind": "MonitorNotFound", "monitor_id": "EINK" }))
+        );
+        assert_eq!(ServiceError::from_rpc_error(&rpc_error), Some(err.clone()));
+
+        let call_error = anyhow::Error::from(RemoteError(rpc_error));
+        assert_eq!(ServiceError::from_call_error(&call_error), Some(err));
+
+        // 框架错误不是服务错误
+        let rpc_error = jsonrpc::Error::internal_error();
+        assert_eq!(ServiceError::from_rpc_error(&rpc_error), None);
+    }
+}
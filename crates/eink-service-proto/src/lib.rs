@@ -0,0 +1,123 @@
+//
+// Copyright (C) Lenovo ThinkBook Gen4 Project.
+//
+// This program is protected under international and China copyright laws as
+// an unpublished work. This program is confidential and proprietary to the
+// copyright owners. Reproduction or disclosure, in whole or in part, or the
+// production of derivative works therefrom without the express permission of
+// the copyright owners is prohibited.
+//
+// All rights reserved.
+//
+
+//! 服务 RPC 接口定义，服务端与客户端共享
+
+pub use eink_pipe_io::service::{Ack, Empty, RpcResult};
+pub use error::ServiceError;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+mod error;
+
+/// `set_mipi_mode` 参数
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SetMipiModeParams {
+    pub mode: u32,
+}
+
+/// `set_shutdown_cover` 参数
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SetShutdownCoverParams {
+    pub path: String,
+    #[serde(rename = "type", default)]
+    pub disp_type: u32,
+}
+
+/// `set_tp_mask_area` 参数
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SetTpMaskAreaParams {
+    pub pen_style: u32,
+    pub area_id: u32,
+    pub x1: u32,
+    pub x2: u32,
+    pub y1: u32,
+    pub y2: u32,
+}
+
+eink_pipe_io::rpc_service! {
+    /// TCON 服务，由 eink-service 提供
+    pub mod tcon {
+        /// 刷新 Eink 屏幕
+        fn refresh(Empty) -> Ack;
+        /// 设置 MIPI 模式
+        fn set_mipi_mode(SetMipiModeParams) -> Ack;
+        /// 获得当前 MIPI 模式
+        fn get_mipi_mode(Empty) -> u32;
+        /// 显示关机壁纸
+        fn show_shutdown_cover(Empty) -> Ack;
+        /// 设置关机壁纸
+        fn set_shutdown_cover(SetShutdownCoverParams) -> Ack;
+        /// 启动锁屏笔记
+        fn start_lockscreen_note(Empty) -> Ack;
+        /// 启动 Launcher
+        fn start_launcher(Empty) -> Ack;
+        /// 软件复位 TCON
+        fn software_reset_api(Empty) -> Ack;
+        /// 设置触摸区域
+        fn set_tp_mask_area(SetTpMaskAreaParams) -> Ack;
+    }
+}
+
+eink_pipe_io::rpc_service! {
+    /// 键盘服务，由 eink-service 提供
+    pub mod keyboard {
+        /// 禁用 Win / AltTab 按键
+        fn disable_win_key(Empty) -> Ack;
+        /// 启用 Win / AltTab 按键
+        fn enable_win_key(Empty) -> Ack;
+    }
+}
+
+/// 窗口句柄参数
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct HwndParams {
+    pub hwnd: i64,
+}
+
+/// 进程 ID 参数
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct PidParams {
+    pub pid: i64,
+}
+
+eink_pipe_io::rpc_service! {
+    /// 置顶服务，由 eink-service-helper 提供
+    pub mod topmost {
+        /// 设置窗口为置顶
+        fn set_window_topmost(HwndParams) -> Ack;
+        /// 取消窗口置顶
+        fn unset_window_topmost(HwndParams) -> Ack;
+        /// 清除所有置顶窗口
+        fn clear_all_windows_topmost(Empty) -> Ack;
+        /// 新应用程序启动后调整窗口置顶关系
+        fn adjust_topmost_on_app_launched(PidParams) -> Ack;
+        /// 切换 Eink / OLED 显示
+        fn switch_eink_oled_display(Empty) -> Ack;
+    }
+}
+
+/// `set_reading_light_status` 参数
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct ReadingLightParams {
+    pub level: u32,
+}
+
+eink_pipe_io::rpc_service! {
+    /// WMI 服务，由 eink-service-helper 提供
+    pub mod wmi {
+        /// 设置阅读灯亮度
+        fn set_reading_light_status(ReadingLightParams) -> u32;
+        /// 获得阅读灯亮度
+        fn get_reading_light_status(Empty) -> u32;
+    }
+}
@@ -15,19 +15,14 @@ use std::sync::Arc;
 
 use anyhow::Result;
 use cmd_lib::run_cmd;
-use eink_pipe_io::server::Socket;
-use jsonrpc_lite::{Id, JsonRpc};
-use log::info;
-use parking_lot::{Mutex, RwLock};
-use signals2::{Connect2, Emit2, Signal};
-use tokio::runtime::Runtime;
+use eink_pipe_io::blocking::{BlockingServer, ServerHandle};
+use eink_service_proto::{keyboard, Ack, Empty, RpcResult, ServiceError};
+use log::{error, info};
+use parking_lot::Mutex;
 use windows::Win32::System::Threading::GetCurrentProcessId;
 
 use crate::settings::SETTINGS;
-use crate::utils::{
-    get_current_data_dir, get_current_exe_dir, jsonrpc_error_internal_error,
-    jsonrpc_error_method_not_found, jsonrpc_success_string,
-};
+use crate::utils::{get_current_data_dir, get_current_exe_dir};
 use crate::win_utils::{kill_process_by_pid, kill_process_by_name, run_as_admin};
 
 const PIPE_NAME: &str = r"\\.\pipe\lenovo\eink-service\keyboard";
@@ -36,63 +31,29 @@ const PIPE_NAME: &str = r"\\.\pipe\lenovo\eink-service\keyboard";
 pub struct KeyboardManager {
     pid: Option<u32>,
 
-    /// IPC 接口使用 tokio 异步运行时
-    rt: Runtime,
-
-    /// IPC 请求响应函数
-    on_request: Arc<RwLock<Signal<(Id, JsonRpc), JsonRpc>>>,
+    /// 后台运行的 IPC 服务器
+    ipc_server: Option<ServerHandle>,
 }
 
 impl KeyboardManager {
     ///
     pub fn new() -> Result<Self> {
-        let rt = tokio::runtime::Builder::new_multi_thread()
-            .worker_threads(3)
-            .on_thread_start(|| {
-                log::info!(
-                    "KeyboardManager thread [{:?}] started",
-                    std::thread::current().id()
-                );
-            })
-            .on_thread_stop(|| {
-                log::info!(
-                    "KeyboardManager thread [{:?}] stopping",
-                    std::thread::current().id()
-                );
-            })
-            .enable_all()
-            .build()
-            .expect("Cannot create tokio runtime for KeyboardManager");
-
         Ok(Self {
             pid: None,
-            rt,
-            on_request: Default::default(),
+            ipc_server: None,
         })
     }
 
     pub fn after_init(this: &mut Arc<Mutex<Self>>) -> Result<()> {
         info!("KeyboardManager: init");
 
-        let this2 = this.clone();
-
-        this.lock().on_request.write().connect(move |id, req| {
-            info!("KeyboardManager: On request");
-            match req.get_method() {
-                Some("disable_win_key") => {
-                    this2.lock().disable_win_key().unwrap();
-                    jsonrpc_success_string(id, "true")
-                }
-                Some("enable_win_key") => {
-                    this2.lock().enable_win_key().unwrap();
-                    jsonrpc_success_string(id, "true")
-                }
-                Some(&_) => jsonrpc_error_method_not_found(id),
-                None => jsonrpc_error_internal_error(id),
-            }
-        });
+        let mut server = BlockingServer::new(PIPE_NAME)?;
+        server.server().set_build_info(crate::utils::build_info());
+        crate::utils::add_middlewares(server.server());
+        keyboard::serve(server.server(), Arc::new(KeyboardRpc(this.clone())));
 
-        this.lock().start_ipc_server()?;
+        info!("KeyboardManager: start_ipc_server");
+        this.lock().ipc_server = Some(server.spawn()?);
 
         Ok(())
     }
@@ -102,38 +63,6 @@ impl KeyboardManager {
         Ok(())
     }
 
-    /// 启动 IPC 服务器
-    fn start_ipc_server(&mut self) -> Result<()> {
-        info!("KeyboardManager: start_ipc_server");
-        let mut server = eink_pipe_io::server::Server::new(PIPE_NAME);
-
-        let on_request = self.on_request.clone();
-
-        let _ = server.on_connection(move |socket, req| {
-            info!("KeyboardManager: On connection");
-            let on_request2 = on_request.clone();
-            socket
-                .lock()
-                .on_request(move |_socket: Arc<Mutex<Socket>>, id: Id, req: JsonRpc| {
-                    info!("KeyboardManager: On request: {req:?}");
-                    match on_request2.read().emit(id.clone(), req) {
-                        Some(reply) => reply,
-                        None => JsonRpc::error(id, jsonrpc_lite::Error::internal_error()),
-                    }
-                });
-            0
-        });
-
-        // 在异步运行时启动
-        self.rt.spawn(async move {
-            info!("KeyboardManager: start server listen");
-            server.listen().await;
-            info!("KeyboardManager: stop server listen");
-        });
-
-        Ok(())
-    }
-
     /// 禁用 Win / AltTab 按键
     /// 1. 启动 eink-keyboard-manager 进程
     pub fn disable_win_key(&mut self) -> Result<()> {
@@ -189,6 +118,13 @@ impl KeyboardManager {
     /// 停止服务
     /// 1. 停止 eink-keyboard-manager 进程
     pub fn stop(&mut self) -> Result<()> {
+        // 停止接受新连接，等待正在处理的请求完成与后台线程退出
+        if let Some(server) = self.ipc_server.take() {
+            server.stop();
+            if let Err(err) = server.join() {
+                error!("KeyboardManager: ipc server stopped with error: {err}");
+            }
+        }
         self.enable_win_key()
     }
 }
@@ -215,3 +151,28 @@ fn test_win_key() {
     std::thread::sleep(std::time::Duration::from_secs(5));
     KEYBOARD_MANAGER.lock().enable_win_key().unwrap();
 }
+
+/// 键盘 RPC 接口实现
+struct KeyboardRpc(Arc<Mutex<KeyboardManager>>);
+
+impl keyboard::Service for KeyboardRpc {
+    fn disable_win_key(&self, _params: Empty) -> RpcResult<Ack> {
+        self.0.lock().disable_win_key().map_err(|err| {
+            error!("KeyboardManager: disable_win_key failed: {err:?}");
+            ServiceError::KeyboardHookFailed {
+                reason: err.to_string(),
+            }
+        })?;
+        Ok(Ack)
+    }
+
+    fn enable_win_key(&self, _params: Empty) -> RpcResult<Ack> {
+        self.0.lock().enable_win_key().map_err(|err| {
+            error!("KeyboardManager: enable_win_key failed: {err:?}");
+            ServiceError::KeyboardHookFailed {
+                reason: err.to_string(),
+            }
+        })?;
+        Ok(Ack)
+    }
+}
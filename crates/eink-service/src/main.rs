@@ -43,6 +43,8 @@ use crate::keyboard_manager::KEYBOARD_MANAGER;
 use crate::tcon_service::TCON_SERVICE;
 use crate::topmost_manager::TOPMOST_MANAGER;
 
+shadow_rs::shadow!(build);
+
 ///////////////////////////////////////////////////////////////////////////////
 /// Functions
 ///
@@ -12,7 +12,9 @@
 
 use std::mem::zeroed;
 use std::ops::DerefMut;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Weak};
+use std::time::Duration;
 
 use anyhow::{bail, Result};
 use eink_itetcon::{
@@ -21,58 +23,131 @@ use eink_itetcon::{
     ITESetFA2, ITESetMIPIModeAPI, IteTconDevice, RecoveryLoadImg, StopLoadImg, GI_MIPI_FAST_READER,
     GI_MIPI_HYBRID, GI_MIPI_READER,
 };
-use eink_pipe_io::server::Socket;
-use if_chain::if_chain;
-use jsonrpc_lite::{Id, JsonRpc, Params};
+use eink_pipe_io::access::{AccessPolicy, Principal};
+use eink_pipe_io::blocking::{BlockingServer, ServerHandle};
+use eink_pipe_io::limits::{Limits, RateLimit};
+use eink_pipe_io::pubsub::Publisher;
+use eink_pipe_io::recorder::Recorder;
+use eink_pipe_io::stream::Progress;
+use eink_service_proto::{
+    tcon, Ack, Empty, RpcResult, ServiceError, SetMipiModeParams, SetShutdownCoverParams,
+    SetTpMaskAreaParams,
+};
 use log::{error, info};
 use parking_lot::{Mutex, RwLock};
 use serde_json::json;
-use signals2::connect::ConnectionImpl;
-use signals2::{Connect2, Emit2, Signal};
-use tokio::runtime::Runtime;
 use windows::Win32::Foundation::INVALID_HANDLE_VALUE;
 
-use crate::utils::{
-    jsonrpc_error_internal_error, jsonrpc_error_invalid_params, jsonrpc_error_method_not_found,
-    jsonrpc_success_string, jsonrpc_success_u32,
-};
+use crate::settings::SETTINGS;
 
 const PIPE_NAME: &str = r"\\.\pipe\lenovo\eink-service\tcon";
 
-pub struct TconService {
-    /// IPC 接口使用 tokio 异步运行时
-    rt: Runtime,
+/// MIPI 模式变化主题，参数 `{"mode": u32}`
+pub const TOPIC_MIPI_MODE_CHANGED: &str = "mipi_mode_changed";
+
+/// 允许复位 TCON、设置关机壁纸与启动 Launcher 的调用方，配置项 `tcon_privileged_callers`
+///
+/// 每一项为 `elevated`、`any`、`user:<用户名>`、`dir:<目录>` 或 `path:<可执行文件>`，
+/// `dir:.` 表示服务所在目录。已知的调用方为：
+/// - 服务自身与安装程序：SYSTEM 用户、提权进程
+/// - eink-service-helper：服务所在目录，以登录用户身份运行
+/// - LenovoGen4.Launcher：服务所在目录，通过 eink-service-api 调用
+///
+/// 未配置时只允许上述调用方，其它加载 eink-service-api 的应用需要显式配置，
+/// 配置 `any` 表示不限制调用方
+fn privileged_callers() -> Vec<Principal> {
+    let entries = match SETTINGS
+        .read()
+        .unwrap()
+        .get::<Vec<String>>("tcon_privileged_callers")
+    {
+        Ok(entries) => entries,
+        Err(_) => {
+            let principals = default_privileged_callers();
+            info!("TconService: tcon_privileged_callers is not configured, use {principals:?}");
+            return principals;
+        }
+    };
+
+    let principals: Vec<Principal> = entries
+        .iter()
+        .filter_map(|entry| {
+            let principal = parse_principal(entry);
+            if principal.is_none() {
+                error!("TconService: invalid tcon_privileged_callers entry: {entry}");
+            }
+            principal
+        })
+        .collect();
+    info!("TconService: privileged callers: {principals:?}");
+    principals
+}
+
+/// 默认的调用方：提权进程、SYSTEM 用户与服务所在目录中的程序
+fn default_privileged_callers() -> Vec<Principal> {
+    let mut principals = vec![Principal::Elevated, Principal::User("SYSTEM".to_owned())];
+    principals.extend(install_dir().map(Principal::ProcessDir));
+    principals
+}
+
+/// 服务所在目录
+fn install_dir() -> Option<PathBuf> {
+    std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf))
+}
+
+fn parse_principal(entry: &str) -> Option<Principal> {
+    if entry.eq_ignore_ascii_case("elevated") {
+        return Some(Principal::Elevated);
+    }
+    if entry.eq_ignore_ascii_case("any") {
+        return Some(Principal::Any);
+    }
+    let (kind, value) = entry.split_once(':')?;
+    match kind {
+        "user" => Some(Principal::User(value.to_owned())),
+        "dir" if value == "." => install_dir().map(Principal::ProcessDir),
+        "dir" => Some(Principal::ProcessDir(value.into())),
+        "path" => Some(Principal::ProcessPath(value.into())),
+        _ => None,
+    }
+}
 
+fn access_policy() -> AccessPolicy {
+    let principals = privileged_callers();
+    AccessPolicy::new()
+        .allow(tcon::method::software_reset_api, principals.clone())
+        .allow(tcon::method::set_shutdown_cover, principals.clone())
+        .allow(tcon::method::start_launcher, principals)
+}
+
+/// 刷新与复位会直接操作 TCON 硬件，限制调用频率
+fn limits() -> Limits {
+    Limits::new()
+        .max_message_size(64 * 1024)
+        .rate_limit(
+            tcon::method::refresh,
+            RateLimit::new(10, Duration::from_secs(1)),
+        )
+        .rate_limit(
+            tcon::method::software_reset_api,
+            RateLimit::new(1, Duration::from_secs(5)),
+        )
+}
+
+pub struct TconService {
     tcon_device: Arc<RwLock<IteTconDevice>>,
 
-    /// IPC 请求响应函数
-    on_request: Arc<RwLock<Signal<(Id, JsonRpc), JsonRpc>>>,
+    /// 后台运行的 IPC 服务器
+    ipc_server: Option<ServerHandle>,
 }
 
 impl TconService {
     pub fn new() -> Result<Self> {
-        let rt = tokio::runtime::Builder::new_multi_thread()
-            .worker_threads(3)
-            .on_thread_start(|| {
-                log::info!(
-                    "TconService thread [{:?}] started",
-                    std::thread::current().id()
-                );
-            })
-            .on_thread_stop(|| {
-                log::info!(
-                    "TconService thread [{:?}] stopping",
-                    std::thread::current().id()
-                );
-            })
-            .enable_all()
-            .build()
-            .expect("Cannot create tokio runtime for TconService");
-
         Ok(Self {
-            rt,
             tcon_device: Arc::new(RwLock::new(IteTconDevice::new()?)),
-            on_request: Default::default(),
+            ipc_server: None,
         })
     }
 
@@ -97,184 +172,163 @@ impl TconService {
             });
         }
 
-        let tcon_device = self.tcon_device.clone();
+        let mut server = BlockingServer::new(PIPE_NAME)?;
+        server.server().set_build_info(crate::utils::build_info());
+        crate::utils::add_middlewares(server.server());
+        server.server().set_access_policy(access_policy());
+        server.server().set_limits(limits());
+
+        // 配置了 rpc_trace_dir 时记录所有调用，用于回放现场问题
+        if let Ok(dir) = SETTINGS.read().unwrap().get_string("rpc_trace_dir") {
+            match Recorder::create(Path::new(&dir).join("tcon-rpc.jsonl")) {
+                Ok(recorder) => server.server().set_recorder(recorder),
+                Err(err) => error!("TconService: cannot create rpc recorder: {err}"),
+            }
+        }
 
-        self.on_request.write().connect(move |id, req| {
-            info!("TconService: On request");
-            match req.get_method() {
-                Some("refresh") => {
-                    if !tcon_avail {
-                        return jsonrpc_error_internal_error(id);
-                    }
-                    tcon_refresh();
-                    jsonrpc_success_string(id, "true")
-                }
-                Some("set_mipi_mode") => {
-                    if !tcon_avail {
-                        return jsonrpc_error_internal_error(id);
-                    }
-
-                    if_chain! {
-                        if let Some(Params::Map(map)) = req.get_params();
-                        if let Some(mode) = map.get("mode");
-                        if let Some(mode) = mode.as_u64();
-                        if let Ok(mode) = MipiMode::try_from(mode as u32);
-                        then {
-                            tcon_set_mipi_mode(mode);
-                            return jsonrpc_success_string(id, "true");
-                        } else {
-                            return jsonrpc_error_invalid_params(id);
-                        }
-                    }
-                }
-                Some("get_mipi_mode") => {
-                    if !tcon_avail {
-                        return jsonrpc_error_internal_error(id);
-                    }
-
-                    if_chain! {
-                        then {
-                            let mode = tcon_get_mipi_mode();
-                            return jsonrpc_success_u32(id, mode.into());
-                        } else {
-                            return jsonrpc_error_invalid_params(id);
-                        }
-                    }
-                }
-                Some("show_shutdown_cover") => {
-                    // show_cover_image 有异常可能，异步化调用
-                    let tcon_device = tcon_device.clone();
-                    let thr = std::thread::spawn(move || {
-                        tcon_device.write().show_cover_image();
-                    });
-                    let _ = thr.join();
-                    jsonrpc_success_string(id, "true")
-                }
-                Some("set_shutdown_cover") => {
-                    if_chain! {
-                        if let Some(Params::Map(map)) = req.get_params();
-                        if let Some(path) = map.get("path");
-                        if let Some(path) = path.as_str();
-                        then {
-                            tcon_device.write().set_cover_image(&path);
-                            return jsonrpc_success_string(id, "true");
-                        } else {
-                            return jsonrpc_error_invalid_params(id);
-                        }
-                    }
-                }
-                Some("start_lockscreen_note") => {
-                    // 临时借这个地方
-                    // 启动锁屏笔记
-                    std::thread::spawn(|| {
-                        let dir = r"C:\Program Files\Lenovo\ThinkBookNotePlus";
-                        let exe = r"C:\Program Files\Lenovo\ThinkBookNotePlus\EInkLockSNote.exe";
-                        let _ = crate::win_utils::run_with_ui_access(dir, exe);
-                    });
-                    jsonrpc_success_string(id, "true")
-                }
-                Some("start_launcher") => {
-                    // 临时借这个地方
-                    // 启动锁屏笔记
-                    std::thread::spawn(|| {
-                        let dir = r"C:\Program Files\Lenovo\ThinkBookEinkPlus";
-                        let exe =
-                            r"C:\Program Files\Lenovo\ThinkBookEinkPlus\LenovoGen4.Launcher.exe";
-                        let _ = crate::win_utils::run_with_ui_access(dir, exe);
-                    });
-                    jsonrpc_success_string(id, "true")
-                }
-                Some("software_reset_api") => {
-                    info!("TconService: software_reset_api");
-                    unsafe { ITEResetTcon() };
-                    jsonrpc_success_string(id, "true")
-                }
-                Some("set_tp_mask_area") => {
-                    info!("TconService: set_tp_mask_area");
-
-                    if !tcon_avail {
-                        return jsonrpc_error_internal_error(id);
-                    }
-
-                    if_chain! {
-                        if let Some(Params::Map(map)) = req.get_params();
-                        if let Some(pen_style) = map.get("pen_style");
-                        if let Some(pen_style) = pen_style.as_u64();
-                        if let Some(area_id) = map.get("area_id");
-                        if let Some(area_id) = area_id.as_u64();
-                        if let Some(x1) = map.get("x1");
-                        if let Some(x1) = x1.as_u64();
-                        if let Some(x2) = map.get("x2");
-                        if let Some(x2) = x2.as_u64();
-                        if let Some(y1) = map.get("y1");
-                        if let Some(y1) = y1.as_u64();
-                        if let Some(y2) = map.get("y2");
-                        if let Some(y2) = y2.as_u64();
-                        then {
-                            let tcon_device = tcon_device.clone();
-                            let thr = std::thread::spawn(move || {
-                                tcon_device
-                                    .write()
-                                    .set_tp_mask_area(
-                                        pen_style as u32,
-                                        area_id as u32,
-                                        x1 as u32,
-                                        x2 as u32,
-                                        y1 as u32,
-                                        y2 as u32)
-                            });
-                            let _ = thr.join();
-                            return jsonrpc_success_string(id, "true");
-                        } else {
-                            return jsonrpc_error_invalid_params(id);
-                        }
-                    }
-                }
-                Some(&_) => jsonrpc_error_method_not_found(id),
-                None => jsonrpc_error_internal_error(id),
+        // 配置了 rpc_stats_interval 时定期将调用统计写入日志，单位秒
+        if let Ok(secs) = SETTINGS.read().unwrap().get_int("rpc_stats_interval") {
+            if secs > 0 {
+                let interval = Duration::from_secs(secs as u64);
+                server.server().set_stats_log_interval(interval);
             }
-        });
+        }
+
+        let service = TconRpc {
+            tcon_device: self.tcon_device.clone(),
+            tcon_avail,
+            publisher: server.server().publisher(),
+        };
+        tcon::serve(server.server(), Arc::new(service));
 
-        self.start_ipc_server()?;
+        info!("TconService: start_ipc_server");
+        self.ipc_server = Some(server.spawn()?);
 
         Ok(())
     }
 
     /// 停止服务
     pub fn stop(&mut self) -> Result<()> {
+        // 停止接受新连接，等待正在处理的请求完成与后台线程退出
+        if let Some(server) = self.ipc_server.take() {
+            server.stop();
+            if let Err(err) = server.join() {
+                error!("TconService: ipc server stopped with error: {err}");
+            }
+        }
         Ok(())
     }
+}
 
-    /// 启动 IPC 服务器
-    fn start_ipc_server(&mut self) -> Result<()> {
-        info!("TconService: start_ipc_server");
-        let mut server = eink_pipe_io::server::Server::new(PIPE_NAME);
-
-        let on_request = self.on_request.clone();
-
-        let _ = server.on_connection(move |socket, _req| {
-            info!("TconService: On connection");
-            let on_request2 = on_request.clone();
-            socket
-                .lock()
-                .on_request(move |_socket: Arc<Mutex<Socket>>, id: Id, req: JsonRpc| {
-                    info!("TconService: On request: {req:?}");
-                    match on_request2.read().emit(id.clone(), req) {
-                        Some(reply) => reply,
-                        None => JsonRpc::error(id, jsonrpc_lite::Error::internal_error()),
-                    }
-                });
-            0
+/// TCON RPC 接口实现
+struct TconRpc {
+    tcon_device: Arc<RwLock<IteTconDevice>>,
+    tcon_avail: bool,
+    publisher: Publisher,
+}
+
+impl TconRpc {
+    /// TCON 设备不可用时返回 `ServiceError::TconUnavailable`
+    fn ensure_tcon_avail(&self) -> RpcResult<()> {
+        if self.tcon_avail {
+            Ok(())
+        } else {
+            Err(ServiceError::TconUnavailable.into())
+        }
+    }
+}
+
+impl tcon::Service for TconRpc {
+    fn refresh(&self, _params: Empty) -> RpcResult<Ack> {
+        self.ensure_tcon_avail()?;
+        tcon_refresh();
+        Ok(Ack)
+    }
+
+    fn set_mipi_mode(&self, params: SetMipiModeParams) -> RpcResult<Ack> {
+        self.ensure_tcon_avail()?;
+        tcon_set_mipi_mode(MipiMode::from(params.mode));
+        self.publisher
+            .publish(TOPIC_MIPI_MODE_CHANGED, json!({ "mode": params.mode }));
+        Ok(Ack)
+    }
+
+    fn get_mipi_mode(&self, _params: Empty) -> RpcResult<u32> {
+        self.ensure_tcon_avail()?;
+        Ok(tcon_get_mipi_mode().into())
+    }
+
+    fn show_shutdown_cover(&self, _params: Empty) -> RpcResult<Ack> {
+        // show_cover_image 有异常可能，异步化调用
+        let tcon_device = self.tcon_device.clone();
+        let thr = std::thread::spawn(move || {
+            tcon_device.write().show_cover_image();
         });
+        thr.join()
+            .map_err(|_| ServiceError::internal("show_cover_image panicked"))?;
+        Ok(Ack)
+    }
+
+    fn set_shutdown_cover(&self, params: SetShutdownCoverParams) -> RpcResult<Ack> {
+        // 流式调用时报告上传进度
+        let progress = Progress::current();
+        self.tcon_device
+            .write()
+            .set_cover_image_with_progress(&params.path, |percent, stage| {
+                let _ = progress.send(json!({ "percent": percent, "stage": stage }));
+            })
+            .map_err(|err| ServiceError::CoverImageLoadFailed {
+                path: params.path.clone(),
+                reason: err.to_string(),
+            })?;
+        Ok(Ack)
+    }
 
-        // 在异步运行时启动
-        self.rt.spawn(async move {
-            info!("TconService: start server listen");
-            server.listen().await;
-            info!("TconService: stop server listen");
+    fn start_lockscreen_note(&self, _params: Empty) -> RpcResult<Ack> {
+        // 临时借这个地方
+        // 启动锁屏笔记
+        std::thread::spawn(|| {
+            let dir = r"C:\Program Files\Lenovo\ThinkBookNotePlus";
+            let exe = r"C:\Program Files\Lenovo\ThinkBookNotePlus\EInkLockSNote.exe";
+            let _ = crate::win_utils::run_with_ui_access(dir, exe);
         });
+        Ok(Ack)
+    }
 
-        Ok(())
+    fn start_launcher(&self, _params: Empty) -> RpcResult<Ack> {
+        // 临时借这个地方
+        // 启动锁屏笔记
+        std::thread::spawn(|| {
+            let dir = r"C:\Program Files\Lenovo\ThinkBookEinkPlus";
+            let exe = r"C:\Program Files\Lenovo\ThinkBookEinkPlus\LenovoGen4.Launcher.exe";
+            let _ = crate::win_utils::run_with_ui_access(dir, exe);
+        });
+        Ok(Ack)
+    }
+
+    fn software_reset_api(&self, _params: Empty) -> RpcResult<Ack> {
+        info!("TconService: software_reset_api");
+        unsafe { ITEResetTcon() };
+        Ok(Ack)
+    }
+
+    fn set_tp_mask_area(&self, params: SetTpMaskAreaParams) -> RpcResult<Ack> {
+        info!("TconService: set_tp_mask_area");
+        self.ensure_tcon_avail()?;
+
+        let tcon_device = self.tcon_device.clone();
+        let thr = std::thread::spawn(move || {
+            tcon_device.write().set_tp_mask_area(
+                params.pen_style,
+                params.area_id,
+                params.x1,
+                params.x2,
+                params.y1,
+                params.y2,
+            )
+        });
+        let _ = thr.join();
+        Ok(Ack)
     }
 }
 
@@ -11,8 +11,13 @@
 //
 
 use std::path::PathBuf;
+use std::time::Duration;
 
-use jsonrpc_lite::{Id, JsonRpc};
+use eink_pipe_io::handshake::BuildInfo;
+use eink_pipe_io::middleware::{CatchPanic, Logging, RequestId, Timing};
+use eink_pipe_io::server::Server;
+
+use crate::build;
 
 /// 获得当前 exe 所在目录
 pub fn get_current_exe_dir() -> PathBuf {
@@ -28,27 +33,21 @@ pub fn get_current_data_dir() -> PathBuf {
     data_dir
 }
 
-/// 返回成功（字符串值）
-pub fn jsonrpc_success_string(id: Id, result: &str) -> JsonRpc {
-    JsonRpc::success(id, &serde_json::Value::String(result.to_owned()))
-}
-
-/// 返回成功（u32值）
-pub fn jsonrpc_success_u32(id: Id, result: u32) -> JsonRpc {
-    JsonRpc::success(id, &serde_json::json!(result))
-}
-
-/// 返回错误（无效参数）
-pub fn jsonrpc_error_invalid_params(id: Id) -> JsonRpc {
-    JsonRpc::error(id, jsonrpc_lite::Error::invalid_params())
-}
-
-/// 返回错误（找不到方法）
-pub fn jsonrpc_error_method_not_found(id: Id) -> JsonRpc {
-    JsonRpc::error(id, jsonrpc_lite::Error::method_not_found())
+/// 当前构建信息，在 pipe-io 握手时发送给客户端
+pub fn build_info() -> BuildInfo {
+    BuildInfo {
+        name: build::PROJECT_NAME.to_owned(),
+        version: build::PKG_VERSION.to_owned(),
+        commit: build::SHORT_COMMIT.to_owned(),
+        commit_date: build::COMMIT_DATE.to_owned(),
+        build_time: build::BUILD_TIME.to_owned(),
+    }
 }
 
-/// 返回错误（内部错误）
-pub fn jsonrpc_error_internal_error(id: Id) -> JsonRpc {
-    JsonRpc::error(id, jsonrpc_lite::Error::internal_error())
+/// 添加服务通用的请求中间件：请求 id、请求日志、慢请求警告与 panic 捕获
+pub fn add_middlewares(server: &mut Server) {
+    server.add_middleware(RequestId);
+    server.add_middleware(Logging);
+    server.add_middleware(Timing::new(Duration::from_millis(500)));
+    server.add_middleware(CatchPanic);
 }